@@ -0,0 +1,107 @@
+//! Owned against borrowed (zero-copy) deserialization of a string-heavy user
+//! record, over a JSON array of many of them:
+//!
+//! ```text
+//! cargo bench --bench serde
+//! ```
+//!
+//! Borrowing skips an allocation and a copy per string, so it pays off with
+//! many or long strings, parsed from a buffer that outlives the result. It
+//! gains nothing on strings with escapes in them (`\n`, `\"`, `\u00e9`):
+//! those have to be unescaped into a fresh buffer, which `Cow` does and
+//! `&str` refuses to.
+
+use std::borrow::Cow;
+
+use chrono::NaiveDate;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{Deserialize, Serialize};
+
+const USERS: usize = 10_000;
+
+/// A string-heavy stand-in for a user, not the serde example's `User`: plain
+/// strings where that one has custom conversions, and a bio added, so
+/// borrowing has the most to save.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct User {
+    name: String,
+    age: u8,
+    dob: NaiveDate,
+    skills: Vec<String>,
+    bio: String,
+    avatar: String,
+    employee_no: String,
+}
+
+/// [`User`] pointing into the input instead of copying out of it.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserRef<'a> {
+    /// Borrowed unless escaped, then owned.
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    age: u8,
+    dob: NaiveDate,
+    #[serde(borrow)]
+    skills: Vec<Cow<'a, str>>,
+    #[serde(borrow)]
+    bio: Cow<'a, str>,
+    /// Base64 and digits never need escaping, so `&str` is safe here.
+    avatar: &'a str,
+    employee_no: &'a str,
+}
+
+fn users(bio: &str) -> String {
+    let users: Vec<_> = (0..USERS)
+        .map(|i| User {
+            name: format!("User Number {}", i),
+            age: (i % 100) as u8,
+            dob: NaiveDate::from_ymd_opt(1990, 1, 1).unwrap(),
+            skills: vec!["rust".to_string(), "go".to_string(), "postgres".to_string()],
+            bio: bio.repeat(8),
+            avatar: "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR4nGNgYGD4DwABBAEAwS2OUAAAAABJRU5ErkJggg==".to_string(),
+            employee_no: (9_007_199_254_740_993 + i as u64).to_string(),
+        })
+        .collect();
+    serde_json::to_string(&users).unwrap()
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+    for (input, bio) in [
+        ("plain", "Writes Rust and Go, mostly for the backend. "),
+        (
+            "escaped",
+            "Writes \"Rust\" and Go,\nmostly for the backend. ",
+        ),
+    ] {
+        let json = users(bio);
+        let owned: Vec<User> = serde_json::from_str(&json).unwrap();
+        let borrowed: Vec<UserRef> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            matches!(borrowed[0].bio, Cow::Borrowed(_)),
+            input == "plain"
+        );
+        // both read the same users, so neither skips work the other does
+        let (user, user_ref) = (&owned[USERS - 1], &borrowed[USERS - 1]);
+        assert_eq!(user_ref.name, user.name);
+        assert_eq!((user_ref.age, user_ref.dob), (user.age, user.dob));
+        assert_eq!(user_ref.skills, user.skills);
+        assert_eq!(user_ref.bio, user.bio);
+        assert_eq!(user_ref.avatar, user.avatar);
+        assert_eq!(user_ref.employee_no, user.employee_no);
+
+        group.throughput(Throughput::Bytes(json.len() as u64));
+        group.bench_with_input(BenchmarkId::new("owned", input), &json, |b, json| {
+            b.iter(|| serde_json::from_str::<Vec<User>>(json).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed", input), &json, |b, json| {
+            b.iter(|| serde_json::from_str::<Vec<UserRef>>(json).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, deserialize);
+criterion_main!(benches);
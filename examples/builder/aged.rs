@@ -0,0 +1,98 @@
+//! Entities with an age derived from a date they are given, like a user's
+//! date of birth or an organization's founding date. The builder takes the
+//! date as a `%Y-%m-%d` string and keeps it unparsed until `build`, so a
+//! malformed one is reported rather than lost; the entity gets the age, which
+//! the builder never sets.
+//!
+//! For an entity with `founded: NaiveDate` and `age: u32`, derive its builder
+//! with
+//!
+//! ```ignore
+//! #[builder(
+//!     setter(custom),
+//!     field(ty = "aged::DateInput", build = "aged::take(self.founded, \"founded\")?")
+//! )]
+//! founded: NaiveDate,
+//! #[builder(setter(skip))]
+//! age: u32,
+//! ```
+//!
+//! then `aged!(Entity, EntityBuilder, founded)` for the setter, and check
+//! `date_error` before the derived build and call `set_age` after it.
+
+use chrono::NaiveDate;
+use thiserror::Error;
+
+/// A date as given to a builder: unset, or parsed or not.
+pub type DateInput = Option<Result<NaiveDate, String>>;
+
+/// What can be wrong with the date an age is derived from.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DateError {
+    #[error("{0} is missing")]
+    Missing(&'static str),
+    #[error("{field} {given:?} is not a %Y-%m-%d date")]
+    Invalid { field: &'static str, given: String },
+    #[error("{field} {date} is in the future")]
+    Future {
+        field: &'static str,
+        date: NaiveDate,
+    },
+}
+
+pub fn parse(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| value.to_string())
+}
+
+/// The date in `input`, unless it is missing, malformed or after `today`.
+pub fn check(
+    field: &'static str,
+    input: &DateInput,
+    today: NaiveDate,
+) -> Result<NaiveDate, DateError> {
+    match input {
+        None => Err(DateError::Missing(field)),
+        Some(Err(given)) => Err(DateError::Invalid {
+            field,
+            given: given.clone(),
+        }),
+        Some(Ok(date)) if *date > today => Err(DateError::Future { field, date: *date }),
+        Some(Ok(date)) => Ok(*date),
+    }
+}
+
+/// The date in `input` for the derived build, which ought to have been
+/// checked already, so skips the check against today.
+pub fn take(input: DateInput, field: &'static str) -> Result<NaiveDate, DateError> {
+    match input {
+        None => Err(DateError::Missing(field)),
+        Some(Err(given)) => Err(DateError::Invalid { field, given }),
+        Some(Ok(date)) => Ok(date),
+    }
+}
+
+/// The setter and checks for `$date` on `$builder`, and the age on `$entity`.
+macro_rules! aged {
+    ($entity:ident, $builder:ident, $date:ident) => {
+        impl $builder {
+            /// Takes `%Y-%m-%d`; anything else fails the build.
+            pub fn $date(mut self, value: &str) -> Self {
+                self.$date = Some($crate::aged::parse(value));
+                self
+            }
+
+            /// What's wrong with the date as given, if anything.
+            fn date_error(&self, today: chrono::NaiveDate) -> Option<$crate::aged::DateError> {
+                $crate::aged::check(stringify!($date), &self.$date, today).err()
+            }
+        }
+
+        impl $entity {
+            /// Whole years since the date, which `date_error` has checked
+            /// isn't in the future.
+            fn set_age(&mut self, today: chrono::NaiveDate) {
+                self.age = today.years_since(self.$date).unwrap_or_default();
+            }
+        }
+    };
+}
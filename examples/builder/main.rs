@@ -0,0 +1,643 @@
+#[macro_use]
+mod aged;
+mod organization;
+mod typestate;
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use derive_builder::{Builder, UninitializedFieldError};
+use serde::Deserialize;
+use thiserror::Error;
+
+use aged::DateError;
+use organization::OrganizationBuilder;
+
+#[allow(unused)]
+#[derive(Debug, Builder)]
+#[builder(pattern = "owned", derive(Debug))]
+#[builder(build_fn(private, name = "pbuild", error = "ValidationError"))]
+pub struct User {
+    #[builder(setter(into), default)]
+    name: String,
+    #[builder(setter(into, strip_option), default)]
+    email: Option<String>,
+    /// Kept as given until `build`, so a malformed date is reported as such.
+    #[builder(
+        setter(custom),
+        field(ty = "aged::DateInput", build = "aged::take(self.dob, \"dob\")?")
+    )]
+    dob: NaiveDate,
+    #[builder(setter(skip))]
+    age: u32,
+    #[builder(default = "Vec::new()", setter(each(name = "skill", into)))]
+    skills: Vec<String>,
+    /// An IANA name, like `Europe/Berlin`.
+    #[builder(setter(into, strip_option), default)]
+    timezone: Option<String>,
+}
+
+/// One thing wrong with a user.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum UserBuildError {
+    #[error("{0} is missing")]
+    UninitializedField(&'static str),
+    #[error("date of birth {0:?} is not a %Y-%m-%d date")]
+    InvalidDob(String),
+    /// The date of birth is in the future, so there is no age to give.
+    #[error("date of birth {0} is in the future")]
+    AgeOverflow(NaiveDate),
+    #[error("{field}: {reason}")]
+    ValidationFailed { field: String, reason: String },
+}
+
+impl UserBuildError {
+    fn failed(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        UserBuildError::ValidationFailed {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Everything wrong with a user, not just the first thing found.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid user: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+pub struct ValidationError(pub Vec<UserBuildError>);
+
+impl From<DateError> for UserBuildError {
+    fn from(e: DateError) -> Self {
+        match e {
+            DateError::Missing(field) => UserBuildError::UninitializedField(field),
+            DateError::Invalid { given, .. } => UserBuildError::InvalidDob(given),
+            DateError::Future { date, .. } => UserBuildError::AgeOverflow(date),
+        }
+    }
+}
+
+impl From<DateError> for ValidationError {
+    fn from(e: DateError) -> Self {
+        UserBuildError::from(e).into()
+    }
+}
+
+impl From<UserBuildError> for ValidationError {
+    fn from(e: UserBuildError) -> Self {
+        Self(vec![e])
+    }
+}
+
+/// Why [`UserBuilder::build_with`] failed.
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error(transparent)]
+    Invalid(#[from] ValidationError),
+    #[error("looking up defaults: {0}")]
+    Defaults(#[source] anyhow::Error),
+}
+
+/// Where [`UserBuilder::build_with`] fills in what a user leaves out, say a
+/// profile service and a timezone lookup.
+#[async_trait]
+pub trait UserDefaults: Sync {
+    /// Skills to start someone on, when they have none.
+    async fn skills(&self, name: &str) -> anyhow::Result<Vec<String>>;
+    /// A timezone for someone with this email address, if one is known.
+    async fn timezone(&self, email: &str) -> anyhow::Result<Option<String>>;
+}
+
+impl From<UninitializedFieldError> for ValidationError {
+    fn from(e: UninitializedFieldError) -> Self {
+        UserBuildError::UninitializedField(e.field_name()).into()
+    }
+}
+
+impl User {
+    /// A builder holding this user, to change some fields and build again,
+    /// validating afresh.
+    pub fn into_builder(self) -> UserBuilder {
+        self.into()
+    }
+}
+
+impl From<User> for UserBuilder {
+    fn from(user: User) -> Self {
+        Self {
+            name: Some(user.name),
+            email: Some(user.email),
+            dob: Some(Ok(user.dob)),
+            skills: Some(user.skills),
+            timezone: Some(user.timezone),
+            ..Self::default()
+        }
+    }
+}
+
+/// A user as a config file has it, any of it left out, to be finished
+/// through the builder:
+///
+/// ```toml
+/// name = "Erin"
+/// dob = "1985-12-24"
+/// skills = ["ops"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserSpec {
+    name: Option<String>,
+    email: Option<String>,
+    /// `%Y-%m-%d`, quoted in TOML.
+    dob: Option<NaiveDate>,
+    #[serde(default)]
+    skills: Vec<String>,
+    timezone: Option<String>,
+}
+
+/// Fields left out of the spec stay unset: those the builder requires are
+/// then reported as [`UserBuildError::UninitializedField`] by `build`.
+impl From<UserSpec> for UserBuilder {
+    fn from(spec: UserSpec) -> Self {
+        Self {
+            name: spec.name,
+            email: spec.email.map(Some),
+            dob: spec.dob.map(Ok),
+            skills: Some(spec.skills),
+            timezone: spec.timezone.map(Some),
+            ..Self::default()
+        }
+    }
+}
+
+/// A user as a form or query string sends it: all strings, and blank
+/// where left out. Untrusted input becomes a builder through here alone.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawUserInput<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    /// `%Y-%m-%d`.
+    pub dob: &'a str,
+    /// Optional, and only checked against `dob`.
+    pub age: &'a str,
+    /// Comma separated.
+    pub skills: &'a str,
+    pub timezone: &'a str,
+}
+
+/// Fails on whatever can't be parsed; what parses but is invalid, like the
+/// email address, is left for `build`.
+impl TryFrom<RawUserInput<'_>> for UserBuilder {
+    type Error = ValidationError;
+
+    fn try_from(input: RawUserInput) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+        let mut builder = UserBuilder::default().name(input.name.trim());
+        if let Some(email) = given(input.email) {
+            builder = builder.email(email);
+        }
+        if let Some(dob) = given(input.dob) {
+            builder = builder.dob(dob);
+        }
+        if let Some(Err(dob)) = &builder.dob {
+            errors.push(UserBuildError::InvalidDob(dob.clone()));
+        }
+        if let Some(age) = given(input.age) {
+            match (age.parse::<u32>(), &builder.dob) {
+                (Err(_), _) => errors.push(UserBuildError::failed(
+                    "age",
+                    format!("{:?} is not a whole number of years", age),
+                )),
+                (Ok(age), Some(Ok(dob))) => {
+                    if Utc::now().date_naive().years_since(*dob) != Some(age) {
+                        let reason = format!("{} doesn't go with date of birth {}", age, dob);
+                        errors.push(UserBuildError::failed("age", reason));
+                    }
+                }
+                (Ok(_), _) => {}
+            }
+        }
+        for skill in input.skills.split(',').filter_map(given) {
+            builder = builder.skill(skill);
+        }
+        if let Some(timezone) = given(input.timezone) {
+            builder = builder.timezone(timezone);
+        }
+        match errors.is_empty() {
+            true => Ok(builder),
+            false => Err(ValidationError(errors)),
+        }
+    }
+}
+
+/// `value` trimmed, unless that leaves nothing.
+fn given(value: &str) -> Option<&str> {
+    Some(value.trim()).filter(|value| !value.is_empty())
+}
+
+aged!(User, UserBuilder, dob);
+
+impl UserBuilder {
+    pub fn build(self) -> Result<User, ValidationError> {
+        let today = Utc::now().date_naive();
+        let errors = self.errors(today);
+        if !errors.is_empty() {
+            return Err(ValidationError(errors));
+        }
+        let mut user = self.pbuild()?;
+        user.set_age(today);
+        Ok(user)
+    }
+
+    /// Builds after asking `defaults` for the skills and timezone left out,
+    /// both at once.
+    pub async fn build_with<D: UserDefaults>(mut self, defaults: &D) -> Result<User, BuildError> {
+        let name = self.name.clone().unwrap_or_default();
+        let email = self.email.clone().flatten();
+        let skills = async {
+            match &self.skills {
+                Some(skills) if !skills.is_empty() => Ok(None),
+                _ => defaults.skills(&name).await.map(Some),
+            }
+        };
+        let timezone = async {
+            match (&self.timezone, &email) {
+                (None, Some(email)) => defaults.timezone(email).await,
+                _ => Ok(None),
+            }
+        };
+        let (skills, timezone) =
+            tokio::try_join!(skills, timezone).map_err(BuildError::Defaults)?;
+
+        if skills.is_some() {
+            self.skills = skills;
+        }
+        if timezone.is_some() {
+            self.timezone = Some(timezone);
+        }
+        Ok(self.build()?)
+    }
+
+    fn errors(&self, today: NaiveDate) -> Vec<UserBuildError> {
+        let mut errors = Vec::new();
+        if let Some(Some(email)) = &self.email {
+            if !is_email(email) {
+                let reason = format!("{:?} is not an address", email);
+                errors.push(UserBuildError::failed("email", reason));
+            }
+        }
+        errors.extend(self.date_error(today).map(UserBuildError::from));
+        let skills = self.skills.iter().flatten();
+        for (i, skill) in skills.enumerate() {
+            if skill.trim().is_empty() {
+                let field = format!("skills[{}]", i);
+                errors.push(UserBuildError::failed(field, "must not be blank"));
+            }
+        }
+        errors
+    }
+}
+
+/// `local@domain`, roughly as RFC 5322 has it: a local part without spaces,
+/// and a domain of at least two dot-separated labels of letters, digits and
+/// inner hyphens.
+fn is_email(email: &str) -> bool {
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return false;
+    };
+    let local_ok = !local.is_empty()
+        && !local.contains('@')
+        && !local.chars().any(|c| c.is_whitespace() || c.is_control());
+    let labels: Vec<_> = domain.split('.').collect();
+    let domain_ok = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    local_ok && domain_ok
+}
+
+/// Skills by name and timezones by email domain, standing in for remote
+/// services.
+#[derive(Debug, Default)]
+struct Directory {
+    skills: HashMap<String, Vec<String>>,
+    timezones: HashMap<String, String>,
+}
+
+#[async_trait]
+impl UserDefaults for Directory {
+    async fn skills(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        Ok(self.skills.get(name).cloned().unwrap_or_default())
+    }
+
+    async fn timezone(&self, email: &str) -> anyhow::Result<Option<String>> {
+        let domain = email.rsplit_once('@').map(|(_, domain)| domain);
+        Ok(domain
+            .and_then(|domain| self.timezones.get(domain))
+            .cloned())
+    }
+}
+
+const SPEC: &str = r#"
+name = "Erin"
+dob = "1985-12-24"
+skills = ["ops"]
+"#;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let user = UserBuilder::default()
+        .name("Alice")
+        .email("lign@awsome.com")
+        .dob("1998-10-2")
+        .skill("guitar")
+        .skill("computer science")
+        .build()?;
+    println!("user: {:?}", user);
+
+    let user = user
+        .into_builder()
+        .email("lign@awesome.com")
+        .skill("rust")
+        .build()?;
+    println!("edited: {:?}", user);
+
+    let invalid = UserBuilder::default()
+        .name("Bob")
+        .email("bob at example.com")
+        .dob("2998-10-2")
+        .skill(" ")
+        .build();
+    if let Err(e) = invalid {
+        println!("{}", e);
+    }
+
+    let directory = Directory {
+        skills: HashMap::from([("Carol".to_string(), vec!["go".to_string()])]),
+        timezones: HashMap::from([("acme.de".to_string(), "Europe/Berlin".to_string())]),
+    };
+    let user = UserBuilder::default()
+        .name("Carol")
+        .email("carol@acme.de")
+        .dob("1990-04-01")
+        .build_with(&directory)
+        .await?;
+    println!("with defaults: {:?}", user);
+
+    let spec: UserSpec = toml::from_str(SPEC)?;
+    let user = UserBuilder::from(spec).email("erin@acme.de").build()?;
+    println!("from config: {:?}", user);
+
+    let form = RawUserInput {
+        name: "Frank",
+        email: " frank@example.com ",
+        dob: "1979-02-28",
+        skills: "bash, , perl",
+        ..Default::default()
+    };
+    let user = UserBuilder::try_from(form)?.build()?;
+    println!("from a form: {:?}", user);
+
+    let user = typestate::UserBuilder::new()
+        .name("Grace")
+        .email("grace@example.com")
+        .skill("cobol")
+        .dob(NaiveDate::from_ymd_opt(1906, 12, 9).unwrap_or_default())
+        .build()?;
+    println!("checked at compile time: {:?}", user);
+
+    let organization = OrganizationBuilder::default()
+        .name("Acme")
+        .founded_date("1949-06-01")
+        .build()?;
+    println!("organization: {:?}", organization);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_valid_users() {
+        let user = UserBuilder::default()
+            .email("alice@mail.example.com")
+            .dob("2000-01-01")
+            .skill("rust")
+            .build()
+            .unwrap();
+        assert!(user.age >= 26);
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let err = UserBuilder::default()
+            .email("alice@example")
+            .dob("2999-01-01")
+            .skill("rust")
+            .skill("")
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.0,
+            [
+                UserBuildError::failed("email", "\"alice@example\" is not an address"),
+                UserBuildError::AgeOverflow(NaiveDate::from_ymd_opt(2999, 1, 1).unwrap()),
+                UserBuildError::failed("skills[1]", "must not be blank"),
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid user: email: \"alice@example\" is not an address; \
+             date of birth 2999-01-01 is in the future; skills[1]: must not be blank"
+        );
+    }
+
+    #[test]
+    fn edits_go_through_the_builder() {
+        let alice = UserBuilder::default()
+            .name("Alice")
+            .email("alice@example.com")
+            .dob("2000-01-01")
+            .skill("rust")
+            .build()
+            .unwrap();
+        let age = alice.age;
+
+        let alice = alice.into_builder().skill("go").build().unwrap();
+        assert_eq!(alice.name, "Alice");
+        assert_eq!(alice.email.as_deref(), Some("alice@example.com"));
+        assert_eq!(alice.dob, NaiveDate::from_ymd_opt(2000, 1, 1).unwrap());
+        assert_eq!(alice.age, age);
+        assert_eq!(alice.skills, ["rust", "go"]);
+
+        let err = UserBuilder::from(alice)
+            .email("alice")
+            .dob("2999-01-01")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.0.len(), 2);
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl UserDefaults for Failing {
+        async fn skills(&self, _name: &str) -> anyhow::Result<Vec<String>> {
+            Err(anyhow::anyhow!("profile service is down"))
+        }
+
+        async fn timezone(&self, _email: &str) -> anyhow::Result<Option<String>> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn fills_in_what_was_left_out() {
+        let directory = Directory {
+            skills: HashMap::from([("Carol".to_string(), vec!["go".to_string()])]),
+            timezones: HashMap::from([("acme.de".to_string(), "Europe/Berlin".to_string())]),
+        };
+        let carol = || {
+            UserBuilder::default()
+                .name("Carol")
+                .email("carol@acme.de")
+                .dob("1990-04-01")
+        };
+
+        let user = carol().build_with(&directory).await.unwrap();
+        assert_eq!(user.skills, ["go"]);
+        assert_eq!(user.timezone.as_deref(), Some("Europe/Berlin"));
+
+        let user = carol()
+            .skill("rust")
+            .timezone("Asia/Tokyo")
+            .build_with(&directory)
+            .await
+            .unwrap();
+        assert_eq!(user.skills, ["rust"]);
+        assert_eq!(user.timezone.as_deref(), Some("Asia/Tokyo"));
+
+        let user = carol()
+            .name("Dan")
+            .email("dan@example.com")
+            .build_with(&directory)
+            .await;
+        let user = user.unwrap();
+        assert!(user.skills.is_empty());
+        assert_eq!(user.timezone, None);
+    }
+
+    #[tokio::test]
+    async fn lookups_fail_the_build() {
+        let err = UserBuilder::default()
+            .dob("1990-04-01")
+            .build_with(&Failing)
+            .await;
+        assert!(matches!(err, Err(BuildError::Defaults(_))));
+        let user = UserBuilder::default().dob("1990-04-01").skill("go");
+        assert!(user.build_with(&Failing).await.is_ok());
+        let err = UserBuilder::default()
+            .skill("go")
+            .build_with(&Failing)
+            .await;
+        assert!(matches!(err, Err(BuildError::Invalid(_))));
+    }
+
+    #[test]
+    fn specs_are_finished_through_the_builder() {
+        let spec: UserSpec = toml::from_str(SPEC).unwrap();
+        let user = UserBuilder::from(spec).skill("dns").build().unwrap();
+        assert_eq!(user.name, "Erin");
+        assert_eq!(user.dob, NaiveDate::from_ymd_opt(1985, 12, 24).unwrap());
+        assert_eq!(user.skills, ["ops", "dns"]);
+
+        let spec: UserSpec = serde_json::from_str(r#"{"name": "Erin"}"#).unwrap();
+        let err = UserBuilder::from(spec).build().unwrap_err();
+        assert_eq!(err.0, [UserBuildError::UninitializedField("dob")]);
+        let spec: UserSpec = serde_json::from_str(r#"{"name": "Erin"}"#).unwrap();
+        assert!(UserBuilder::from(spec).dob("1985-12-24").build().is_ok());
+    }
+
+    #[test]
+    fn specs_refuse_unknown_or_malformed_fields() {
+        let err = toml::from_str::<UserSpec>("nmae = \"Erin\"").unwrap_err();
+        assert!(err.to_string().contains("unknown field `nmae`"), "{}", err);
+        let err = toml::from_str::<UserSpec>("dob = \"24.12.1985\"").unwrap_err();
+        assert!(err.to_string().contains("dob"), "{}", err);
+    }
+
+    #[test]
+    fn raw_input_is_parsed_in_one_place() {
+        let today = Utc::now().date_naive();
+        let dob = NaiveDate::from_ymd_opt(1979, 2, 28).unwrap();
+        let age = today.years_since(dob).unwrap().to_string();
+        let form = RawUserInput {
+            name: " Frank ",
+            email: "frank@example.com",
+            dob: "1979-02-28",
+            age: &age,
+            skills: "bash, , perl ",
+            timezone: "",
+        };
+        let user = UserBuilder::try_from(form).unwrap().build().unwrap();
+        assert_eq!(user.name, "Frank");
+        assert_eq!(user.dob, dob);
+        assert_eq!(user.skills, ["bash", "perl"]);
+        assert_eq!(user.timezone, None);
+
+        let err = UserBuilder::try_from(RawUserInput { age: "12", ..form }).unwrap_err();
+        let reason = "12 doesn't go with date of birth 1979-02-28";
+        assert_eq!(err.0, [UserBuildError::failed("age", reason)]);
+    }
+
+    #[test]
+    fn raw_input_reports_every_malformed_field() {
+        let form = RawUserInput {
+            dob: "28/02/1979",
+            age: "forty",
+            ..Default::default()
+        };
+        let err = UserBuilder::try_from(form).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid user: date of birth \"28/02/1979\" is not a %Y-%m-%d date; \
+             age: \"forty\" is not a whole number of years"
+        );
+        let blank = UserBuilder::try_from(RawUserInput::default()).unwrap();
+        let missing = UserBuildError::UninitializedField("dob");
+        assert_eq!(blank.build().unwrap_err().0, [missing]);
+    }
+
+    #[test]
+    fn unparsable_dates_are_reported() {
+        let err = UserBuilder::default()
+            .dob("01/02/2000")
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.0,
+            [UserBuildError::InvalidDob("01/02/2000".to_string())]
+        );
+    }
+
+    #[test]
+    fn checks_email_shapes() {
+        for email in ["a@b.co", "first.last+tag@sub.example-mail.org"] {
+            assert!(is_email(email), "{}", email);
+        }
+        for email in [
+            "",
+            "@example.com",
+            "a@",
+            "a@b",
+            "a b@c.de",
+            "a@-b.com",
+            "a@b..com",
+        ] {
+            assert!(!is_email(email), "{}", email);
+        }
+    }
+}
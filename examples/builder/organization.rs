@@ -0,0 +1,94 @@
+use chrono::{NaiveDate, Utc};
+use derive_builder::{Builder, UninitializedFieldError};
+use thiserror::Error;
+
+use crate::aged::{self, DateError};
+
+/// Built the way [`User`](crate::User) is, with an age from when it was
+/// founded rather than born.
+#[allow(unused)]
+#[derive(Debug, Builder)]
+#[builder(pattern = "owned", derive(Debug))]
+#[builder(build_fn(private, name = "pbuild", error = "OrganizationBuildError"))]
+pub struct Organization {
+    #[builder(setter(into))]
+    name: String,
+    #[builder(
+        setter(custom),
+        field(
+            ty = "aged::DateInput",
+            build = "aged::take(self.founded_date, \"founded_date\")?"
+        )
+    )]
+    founded_date: NaiveDate,
+    #[builder(setter(skip))]
+    age: u32,
+}
+
+aged!(Organization, OrganizationBuilder, founded_date);
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OrganizationBuildError {
+    #[error("{0} is missing")]
+    UninitializedField(&'static str),
+    #[error(transparent)]
+    Date(#[from] DateError),
+}
+
+impl From<UninitializedFieldError> for OrganizationBuildError {
+    fn from(e: UninitializedFieldError) -> Self {
+        Self::UninitializedField(e.field_name())
+    }
+}
+
+impl OrganizationBuilder {
+    pub fn build(self) -> Result<Organization, OrganizationBuildError> {
+        let today = Utc::now().date_naive();
+        if let Some(e) = self.date_error(today) {
+            return Err(e.into());
+        }
+        let mut organization = self.pbuild()?;
+        organization.set_age(today);
+        Ok(organization)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn organizations_age_from_their_founding() {
+        let founded = Utc::now().date_naive() - chrono::Months::new(12 * 10);
+        let organization = OrganizationBuilder::default()
+            .name("Acme")
+            .founded_date(&founded.format("%Y-%m-%d").to_string())
+            .build()
+            .unwrap();
+        assert_eq!(organization.age, 10);
+    }
+
+    #[test]
+    fn organizations_report_bad_founding_dates() {
+        let err = OrganizationBuilder::default()
+            .name("Acme")
+            .founded_date("2999-01-01")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "founded_date 2999-01-01 is in the future");
+        let err = OrganizationBuilder::default()
+            .name("Acme")
+            .founded_date("June 1949")
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "founded_date \"June 1949\" is not a %Y-%m-%d date"
+        );
+        let err = OrganizationBuilder::default()
+            .founded_date("1949-06-01")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "name is missing");
+    }
+}
@@ -0,0 +1,126 @@
+//! A builder that won't compile `build()` until `name` and `dob` are set,
+//! where the derived one reports them missing at run time. Each required
+//! field's type parameter goes from [`Missing`] to [`Set`] with its setter.
+
+use chrono::{NaiveDate, Utc};
+
+use crate::aged::{self, DateError};
+
+#[allow(unused)]
+#[derive(Debug)]
+pub struct User {
+    name: String,
+    email: Option<String>,
+    dob: NaiveDate,
+    age: u32,
+    skills: Vec<String>,
+}
+
+/// A required field not set yet.
+#[derive(Debug)]
+pub struct Missing;
+
+/// A required field that is set.
+#[derive(Debug)]
+pub struct Set<T>(T);
+
+#[derive(Debug)]
+pub struct UserBuilder<Name, Dob> {
+    name: Name,
+    dob: Dob,
+    email: Option<String>,
+    skills: Vec<String>,
+}
+
+impl UserBuilder<Missing, Missing> {
+    pub fn new() -> Self {
+        Self {
+            name: Missing,
+            dob: Missing,
+            email: None,
+            skills: Vec::new(),
+        }
+    }
+}
+
+impl Default for UserBuilder<Missing, Missing> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Name, Dob> UserBuilder<Name, Dob> {
+    pub fn name(self, name: impl Into<String>) -> UserBuilder<Set<String>, Dob> {
+        UserBuilder {
+            name: Set(name.into()),
+            dob: self.dob,
+            email: self.email,
+            skills: self.skills,
+        }
+    }
+
+    pub fn dob(self, dob: NaiveDate) -> UserBuilder<Name, Set<NaiveDate>> {
+        UserBuilder {
+            name: self.name,
+            dob: Set(dob),
+            email: self.email,
+            skills: self.skills,
+        }
+    }
+
+    pub fn email(mut self, email: impl Into<String>) -> Self {
+        self.email = Some(email.into());
+        self
+    }
+
+    pub fn skill(mut self, skill: impl Into<String>) -> Self {
+        self.skills.push(skill.into());
+        self
+    }
+}
+
+impl UserBuilder<Set<String>, Set<NaiveDate>> {
+    /// Fails only on what the types can't promise: a date of birth in the
+    /// future.
+    pub fn build(self) -> Result<User, DateError> {
+        let today = Utc::now().date_naive();
+        let Set(dob) = self.dob;
+        let dob = aged::check("dob", &Some(Ok(dob)), today)?;
+        Ok(User {
+            name: self.name.0,
+            email: self.email,
+            dob,
+            age: today.years_since(dob).unwrap_or_default(),
+            skills: self.skills,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn future_dates_of_birth_fail_the_build() {
+        let user = UserBuilder::new()
+            .dob(NaiveDate::from_ymd_opt(2000, 1, 1).unwrap())
+            .name("Grace")
+            .build()
+            .unwrap();
+        assert!(user.age >= 26);
+
+        let dob = NaiveDate::from_ymd_opt(2999, 1, 1).unwrap();
+        let err = UserBuilder::new()
+            .name("Grace")
+            .dob(dob)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            DateError::Future {
+                field: "dob",
+                date: dob
+            }
+        );
+    }
+}
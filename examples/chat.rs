@@ -1,155 +1,224 @@
-use std::fmt::{Display, Formatter};
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::anyhow;
-use dashmap::DashMap;
-use futures_util::stream::SplitSink;
-use futures_util::{SinkExt, StreamExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio_util::codec::{Framed, LinesCodec};
-use tracing::{error, info, warn};
+use clap::Parser;
+use ecosystem::chat::{admit, reap, AuditLog, PeerAddr, Server, UserStore, WebhookNotifier};
+use futures_util::{future, FutureExt};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::{RandomIdGenerator, Tracer};
+use opentelemetry_sdk::{trace, Resource};
+use socket2::{Domain, Socket, Type};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, info_span, warn, Instrument};
 use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, Layer};
 
-#[derive(Debug)]
-struct Peer {
-    name: String,
-    stream: SplitSink<Framed<TcpStream, LinesCodec>, String>,
+#[derive(Debug, Parser)]
+struct Args {
+    /// Address the chat server listens on, may be given several times
+    /// (e.g. `--listen 0.0.0.0:8088 --listen [::]:8088`).
+    #[arg(long, default_value = "0.0.0.0:8088")]
+    listen: Vec<SocketAddr>,
+    /// Maximum number of concurrently connected clients.
+    #[arg(long, default_value_t = NonZeroUsize::new(128).unwrap())]
+    max_clients: NonZeroUsize,
+    /// Also accept clients on a unix domain socket at this path.
+    #[arg(long)]
+    unix: Option<PathBuf>,
+    /// Database holding user accounts and queued direct messages.
+    #[arg(long, default_value = "sqlite://chat.db?mode=rwc")]
+    db: String,
+    /// Number of mention notifications that may wait for delivery before new
+    /// ones are dropped.
+    #[arg(long, default_value_t = 256)]
+    webhook_queue: usize,
+    /// Let webhooks post to loopback, private and link-local addresses,
+    /// e.g. to try them out against a local server.
+    #[arg(long)]
+    webhook_allow_private: bool,
+    /// Append-only JSON-lines audit trail of joins, leaves and moderation.
+    #[arg(long, default_value = "chat-audit.jsonl")]
+    audit_log: PathBuf,
+    /// Rotate the audit log once it grows past this many bytes.
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    audit_max_bytes: u64,
+    /// Number of rotated audit logs to keep.
+    #[arg(long, default_value_t = 5)]
+    audit_keep: usize,
 }
 
-impl Peer {
-    pub fn new(name: String, stream: SplitSink<Framed<TcpStream, LinesCodec>, String>) -> Self {
-        Self { name, stream }
+/// Bind a listener, keeping IPv6 sockets v6-only so that an IPv4 and an IPv6
+/// listener can share the same port.
+fn bind(addr: SocketAddr) -> anyhow::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
     }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(TcpListener::from_std(socket.into())?)
 }
 
-#[derive(Debug)]
-struct Message {
-    username: String,
-    content: String,
-}
-
-impl Message {
-    fn new(username: String, content: String) -> Self {
-        Self { username, content }
+async fn serve_tcp(
+    listener: TcpListener,
+    server: Arc<Server>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let mut clients = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, addr) = accepted?;
+                clients.spawn(admit(stream, PeerAddr::Tcp(addr), &server));
+            }
+            Some(done) = clients.join_next() => reap(done),
+            _ = shutdown.cancelled() => break,
+        }
     }
+    close(clients).await;
+    Ok(())
 }
 
-impl Display for Message {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{}", self.username, self.content)
+async fn serve_unix(
+    listener: UnixListener,
+    server: Arc<Server>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let mut clients = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                clients.spawn(admit(stream, PeerAddr::next_unix(), &server));
+            }
+            Some(done) = clients.join_next() => reap(done),
+            _ = shutdown.cancelled() => break,
+        }
     }
+    close(clients).await;
+    Ok(())
 }
 
-#[derive(Debug, Default)]
-struct Server {
-    peers: DashMap<SocketAddr, Peer>,
-}
-
-impl Server {
-    pub fn new() -> Self {
-        Self::default()
+/// Disconnect a listener's clients and wait until every task is gone.
+async fn close(mut clients: JoinSet<()>) {
+    if !clients.is_empty() {
+        info!("Disconnecting {} client(s).", clients.len());
     }
-
-    pub async fn join(&self, addr: SocketAddr, peer: Peer) -> anyhow::Result<()> {
-        let name = peer.name.clone();
-        self.peers.insert(addr, peer);
-        let msg = format!("{} joined the chat.", name);
-        info!(msg);
-        let msg = Message::new("Server".to_string(), msg);
-        self.broadcast(addr, Arc::new(msg)).await?;
-        Ok(())
-    }
-
-    pub async fn broadcast(&self, src_addr: SocketAddr, msg: Arc<Message>) -> anyhow::Result<()> {
-        for mut peer in self.peers.iter_mut() {
-            if peer.key().eq(&src_addr) {
-                continue;
-            }
-            let msg = msg.clone();
-            if let Err(e) = peer.stream.send(msg.to_string()).await {
-                warn!("failed sending message to {}: {}", peer.key(), e);
-                self.peers.remove(peer.key());
-            }
-        }
-
-        Ok(())
+    clients.abort_all();
+    while let Some(done) = clients.join_next().await {
+        reap(done);
     }
+}
 
-    pub async fn leave(&self, addr: SocketAddr) -> anyhow::Result<()> {
-        let Some((_, peer)) = self.peers.remove(&addr) else {
-            return Err(anyhow!("fail to remove peer({}) from global state.", addr));
-        };
-        let msg = format!("{} left the chat.", peer.name);
-
-        info!(msg);
-        let msg = Message::new("Server".to_string(), msg);
-        self.broadcast(addr, Arc::new(msg)).await
+/// Bind a unix socket listener, replacing a stale socket file left behind by
+/// a previous run.
+fn bind_unix(path: &Path) -> anyhow::Result<UnixListener> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
     }
+    Ok(UnixListener::bind(path)?)
 }
 
-async fn handle_client(
-    mut stream: Framed<TcpStream, LinesCodec>,
-    addr: SocketAddr,
-    server: Arc<Server>,
-) -> anyhow::Result<()> {
-    stream.send("Please enter your name:").await?;
-    let Some(Ok(name)) = stream.next().await else {
-        let err_msg = "failed to get username".to_string();
-        error!(err_msg);
-        return Err(anyhow!(err_msg));
-    };
-
-    let (writer, mut reader) = stream.split();
-    let peer = Peer::new(name.clone(), writer);
-
-    server.join(addr, peer).await?;
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let console = fmt::Layer::new().pretty().with_filter(LevelFilter::INFO);
+
+    let tracer = init_tracer()?;
+    // the server's connection slot gauges
+    let meter_provider = init_meter_provider()?;
+    global::set_meter_provider(meter_provider.clone());
+    let open_telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(open_telemetry)
+        .with(ecosystem::console::layer())
+        .init();
+
+    let store = UserStore::try_new(&args.db).await?;
+    let notifier = WebhookNotifier::spawn(args.webhook_queue, args.webhook_allow_private);
+    let audit = AuditLog::open(args.audit_log, args.audit_max_bytes, args.audit_keep).await?;
+    let server = Server::new(args.max_clients, store, notifier, audit);
+    let server = Arc::new(server);
 
-    while let Some(line) = reader.next().await {
-        match line {
-            Ok(msg) => {
-                if !msg.is_empty() {
-                    let msg = Message::new(name.clone(), msg);
-                    server.broadcast(addr, Arc::new(msg)).await?;
-                } else {
-                    warn!("empty line");
-                    continue;
-                }
-            }
-            Err(e) => {
-                warn!("error read line from {}: {}", addr, e);
-                break;
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                info!("Shutting down.");
+                shutdown.cancel();
             }
         }
+    });
+
+    let mut listeners = Vec::with_capacity(args.listen.len() + 1);
+    for addr in args.listen {
+        let listener = bind(addr)?;
+        info!("Listening on {}.", addr);
+        let span = info_span!("listener", %addr);
+        listeners.push(
+            serve_tcp(listener, server.clone(), shutdown.clone())
+                .instrument(span)
+                .boxed(),
+        );
+    }
+    if let Some(path) = args.unix {
+        let listener = bind_unix(&path)?;
+        info!("Listening on {}.", path.display());
+        let span = info_span!("listener", addr = %path.display());
+        listeners.push(
+            serve_unix(listener, server.clone(), shutdown.clone())
+                .instrument(span)
+                .boxed(),
+        );
+    }
+    future::try_join_all(listeners).await?;
+    if let Err(e) = meter_provider.shutdown() {
+        warn!("flushing metrics: {}", e);
     }
-
-    server.leave(addr).await?;
-
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let layer = fmt::Layer::new().pretty().with_filter(LevelFilter::INFO);
-    tracing_subscriber::registry().with(layer).init();
+fn init_tracer() -> anyhow::Result<Tracer> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint("http://localhost:4317"),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_id_generator(RandomIdGenerator::default())
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", "chat")])),
+        )
+        .install_batch(Tokio)?;
+    Ok(tracer)
+}
 
-    let addr = "0.0.0.0:8088";
-    let listener = TcpListener::bind(addr).await?;
-    info!("Listening on {}.", addr);
-    let server = Server::default();
-    let server = Arc::new(server);
-    loop {
-        let (stream, addr) = listener.accept().await?;
-        info!("Accepted connection from {}", addr);
-        let framed = Framed::new(stream, LinesCodec::default());
-        let server_cloned = server.clone();
-        tokio::spawn(async move {
-            if let Err(e) = handle_client(framed, addr, server_cloned).await {
-                error!("error handle client {}: {}", addr, e);
-            }
-        });
-    }
+fn init_meter_provider() -> anyhow::Result<SdkMeterProvider> {
+    let meter_provider = opentelemetry_otlp::new_pipeline()
+        .metrics(Tokio)
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint("http://localhost:4317"),
+        )
+        .with_resource(Resource::new(vec![KeyValue::new("service.name", "chat")]))
+        .build()?;
+    Ok(meter_provider)
 }
@@ -0,0 +1,204 @@
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use ecosystem::chat::bus::{Message, MessageBus};
+use ecosystem::ids::UserId;
+use futures_util::{future, Stream, StreamExt};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+use pb::chat_client::ChatClient;
+use pb::chat_server::{Chat, ChatServer};
+use pb::{client_event, server_event, ClientEvent, ServerEvent};
+
+mod pb {
+    tonic::include_proto!("chat");
+}
+
+/// The broadcast-bus chat from `chat_mpsc_broadcast`, served as a gRPC
+/// bidirectional stream (see `proto/chat.proto`) instead of raw lines.
+#[derive(Debug, Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the chat service.
+    Server {
+        #[arg(long, default_value = "0.0.0.0:8090")]
+        listen: SocketAddr,
+    },
+    /// Join the chat as `name`, sending stdin lines and printing events.
+    Client {
+        #[arg(long, default_value = "http://127.0.0.1:8090")]
+        server: String,
+        #[arg(long)]
+        name: String,
+    },
+}
+
+type ServerEventStream = Pin<Box<dyn Stream<Item = Result<ServerEvent, Status>> + Send>>;
+
+#[derive(Debug, Default)]
+struct ChatService {
+    bus: MessageBus,
+}
+
+impl From<&Message> for ServerEvent {
+    fn from(msg: &Message) -> Self {
+        let event = match msg {
+            Message::UserJoin(name) => server_event::Event::UserJoined(pb::UserJoined {
+                name: name.to_string(),
+            }),
+            Message::UserLeft(name) => server_event::Event::UserLeft(pb::UserLeft {
+                name: name.to_string(),
+            }),
+            Message::Chat { user_name, content } => server_event::Event::Chat(pb::ChatMessage {
+                user_name: user_name.to_string(),
+                content: content.clone(),
+            }),
+        };
+        ServerEvent { event: Some(event) }
+    }
+}
+
+#[tonic::async_trait]
+impl Chat for ChatService {
+    type ChatStream = ServerEventStream;
+
+    async fn chat(
+        &self,
+        request: Request<Streaming<ClientEvent>>,
+    ) -> Result<Response<Self::ChatStream>, Status> {
+        let mut inbound = request.into_inner();
+        let user_name = match inbound.message().await? {
+            Some(ClientEvent {
+                event: Some(client_event::Event::Join(join)),
+            }) => UserId::from(join.name),
+            _ => return Err(Status::invalid_argument("first event must be a join")),
+        };
+
+        // subscribe before announcing ourselves so we see our own join
+        let rx = self.bus.get_receiver();
+        let tx = self.bus.get_sender();
+        info!("{} joined the chat.", user_name);
+        tx.send(Arc::new(Message::user_join(user_name.clone())))
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        let name = user_name.clone();
+        tokio::spawn(async move {
+            loop {
+                match inbound.message().await {
+                    Ok(Some(ClientEvent {
+                        event: Some(client_event::Event::Say(say)),
+                    })) => {
+                        let _ = tx.send(Arc::new(Message::chat(name.clone(), say.content)));
+                    }
+                    Ok(Some(_)) => warn!("{} sent an unexpected event", name),
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("error reading from {}: {}", name, e);
+                        break;
+                    }
+                }
+            }
+            info!("{} left the chat.", name);
+            let _ = tx.send(Arc::new(Message::user_left(name)));
+        });
+
+        let outbound = BroadcastStream::new(rx)
+            .take_while({
+                let name = user_name.clone();
+                move |msg| {
+                    let own_leave = match msg {
+                        Ok(m) => matches!(m.as_ref(), Message::UserLeft(left) if *left == name),
+                        Err(_) => false,
+                    };
+                    future::ready(!own_leave)
+                }
+            })
+            .filter_map(move |msg| {
+                let event = match msg {
+                    Ok(m) => match m.as_ref() {
+                        Message::Chat {
+                            user_name: from, ..
+                        } if *from == user_name => None,
+                        m => Some(Ok(ServerEvent::from(m))),
+                    },
+                    Err(BroadcastStreamRecvError::Lagged(n)) => {
+                        warn!("{} lagged behind by {} messages", user_name, n);
+                        None
+                    }
+                };
+                future::ready(event)
+            });
+
+        Ok(Response::new(Box::pin(outbound)))
+    }
+}
+
+async fn client(server: String, name: String) -> anyhow::Result<()> {
+    let mut client = ChatClient::connect(server).await?;
+
+    let (tx, rx) = mpsc::channel(16);
+    tx.send(ClientEvent {
+        event: Some(client_event::Event::Join(pb::Join { name })),
+    })
+    .await?;
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(content)) = lines.next_line().await {
+            let event = ClientEvent {
+                event: Some(client_event::Event::Say(pb::Say { content })),
+            };
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut events = client
+        .chat(Request::new(ReceiverStream::new(rx)))
+        .await?
+        .into_inner();
+    while let Some(event) = events.message().await? {
+        match event.event {
+            Some(server_event::Event::UserJoined(e)) => println!("{} joined the chat.", e.name),
+            Some(server_event::Event::UserLeft(e)) => println!("{} left the chat.", e.name),
+            Some(server_event::Event::Chat(e)) => println!("{}:{}", e.user_name, e.content),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let layer = fmt::Layer::new().pretty().with_filter(LevelFilter::INFO);
+    tracing_subscriber::registry().with(layer).init();
+
+    match Args::parse().command {
+        Command::Server { listen } => {
+            info!("Start gRPC chat server, listening on {}", listen);
+            Server::builder()
+                .add_service(ChatServer::new(ChatService::default()))
+                .serve(listen)
+                .await?;
+        }
+        Command::Client { server, name } => client(server, name).await?,
+    }
+    Ok(())
+}
@@ -1,101 +1,47 @@
-use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 
 use anyhow::anyhow;
+use ecosystem::chat::bus::{Message, MessageBus};
+use ecosystem::ids::UserId;
+use ecosystem::observed::Lagging;
 use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::broadcast::error::RecvError;
-use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tokio::sync::broadcast::{Receiver, Sender};
 use tokio_util::codec::{Framed, LinesCodec};
 use tracing::{error, info, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-#[derive(Debug)]
-enum Message {
-    UserJoin(String),
-    UserLeft(String),
-    Chat { user_name: String, content: String },
-}
-
-impl Message {
-    fn chat(user_name: String, content: String) -> Self {
-        Self::Chat { user_name, content }
-    }
-    fn user_join(user_name: String) -> Self {
-        Self::UserJoin(user_name)
-    }
-    fn user_left(user_name: String) -> Self {
-        Self::UserLeft(user_name)
-    }
-}
-
-impl Display for Message {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Message::UserJoin(name) => write!(f, "{} joined the chat.", name),
-            Message::UserLeft(name) => write!(f, "{} left the chat.", name),
-            Message::Chat { user_name, content } => write!(f, "{}:{}", user_name, content),
-        }
-    }
-}
-
-struct MessageBus {
-    tx: Sender<Arc<Message>>,
-}
-
-impl MessageBus {
-    fn new() -> Self {
-        let (tx, _) = channel(512);
-        Self { tx }
-    }
-
-    fn get_sender(&self) -> Sender<Arc<Message>> {
-        self.tx.clone()
-    }
-
-    fn get_receiver(&self) -> Receiver<Arc<Message>> {
-        self.tx.subscribe()
-    }
-}
-
+/// A client too slow to keep up skips what it missed; the `Lagging` receiver
+/// logs each gap, and the totals are logged when the client goes.
 async fn forward_to_client(
-    mut rx: Receiver<Arc<Message>>,
+    rx: Receiver<Arc<Message>>,
     mut stream_sender: SplitSink<Framed<TcpStream, LinesCodec>, String>,
-    client_name: String,
+    client_name: UserId,
 ) -> anyhow::Result<()> {
-    loop {
-        match rx.recv().await {
-            Ok(m) => {
-                match m.as_ref() {
-                    Message::UserLeft(left) if left.eq(&client_name) => {
-                        stream_sender.send("Bye!".to_string()).await?;
-                        break;
-                    }
-                    Message::UserJoin(join) if join.eq(&client_name) => {
-                        stream_sender
-                            .send(format!("Welcome {}!", client_name))
-                            .await?;
-                        continue;
-                    }
-                    Message::Chat { user_name, .. } if user_name.eq(&client_name) => continue,
-                    _ => {}
-                }
-                if let Err(e) = stream_sender.send(m.to_string()).await {
-                    warn!("error sending message to client: {}", e);
-                    break;
-                }
-            }
-            Err(RecvError::Lagged(_)) => {
-                warn!("message lagged.");
-            }
-            Err(e) => {
-                warn!("error receive message: {}", e);
+    let mut rx = Lagging::new(format!("bus:{}", client_name), rx);
+    while let Some(m) = rx.recv().await {
+        match m.as_ref() {
+            Message::UserLeft(left) if left.eq(&client_name) => {
+                stream_sender.send("Bye!".to_string()).await?;
                 break;
             }
+            Message::UserJoin(join) if join.eq(&client_name) => {
+                stream_sender
+                    .send(format!("Welcome {}!", client_name))
+                    .await?;
+                continue;
+            }
+            Message::Chat { user_name, .. } if user_name.eq(&client_name) => continue,
+            _ => {}
+        }
+        if let Err(e) = stream_sender.send(m.to_string()).await {
+            warn!("error sending message to client: {}", e);
+            break;
         }
     }
+    info!(stats = ?rx.stats().snapshot(), "{} stopped receiving.", client_name);
     Ok(())
 }
 
@@ -110,6 +56,7 @@ async fn handle_client(
         error!("error read user_name");
         return Err(anyhow!("error read user_name"));
     };
+    let user_name = UserId::from(user_name);
 
     info!("{} joined the chat.", user_name);
 
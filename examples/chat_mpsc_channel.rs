@@ -5,10 +5,12 @@ use std::sync::Arc;
 
 use anyhow::anyhow;
 use dashmap::DashMap;
+use ecosystem::ids::UserId;
+use ecosystem::observed::{self, ObservedSender};
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::Receiver;
 use tokio_util::codec::{Framed, LinesCodec};
 use tracing::{error, info, warn};
 use tracing_subscriber::layer::SubscriberExt;
@@ -17,16 +19,16 @@ use tracing_subscriber::util::SubscriberInitExt;
 #[derive(Debug)]
 enum Message {
     UserJoined {
-        user_name: String,
+        user_name: UserId,
         addr: SocketAddr,
-        handle: Sender<Arc<Message>>,
+        handle: ObservedSender<Arc<Message>>,
     },
     UserLeft {
-        user_name: String,
+        user_name: UserId,
         addr: SocketAddr,
     },
     Chat {
-        user_name: String,
+        user_name: UserId,
         content: String,
     },
 }
@@ -48,10 +50,10 @@ impl Display for Message {
 }
 
 #[derive(Debug, Default, Clone)]
-struct State(DashMap<SocketAddr, Sender<Arc<Message>>>);
+struct State(DashMap<SocketAddr, ObservedSender<Arc<Message>>>);
 
 impl Deref for State {
-    type Target = DashMap<SocketAddr, Sender<Arc<Message>>>;
+    type Target = DashMap<SocketAddr, ObservedSender<Arc<Message>>>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -59,6 +61,8 @@ impl Deref for State {
 }
 
 impl State {
+    /// Sends to each peer in turn, so one whose queue is full holds up the
+    /// rest; its sender logs how long that took.
     async fn broadcast(&self, addr: SocketAddr, msg: Arc<Message>) {
         for peer in self.iter() {
             if peer.key().eq(&addr) {
@@ -74,14 +78,14 @@ impl State {
 
 #[derive(Debug)]
 struct Peer {
-    user_name: String,
+    user_name: UserId,
     addr: SocketAddr,
     /// all the other peers to receive message from client
     others: Arc<State>,
 }
 
 impl Peer {
-    fn new(user_name: String, addr: SocketAddr, others: State) -> Self {
+    fn new(user_name: UserId, addr: SocketAddr, others: State) -> Self {
         Self {
             user_name,
             addr,
@@ -144,8 +148,8 @@ struct Registry {
 impl Registry {
     const MAX_MSG: usize = 128;
     /// get a peer and message faucet
-    async fn register(&self, addr: SocketAddr, name: String) -> (Peer, Receiver<Arc<Message>>) {
-        let (tx, rx) = tokio::sync::mpsc::channel::<Arc<Message>>(Self::MAX_MSG);
+    async fn register(&self, addr: SocketAddr, name: UserId) -> (Peer, Receiver<Arc<Message>>) {
+        let (tx, rx) = observed::channel(format!("peer:{}", name), Self::MAX_MSG);
 
         // user join message
         let msg = Message::UserJoined {
@@ -165,9 +169,11 @@ impl Registry {
         (peer, rx)
     }
 
-    async fn cancel(&self, addr: SocketAddr, user_name: String) {
-        self.peers.remove(&addr);
-        info!("{} left the chat.", user_name);
+    async fn cancel(&self, addr: SocketAddr, user_name: UserId) {
+        match self.peers.remove(&addr) {
+            Some((_, tx)) => info!(stats = ?tx.stats().snapshot(), "{} left the chat.", user_name),
+            None => info!("{} left the chat.", user_name),
+        }
         let msg = Arc::new(Message::UserLeft { user_name, addr });
         self.peers.broadcast(addr, msg.clone()).await;
     }
@@ -185,6 +191,7 @@ async fn handle_client(
         error!("error read user_name");
         return Err(anyhow!("error read user_name"));
     };
+    let user_name = UserId::from(user_name);
 
     let (peer, notifier) = registry.register(addr, user_name.clone()).await;
 
@@ -0,0 +1,181 @@
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use ecosystem::chat::{admit, reap, AuditLog, PeerAddr, Server, UserStore, WebhookNotifier};
+use quinn::rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use quinn::rustls::RootCertStore;
+use quinn::{ClientConfig, Endpoint, ServerConfig};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+/// The chat server from `examples/chat.rs`, carried over QUIC. Each client
+/// opens one bidirectional stream and speaks the same line protocol as over
+/// TCP; unlike TCP the connection survives the client changing address
+/// (e.g. switching networks), since quinn enables connection migration.
+#[derive(Debug, Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the chat server.
+    Server {
+        #[arg(long, default_value = "0.0.0.0:8089")]
+        listen: SocketAddr,
+        /// Where to write the self-signed certificate clients must trust.
+        #[arg(long, default_value = "chat_quic.der")]
+        cert: PathBuf,
+        #[arg(long, default_value_t = NonZeroUsize::new(128).unwrap())]
+        max_clients: NonZeroUsize,
+        #[arg(long, default_value = "sqlite://chat.db?mode=rwc")]
+        db: String,
+        #[arg(long, default_value = "chat-audit.jsonl")]
+        audit_log: PathBuf,
+    },
+    /// Connect to a server, forwarding stdin lines and printing what arrives.
+    Client {
+        #[arg(long, default_value = "127.0.0.1:8089")]
+        server: SocketAddr,
+        /// Certificate written by the server.
+        #[arg(long, default_value = "chat_quic.der")]
+        cert: PathBuf,
+        /// Ask the server for colored nicknames and notices.
+        #[arg(long)]
+        color: bool,
+    },
+}
+
+const SERVER_NAME: &str = "localhost";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let layer = fmt::Layer::new().pretty().with_filter(LevelFilter::INFO);
+    tracing_subscriber::registry().with(layer).init();
+
+    match Args::parse().command {
+        Command::Server {
+            listen,
+            cert,
+            max_clients,
+            db,
+            audit_log,
+        } => {
+            let store = UserStore::try_new(&db).await?;
+            let notifier = WebhookNotifier::spawn(256, false);
+            let audit = AuditLog::open(audit_log, 10 * 1024 * 1024, 5).await?;
+            let server = Arc::new(Server::new(max_clients, store, notifier, audit));
+            serve(listen, cert, server).await
+        }
+        Command::Client {
+            server,
+            cert,
+            color,
+        } => client(server, cert, color).await,
+    }
+}
+
+async fn serve(addr: SocketAddr, cert_path: PathBuf, server: Arc<Server>) -> anyhow::Result<()> {
+    let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+    let cert_der = CertificateDer::from(cert.cert);
+    tokio::fs::write(&cert_path, &cert_der).await?;
+    let key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let config = ServerConfig::with_single_cert(vec![cert_der], key.into())?;
+
+    let endpoint = Endpoint::server(config, addr)?;
+    info!(
+        "Listening on quic://{}, certificate in {}.",
+        addr,
+        cert_path.display()
+    );
+
+    // each task does the handshake and then serves the client, so the set
+    // owns every connection from the moment it's accepted
+    let mut clients = JoinSet::new();
+    loop {
+        let incoming = tokio::select! {
+            incoming = endpoint.accept() => incoming,
+            Some(done) = clients.join_next() => {
+                reap(done);
+                continue;
+            }
+        };
+        let Some(incoming) = incoming else {
+            break;
+        };
+        let server = server.clone();
+        clients.spawn(async move {
+            let connection = match incoming.await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("quic handshake failed: {}", e);
+                    return;
+                }
+            };
+            let addr = connection.remote_address();
+            match connection.accept_bi().await {
+                Ok((send, recv)) => {
+                    admit(tokio::io::join(recv, send), PeerAddr::Quic(addr), &server).await
+                }
+                Err(e) => warn!("no stream opened by {}: {}", addr, e),
+            }
+        });
+    }
+    while let Some(done) = clients.join_next().await {
+        reap(done);
+    }
+    Ok(())
+}
+
+async fn client(addr: SocketAddr, cert_path: PathBuf, color: bool) -> anyhow::Result<()> {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(tokio::fs::read(&cert_path).await?))?;
+    let config = ClientConfig::with_root_certificates(Arc::new(roots))?;
+
+    let bind_addr: SocketAddr = if addr.is_ipv6() {
+        "[::]:0".parse()?
+    } else {
+        "0.0.0.0:0".parse()?
+    };
+    let mut endpoint = Endpoint::client(bind_addr)?;
+    endpoint.set_default_client_config(config);
+
+    let connection = endpoint.connect(addr, SERVER_NAME)?.await?;
+    // A QUIC stream only becomes visible to the peer once data is written to
+    // it, so the server greets us after the first line (our name) is sent.
+    let (mut send, recv) = connection.open_bi().await?;
+    if color {
+        send.write_all(b"CAP color\n").await?;
+    }
+    eprintln!(
+        "Connected to {}, type your name and then a password to join.",
+        addr
+    );
+
+    // The server closes the connection once we leave; that ends the printer.
+    let printer = tokio::spawn(async move {
+        let mut lines = BufReader::new(recv).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            println!("{}", line);
+        }
+    });
+
+    let mut stdin = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = stdin.next_line().await? {
+        send.write_all(line.as_bytes()).await?;
+        send.write_all(b"\n").await?;
+    }
+    send.finish()?;
+    printer.await?;
+    endpoint.wait_idle().await;
+    Ok(())
+}
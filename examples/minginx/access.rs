@@ -0,0 +1,140 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use ecosystem::units::{Bytes, Millis};
+use serde::Serialize;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{error, warn, Span};
+
+/// One proxied connection, written when it ends. `bytes_in` counts client to
+/// upstream, `bytes_out` upstream to client.
+#[derive(Debug, Serialize)]
+pub struct AccessRecord {
+    pub at: DateTime<Utc>,
+    pub listener: String,
+    pub client: SocketAddr,
+    pub upstream: Option<String>,
+    pub bytes_in: Bytes,
+    pub bytes_out: Bytes,
+    #[serde(rename = "duration_ms")]
+    pub duration: Millis,
+    pub reason: String,
+}
+
+impl AccessRecord {
+    pub fn new(listener: &str, client: SocketAddr) -> Self {
+        Self {
+            at: Utc::now(),
+            listener: listener.to_string(),
+            client,
+            upstream: None,
+            bytes_in: Bytes::default(),
+            bytes_out: Bytes::default(),
+            duration: Millis::default(),
+            reason: String::new(),
+        }
+    }
+
+    /// Fill in the connection's tracing span, so traces carry the same
+    /// facts as the access log.
+    pub fn trace(&self, span: &Span) {
+        span.record("client", tracing::field::display(self.client));
+        if let Some(upstream) = &self.upstream {
+            span.record("upstream", upstream.as_str());
+        }
+        span.record("bytes_in", self.bytes_in.0);
+        span.record("bytes_out", self.bytes_out.0);
+        span.record("duration_ms", self.duration.0);
+        span.record("reason", self.reason.as_str());
+    }
+}
+
+/// JSON-lines access log, separate from the tracing output and rotated by
+/// size (`access.jsonl` → `access.jsonl.1` → ... → `access.jsonl.<keep>`).
+/// Records go through a background writer so the proxy never waits on disk.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    tx: mpsc::Sender<AccessRecord>,
+}
+
+impl AccessLog {
+    pub async fn open(path: PathBuf, max_bytes: u64, keep: usize) -> anyhow::Result<Self> {
+        let file = open_append(&path).await?;
+        let written = file.metadata().await?.len();
+        let (tx, rx) = mpsc::channel(1024);
+        let writer = Writer {
+            path,
+            file,
+            written,
+            max_bytes,
+            keep,
+        };
+        tokio::spawn(writer.run(rx));
+        Ok(Self { tx })
+    }
+
+    pub async fn record(&self, record: AccessRecord) {
+        if self.tx.send(record).await.is_err() {
+            warn!("access log writer gone, dropping record");
+        }
+    }
+}
+
+async fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+struct Writer {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl Writer {
+    async fn run(mut self, mut rx: mpsc::Receiver<AccessRecord>) {
+        while let Some(record) = rx.recv().await {
+            if let Err(e) = self.write(&record).await {
+                error!("failed writing access log {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    async fn write(&mut self, record: &AccessRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate().await?;
+        }
+        self.file.write_all(&line).await?;
+        self.file.flush().await?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    async fn rotate(&mut self) -> anyhow::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        for n in (1..self.keep).rev() {
+            match tokio::fs::rename(rotated(n), rotated(n + 1)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        if self.keep > 0 {
+            tokio::fs::rename(&self.path, rotated(1)).await?;
+        } else {
+            tokio::fs::remove_file(&self.path).await?;
+        }
+        self.file = open_append(&self.path).await?;
+        self.written = 0;
+        Ok(())
+    }
+}
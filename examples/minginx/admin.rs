@@ -0,0 +1,269 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use prometheus::Registry;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::metrics;
+use crate::upstream::Upstream;
+use crate::vhost::Switch;
+
+/// Each listener's name with its upstream groups, the default ones first.
+pub type Groups = Vec<(String, Vec<Arc<Upstream>>)>;
+/// Each listener's name with its switchable default group.
+pub type Switches = Vec<(String, Arc<Switch>)>;
+
+#[derive(Debug, Clone)]
+struct Admin {
+    registry: Registry,
+    groups: Arc<Groups>,
+    switches: Arc<Switches>,
+}
+
+/// One server in `GET /upstreams`. `group` counts the listener's groups:
+/// its top-level `upstreams` or blue/green `groups` first, then the header
+/// routes' and virtual servers'.
+#[derive(Debug, Serialize)]
+struct ServerStatus {
+    listener: String,
+    group: usize,
+    addr: String,
+    resolved: Vec<SocketAddr>,
+    weight: u32,
+    healthy: bool,
+    drained: bool,
+    active: usize,
+    connections: u64,
+    failures: u64,
+}
+
+/// One of a listener's blue/green groups in `GET /groups`. `in_flight`
+/// counts its open connections, so a group switched away from has drained
+/// once it reaches zero.
+#[derive(Debug, Serialize)]
+struct GroupStatus {
+    listener: String,
+    group: String,
+    active: bool,
+    servers: Vec<String>,
+    in_flight: usize,
+}
+
+/// The answer to switching groups.
+#[derive(Debug, Serialize)]
+struct Switched {
+    listener: String,
+    active: String,
+    previous: String,
+    /// Connections still open on `previous`.
+    draining: usize,
+}
+
+/// Serve the admin API on `listen` until the process exits: Prometheus
+/// metrics, upstream state, draining servers out of rotation, and switching
+/// blue/green groups.
+pub async fn serve(
+    listen: SocketAddr,
+    registry: Registry,
+    groups: Groups,
+    switches: Switches,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    info!(
+        "admin: http://{}/metrics, http://{}/upstreams",
+        listen, listen
+    );
+    let admin = Admin {
+        registry,
+        groups: Arc::new(groups),
+        switches: Arc::new(switches),
+    };
+    let app = Router::new()
+        .route("/metrics", get(render))
+        .route("/upstreams", get(upstreams))
+        .route("/upstreams/:addr/drain", post(drain))
+        .route("/upstreams/:addr/enable", post(enable))
+        .route("/groups", get(switchable_groups))
+        .route(
+            "/listeners/:listener/groups/:group/activate",
+            post(activate),
+        )
+        .with_state(admin);
+    axum::serve(listener, app.into_make_service()).await?;
+    Ok(())
+}
+
+async fn render(State(admin): State<Admin>) -> String {
+    metrics::render(&admin.registry)
+}
+
+async fn upstreams(State(admin): State<Admin>) -> Json<Vec<ServerStatus>> {
+    let groups = admin.groups.iter().flat_map(|(listener, groups)| {
+        groups
+            .iter()
+            .enumerate()
+            .map(move |(i, g)| (listener, i, g))
+    });
+    let servers = groups.flat_map(|(listener, group, upstream)| {
+        upstream.servers().iter().map(move |server| ServerStatus {
+            listener: listener.clone(),
+            group,
+            addr: server.addr.clone(),
+            resolved: server.resolved(),
+            weight: server.weight,
+            healthy: server.is_healthy(),
+            drained: server.is_drained(),
+            active: server.active(),
+            connections: server.connections(),
+            failures: server.failures(),
+        })
+    });
+    Json(servers.collect())
+}
+
+async fn drain(State(admin): State<Admin>, Path(addr): Path<String>) -> StatusCode {
+    set_drained(&admin, &addr, true)
+}
+
+async fn enable(State(admin): State<Admin>, Path(addr): Path<String>) -> StatusCode {
+    set_drained(&admin, &addr, false)
+}
+
+async fn switchable_groups(State(admin): State<Admin>) -> Json<Vec<GroupStatus>> {
+    let groups = admin.switches.iter().flat_map(|(listener, switch)| {
+        switch
+            .groups()
+            .iter()
+            .map(move |(name, upstream)| GroupStatus {
+                listener: listener.clone(),
+                group: name.clone(),
+                active: name == switch.active_name(),
+                servers: upstream.servers().iter().map(|s| s.addr.clone()).collect(),
+                in_flight: in_flight(upstream),
+            })
+    });
+    Json(groups.collect())
+}
+
+async fn activate(
+    State(admin): State<Admin>,
+    Path((listener, group)): Path<(String, String)>,
+) -> Result<Json<Switched>, StatusCode> {
+    let (_, switch) = admin
+        .switches
+        .iter()
+        .find(|(name, _)| *name == listener)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let (previous, upstream) = switch.activate(&group).ok_or(StatusCode::NOT_FOUND)?;
+    info!(listener, group, previous, "active group switched by admin");
+    Ok(Json(Switched {
+        listener,
+        active: group,
+        previous: previous.clone(),
+        draining: in_flight(upstream),
+    }))
+}
+
+fn in_flight(upstream: &Upstream) -> usize {
+    upstream.servers().iter().map(|s| s.active()).sum()
+}
+
+/// Applies to `addr` in every group of every listener it appears in.
+fn set_drained(admin: &Admin, addr: &str, drained: bool) -> StatusCode {
+    let mut found = false;
+    let groups = admin.groups.iter().flat_map(|(_, groups)| groups);
+    for server in groups.flat_map(|g| g.servers()) {
+        if server.addr == addr {
+            server.set_drained(drained);
+            found = true;
+        }
+    }
+    match found {
+        true => {
+            info!(upstream = addr, drained, "changed by admin");
+            StatusCode::NO_CONTENT
+        }
+        false => StatusCode::NOT_FOUND,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Balance;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_and_enable_by_address() {
+        let upstream = Upstream::new(
+            vec!["a:1".to_string().into(), "b:2".to_string().into()],
+            Balance::RoundRobin,
+        )
+        .unwrap();
+        let admin = Admin {
+            registry: metrics::registry().unwrap(),
+            groups: Arc::new(vec![("default".to_string(), vec![Arc::new(upstream)])]),
+            switches: Arc::new(Vec::new()),
+        };
+
+        let status = drain(State(admin.clone()), Path("b:2".to_string())).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let Json(servers) = upstreams(State(admin.clone())).await;
+        let drained: Vec<_> = servers.iter().map(|s| s.drained).collect();
+        assert_eq!(drained, [false, true]);
+
+        let status = enable(State(admin.clone()), Path("b:2".to_string())).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(!admin.groups[0].1[0].servers()[1].is_drained());
+        let status = drain(State(admin), Path("c:3".to_string())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn activate_switches_named_group() {
+        let config: crate::config::Config = toml::from_str(
+            r#"
+            [[groups]]
+            name = "blue"
+            upstreams = ["blue:80"]
+
+            [[groups]]
+            name = "green"
+            upstreams = ["green:80"]
+            "#,
+        )
+        .unwrap();
+        let router = crate::vhost::Router::new(&config.listeners()[0]).unwrap();
+        let admin = Admin {
+            registry: metrics::registry().unwrap(),
+            groups: Arc::new(Vec::new()),
+            switches: Arc::new(vec![("default".to_string(), Arc::clone(router.switch()))]),
+        };
+        let path = |listener: &str, group: &str| Path((listener.to_string(), group.to_string()));
+
+        let _lease = router.default().pick("10.0.0.1".parse().unwrap());
+        let Json(switched) = activate(State(admin.clone()), path("default", "green"))
+            .await
+            .unwrap();
+        assert_eq!((switched.previous.as_str(), switched.draining), ("blue", 1));
+        assert_eq!(router.default().servers()[0].addr, "green:80");
+
+        let Json(groups) = switchable_groups(State(admin.clone())).await;
+        let active: Vec<_> = groups
+            .iter()
+            .map(|g| (g.group.as_str(), g.active))
+            .collect();
+        assert_eq!(active, [("blue", false), ("green", true)]);
+
+        let missing = activate(State(admin.clone()), path("default", "red")).await;
+        assert_eq!(missing.unwrap_err(), StatusCode::NOT_FOUND);
+        let missing = activate(State(admin), path("other", "blue")).await;
+        assert_eq!(missing.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use flate2::write::{DeflateEncoder, GzEncoder};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::config::Compression;
+use crate::transfer::{copy, Transfer};
+
+/// Response heads larger than this are relayed uncompressed.
+const MAX_HEAD: usize = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// The encoding to use for a client's `Accept-Encoding`, gzip preferred;
+/// codings listed with `q=0` are refused.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let accepted = |name: &str| {
+        accept_encoding.split(',').any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let coding = parts.next().unwrap_or_default();
+            let refused = parts.any(|p| matches!(p, "q=0" | "q=0.0" | "q=0.00" | "q=0.000"));
+            (coding.eq_ignore_ascii_case(name) || coding == "*") && !refused
+        })
+    };
+    [Encoding::Gzip, Encoding::Deflate]
+        .into_iter()
+        .find(|encoding| accepted(encoding.name()))
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(DeflateEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding) -> Self {
+        let level = flate2::Compression::default();
+        match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), level)),
+            Encoding::Deflate => Encoder::Deflate(DeflateEncoder::new(Vec::new(), level)),
+        }
+    }
+
+    /// Feed `data` in and take whatever compressed output is ready, so only
+    /// the encoder's window is ever held in memory.
+    fn encode(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(e) => {
+                e.write_all(data)?;
+                Ok(std::mem::take(e.get_mut()))
+            }
+            Encoder::Deflate(e) => {
+                e.write_all(data)?;
+                Ok(std::mem::take(e.get_mut()))
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(e) => e.finish(),
+            Encoder::Deflate(e) => e.finish(),
+        }
+    }
+}
+
+/// Relay the upstream's response to the client, compressing its body with
+/// `encoding` if [`eligible`]. Relies on the upstream closing after one
+/// response (the `http` mode sends `Connection: close`), so the compressed
+/// body is delimited by closing the connection as well.
+pub async fn relay<R, W>(
+    upstream: &mut R,
+    client: &mut W,
+    encoding: Encoding,
+    config: &Compression,
+    counter: &AtomicU64,
+    transfer: &Transfer,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = Vec::with_capacity(4096);
+    let parsed = loop {
+        if upstream.read_buf(&mut buf).await? == 0 || buf.len() >= MAX_HEAD {
+            break None;
+        }
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut res = httparse::Response::new(&mut headers);
+        match res.parse(&buf) {
+            Ok(httparse::Status::Complete(len)) => {
+                break eligible(&res, config).then(|| (rewrite(&res, encoding), len))
+            }
+            Ok(httparse::Status::Partial) => continue,
+            Err(_) => break None,
+        }
+    };
+    transfer.touch();
+
+    let Some((head, len)) = parsed else {
+        client.write_all(&buf).await?;
+        counter.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        return copy(upstream, client, counter, transfer).await;
+    };
+
+    let mut encoder = Encoder::new(encoding);
+    let mut out = head;
+    out.extend(encoder.encode(&buf[len..])?);
+    let mut chunk = vec![0; transfer.buffer_size];
+    loop {
+        if !out.is_empty() {
+            client.write_all(&out).await?;
+            counter.fetch_add(out.len() as u64, Ordering::Relaxed);
+        }
+        let n = upstream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        transfer.touch();
+        out = encoder.encode(&chunk[..n])?;
+    }
+    let tail = encoder.finish()?;
+    client.write_all(&tail).await?;
+    counter.fetch_add(tail.len() as u64, Ordering::Relaxed);
+    client.shutdown().await
+}
+
+/// Worth compressing: a response with a body, not already encoded or
+/// chunked, of a configured content type, and not known to be smaller than
+/// `min_size`.
+fn eligible(res: &httparse::Response, config: &Compression) -> bool {
+    let header = |name: &str| {
+        res.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| String::from_utf8_lossy(h.value).trim().to_ascii_lowercase())
+    };
+    let has_body = !matches!(res.code, Some(100..=199 | 204 | 304) | None);
+    let content_type = header("content-type").unwrap_or_default();
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    let big_enough = header("content-length")
+        .and_then(|len| len.parse::<u64>().ok())
+        .is_none_or(|len| len >= config.min_size);
+    has_body
+        && header("content-encoding").is_none()
+        && header("transfer-encoding").is_none()
+        && config
+            .types
+            .iter()
+            .any(|t| media_type.starts_with(t.as_str()))
+        && big_enough
+}
+
+fn rewrite(res: &httparse::Response, encoding: Encoding) -> Vec<u8> {
+    let mut out = format!(
+        "HTTP/1.{} {} {}\r\n",
+        res.version.unwrap_or(1),
+        res.code.unwrap_or(200),
+        res.reason.unwrap_or_default()
+    )
+    .into_bytes();
+    for header in res.headers.iter() {
+        let name = header.name.to_ascii_lowercase();
+        if matches!(
+            name.as_str(),
+            "content-length" | "connection" | "keep-alive"
+        ) {
+            continue;
+        }
+        out.extend_from_slice(header.name.as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(header.value);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(format!("Content-Encoding: {}\r\n", encoding.name()).as_bytes());
+    out.extend_from_slice(b"Vary: Accept-Encoding\r\nConnection: close\r\n\r\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    fn negotiate_prefers_gzip_and_honours_q0() {
+        assert_eq!(negotiate("deflate, gzip;q=0.8"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("br"), None);
+    }
+
+    #[tokio::test]
+    async fn relay_compresses_eligible_body() {
+        let body = "hello ".repeat(1000);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let transfer = Transfer::new();
+        let mut client = Vec::new();
+        relay(
+            &mut response.as_bytes(),
+            &mut client,
+            Encoding::Gzip,
+            &Compression::default(),
+            &transfer.bytes_out,
+            &transfer,
+        )
+        .await
+        .unwrap();
+
+        let split = client.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&client[..split]);
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        assert!(!head.contains("Content-Length"));
+        let mut decoded = String::new();
+        GzDecoder::new(&client[split..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+        assert_eq!(
+            transfer.bytes_out.load(Ordering::Relaxed),
+            client.len() as u64
+        );
+    }
+}
@@ -0,0 +1,1060 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{de, Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::acl::Cidr;
+use tracing_subscriber::filter::LevelFilter;
+
+/// Everything minginx needs to know before it binds a socket. Loaded from a
+/// TOML file (see `examples/minginx/minginx.toml`), then overridden by
+/// `MINGINX_*` environment variables, then validated as a whole.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The `default` listener; see [`ListenerConfig`].
+    pub listen: SocketAddr,
+    pub mode: Mode,
+    /// See [`UpstreamConfig`]. These take any request no virtual server
+    /// claims, and all connections in `tcp` mode.
+    pub upstreams: Vec<UpstreamConfig>,
+    pub balance: Balance,
+    pub groups: Vec<UpstreamGroup>,
+    /// The group that takes traffic at startup; the first one when unset.
+    pub active_group: Option<String>,
+    pub virtual_servers: Vec<VirtualServer>,
+    pub routes: Vec<RouteRule>,
+    pub tls: Option<ListenerTls>,
+    /// More listeners besides the `default` one above.
+    pub listeners: Vec<ListenerConfig>,
+    pub timeouts: Timeouts,
+    pub limits: Limits,
+    pub socket: SocketConfig,
+    pub rate_limit: RateLimit,
+    pub access: AccessControl,
+    pub health: HealthCheck,
+    pub dns: Dns,
+    pub http: HttpConfig,
+    pub log: LogConfig,
+    pub access_log: AccessLogConfig,
+    pub admin: AdminConfig,
+    pub udp: UdpConfig,
+    pub proxy_protocol: ProxyProtocolConfig,
+    pub socks5: Socks5Config,
+}
+
+/// `tcp` copies bytes blindly; `http` parses each request head first so it
+/// can add forwarding headers; `tls` passes TLS through untouched, reading
+/// only the ClientHello's SNI to pick a virtual server; `socks5` is a
+/// forward proxy, connecting wherever each client asks and ignoring
+/// `upstreams`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Tcp,
+    Http,
+    Tls,
+    Socks5,
+}
+
+/// How a new connection's upstream is chosen. `round_robin` is smooth
+/// weighted round-robin; `least_conn` prefers the server with the fewest
+/// in-flight connections per unit of weight; `ip_hash` pins each client
+/// address to one server for as long as that server stays healthy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    RoundRobin,
+    LeastConn,
+    IpHash,
+}
+
+/// One listening address with its own mode and upstreams, stats and
+/// connection limits. Everything outside the listener blocks is shared.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub name: String,
+    pub listen: SocketAddr,
+    #[serde(default = "default_mode")]
+    pub mode: Mode,
+    #[serde(default)]
+    pub upstreams: Vec<UpstreamConfig>,
+    #[serde(default = "default_balance")]
+    pub balance: Balance,
+    #[serde(default)]
+    pub groups: Vec<UpstreamGroup>,
+    pub active_group: Option<String>,
+    #[serde(default)]
+    pub virtual_servers: Vec<VirtualServer>,
+    #[serde(default)]
+    pub routes: Vec<RouteRule>,
+    pub tls: Option<ListenerTls>,
+}
+
+/// Terminate client TLS on a listener with `cert` and `key` (PEM). With
+/// `client_ca`, clients must present a certificate it signed (mutual TLS),
+/// and in `http` mode its subject goes upstream as `X-Client-Cert-Subject`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerTls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+}
+
+fn default_mode() -> Mode {
+    Mode::Tcp
+}
+
+/// An upstream group for requests whose `Host` (SNI in `tls` mode) matches
+/// one of `hosts`, either exactly (`api.example.com`) or as a wildcard
+/// (`*.example.com`, any depth of subdomain). Exact names win over
+/// wildcards, longer wildcards over shorter ones. Only used in `http` and
+/// `tls` modes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VirtualServer {
+    pub hosts: Vec<String>,
+    pub upstreams: Vec<UpstreamConfig>,
+    #[serde(default = "default_balance")]
+    pub balance: Balance,
+}
+
+/// Named alternatives to `upstreams` for blue/green deployments: only the
+/// active group takes the traffic `upstreams` otherwise would, and the admin
+/// API switches between them. Once `groups` are set, `upstreams` is unused.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamGroup {
+    pub name: String,
+    pub upstreams: Vec<UpstreamConfig>,
+    #[serde(default = "default_balance")]
+    pub balance: Balance,
+}
+
+/// In `http` mode, an upstream group for requests whose `header` (or
+/// `cookie`) matches, tried before any `Host` routing; the first matching
+/// rule wins. The value must equal `equals` or start with `prefix`; with
+/// neither it need only be present, or with `present = false` be missing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteRule {
+    pub header: Option<String>,
+    pub cookie: Option<String>,
+    pub equals: Option<String>,
+    pub prefix: Option<String>,
+    #[serde(default = "default_present")]
+    pub present: bool,
+    pub upstreams: Vec<UpstreamConfig>,
+    #[serde(default = "default_balance")]
+    pub balance: Balance,
+}
+
+fn default_present() -> bool {
+    true
+}
+
+fn default_balance() -> Balance {
+    Balance::RoundRobin
+}
+
+/// Either a bare `"host:port"` string or a table. The host may be a name,
+/// resolved on connect. With `tls = true` the proxy re-encrypts towards the
+/// upstream, verifying it against `ca` (a PEM bundle; the webpki roots when
+/// unset) under the name `sni` (the host part of `addr` when unset). A
+/// server with `weight = 3` gets three connections for every one sent to a
+/// server with the default weight of 1.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "UpstreamEntry")]
+pub struct UpstreamConfig {
+    pub addr: String,
+    pub weight: u32,
+    pub tls: bool,
+    pub sni: Option<String>,
+    pub ca: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UpstreamEntry {
+    Addr(String),
+    Table {
+        addr: String,
+        #[serde(default = "default_weight")]
+        weight: u32,
+        #[serde(default)]
+        tls: bool,
+        sni: Option<String>,
+        ca: Option<PathBuf>,
+    },
+}
+
+impl From<UpstreamEntry> for UpstreamConfig {
+    fn from(entry: UpstreamEntry) -> Self {
+        match entry {
+            UpstreamEntry::Addr(addr) => addr.into(),
+            UpstreamEntry::Table {
+                addr,
+                weight,
+                tls,
+                sni,
+                ca,
+            } => Self {
+                addr,
+                weight,
+                tls,
+                sni,
+                ca,
+            },
+        }
+    }
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl From<String> for UpstreamConfig {
+    fn from(addr: String) -> Self {
+        Self {
+            addr,
+            weight: default_weight(),
+            tls: false,
+            sni: None,
+            ca: None,
+        }
+    }
+}
+
+/// `connect` bounds the upstream TCP connect plus any TLS handshake, `read`
+/// how long a client may take to send its request head in `http` mode (its
+/// ClientHello in `tls` mode), and `idle` how long a connection may go
+/// without bytes moving either way.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    #[serde(with = "humantime_serde")]
+    pub connect: Duration,
+    #[serde(with = "humantime_serde")]
+    pub read: Duration,
+    #[serde(with = "humantime_serde")]
+    pub idle: Duration,
+}
+
+/// Once a listener is proxying `max_connections`, its further clients either
+/// wait up to `queue_timeout` for a slot (`queue`) or are closed at once
+/// (`reject`). Each listener counts separately.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    pub max_connections: usize,
+    pub overflow: Overflow,
+    #[serde(with = "humantime_serde")]
+    pub queue_timeout: Duration,
+}
+
+/// Listener and per-connection socket tuning. `nodelay` and `keepalive`
+/// (TCP keepalive probes after this much idle time; off while unset) apply
+/// to client and upstream sockets alike. `buffer_size` is the per-direction
+/// copy buffer in bytes; spliced connections on Linux don't use one.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SocketConfig {
+    pub backlog: u32,
+    pub buffer_size: usize,
+    pub nodelay: bool,
+    #[serde(with = "humantime_serde")]
+    pub keepalive: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    Queue,
+    Reject,
+}
+
+/// Per-client-IP token bucket: `rate` connections (requests in `http`
+/// mode) per second with bursts of up to `burst`, tracking at most
+/// `max_clients` addresses, shared by all listeners. Clients are checked as
+/// soon as they connect, or once their PROXY protocol header is read; limited
+/// ones on plain HTTP listeners get a 429, the rest a closed connection.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimit {
+    pub enabled: bool,
+    pub rate: f64,
+    pub burst: u32,
+    pub max_clients: usize,
+}
+
+/// Client address filtering at accept time, before PROXY protocol headers
+/// are read: `deny` always refuses, a non-empty `allow` refuses everything
+/// it doesn't cover. Reloaded from the config file on SIGHUP.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessControl {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+/// Active TCP health checks; a server leaves rotation after `fall` failed
+/// probes in a row and rejoins after `rise` successful ones.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthCheck {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    pub rise: u32,
+    pub fall: u32,
+}
+
+/// Upstreams given by host name are looked up in the background every
+/// `refresh`, so connects use cached addresses and pick up DNS changes
+/// without a restart. A failed lookup keeps the previous addresses.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dns {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub refresh: Duration,
+}
+
+/// Only used in `http` mode. `host` replaces the client's `Host` header when
+/// set; `via` is appended to the `Via` header.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub via: String,
+    pub host: Option<String>,
+    pub compression: Compression,
+    pub mirror: Option<MirrorConfig>,
+    pub pool: PoolConfig,
+}
+
+/// Keep connections to plain TCP upstreams open between requests and reuse
+/// them, up to `max_idle` idle ones per upstream address. An idle connection
+/// is closed after `idle_timeout`, and any connection once it is
+/// `max_lifetime` old. Only requests whose end is known up front are sent
+/// over kept-alive connections; the rest still get `Connection: close`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub enabled: bool,
+    pub max_idle: usize,
+    #[serde(with = "humantime_serde")]
+    pub idle_timeout: Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_lifetime: Duration,
+}
+
+/// Send a copy of `percent` of requests (WebSocket handshakes excepted) to
+/// the plain TCP `upstream` as well, discarding its responses. A shadow
+/// that can't keep up with the client is abandoned, never waited for.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorConfig {
+    pub upstream: String,
+    pub percent: f64,
+}
+
+/// gzip/deflate for clients that accept it, applied to responses whose
+/// `Content-Type` starts with one of `types` and that are at least
+/// `min_size` bytes (or of unknown size).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Compression {
+    pub enabled: bool,
+    pub min_size: u64,
+    pub types: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    #[serde(deserialize_with = "level")]
+    pub level: LevelFilter,
+    pub otlp_endpoint: String,
+    pub service_name: String,
+    /// Share of new traces kept, from 0 to 1; traces started upstream keep
+    /// their sampling decision.
+    pub sample_ratio: f64,
+}
+
+/// Disabled unless `path` is set. Rotated once it grows past `max_bytes`,
+/// keeping `keep` old files.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    pub path: Option<PathBuf>,
+    pub max_bytes: u64,
+    pub keep: usize,
+}
+
+/// Serves Prometheus metrics and the upstream admin API on `listen` when
+/// set. Nothing there is authenticated, so keep it on a private address.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub listen: Option<SocketAddr>,
+}
+
+/// Relays datagrams to the same upstreams when `listen` is set; a client's
+/// session is dropped after `idle` without traffic.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UdpConfig {
+    pub listen: Option<SocketAddr>,
+    #[serde(with = "humantime_serde")]
+    pub idle: Duration,
+}
+
+/// `accept` expects every client connection to open with a PROXY protocol
+/// header from a fronting balancer and trusts the address in it; `send`
+/// opens every upstream connection with one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyProtocolConfig {
+    pub accept: bool,
+    pub send: Option<ProxyVersion>,
+}
+
+/// Clients of `socks5` listeners must log in as one of `users`; with none,
+/// they connect without authenticating.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Socks5Config {
+    pub users: Vec<Socks5User>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Socks5User {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyVersion {
+    V1,
+    V2,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {path}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse {path}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid {var}={value:?}: {reason}")]
+    Env {
+        var: &'static str,
+        value: String,
+        reason: String,
+    },
+    #[error("invalid configuration:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:8082".parse().unwrap(),
+            mode: default_mode(),
+            upstreams: vec!["0.0.0.0:8081".to_string().into()],
+            balance: default_balance(),
+            groups: Vec::new(),
+            active_group: None,
+            virtual_servers: Vec::new(),
+            routes: Vec::new(),
+            tls: None,
+            listeners: Vec::new(),
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            socket: SocketConfig::default(),
+            rate_limit: RateLimit::default(),
+            access: AccessControl::default(),
+            health: HealthCheck::default(),
+            dns: Dns::default(),
+            http: HttpConfig::default(),
+            log: LogConfig::default(),
+            access_log: AccessLogConfig::default(),
+            admin: AdminConfig::default(),
+            udp: UdpConfig::default(),
+            proxy_protocol: ProxyProtocolConfig::default(),
+            socks5: Socks5Config::default(),
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(5),
+            read: Duration::from_secs(30),
+            idle: Duration::from_secs(300),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            overflow: Overflow::Queue,
+            queue_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            backlog: 1024,
+            buffer_size: 8 * 1024,
+            nodelay: false,
+            keepalive: None,
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rate: 10.0,
+            burst: 20,
+            max_clients: 10_000,
+        }
+    }
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(1),
+            rise: 2,
+            fall: 3,
+        }
+    }
+}
+
+impl Default for Dns {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            refresh: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            via: "1.1 minginx".to_string(),
+            host: None,
+            compression: Compression::default(),
+            mirror: None,
+            pool: PoolConfig::default(),
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_idle: 32,
+            idle_timeout: Duration::from_secs(30),
+            max_lifetime: Duration::from_secs(300),
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_size: 1024,
+            types: [
+                "text/",
+                "application/json",
+                "application/javascript",
+                "application/xml",
+                "image/svg+xml",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::INFO,
+            otlp_endpoint: "http://localhost:4317".to_string(),
+            service_name: "minginx".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_bytes: 10 * 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            idle: Duration::from_secs(30),
+        }
+    }
+}
+
+fn level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<LevelFilter, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+impl Config {
+    /// Load `path` if given (defaults otherwise), apply the environment and
+    /// validate. Any error here should stop startup before we bind.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env(std::env::vars())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// Apply `MINGINX_LISTEN`, `MINGINX_UPSTREAMS` (comma separated, plain
+    /// TCP),
+    /// `MINGINX_CONNECT_TIMEOUT`, `MINGINX_READ_TIMEOUT`,
+    /// `MINGINX_IDLE_TIMEOUT`, `MINGINX_LOG_LEVEL`,
+    /// `MINGINX_OTLP_ENDPOINT`, `MINGINX_ACCESS_LOG` and `MINGINX_ADMIN_LISTEN`
+    /// on top of the file, along with the standard OpenTelemetry
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT`, `OTEL_SERVICE_NAME` and
+    /// `OTEL_TRACES_SAMPLER_ARG`. `MINGINX_OTLP_ENDPOINT` wins over
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT`.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        fn parse<T>(var: &'static str, value: String) -> Result<T, ConfigError>
+        where
+            T: std::str::FromStr,
+            T::Err: std::fmt::Display,
+        {
+            value.parse().map_err(|e: T::Err| ConfigError::Env {
+                var,
+                reason: e.to_string(),
+                value,
+            })
+        }
+
+        // The generic OTEL_* variables first, so ours can override them.
+        let mut vars: Vec<_> = vars.into_iter().collect();
+        vars.sort_by_key(|(var, _)| !var.starts_with("OTEL_"));
+        for (var, value) in vars {
+            match var.as_str() {
+                "MINGINX_LISTEN" => self.listen = parse("MINGINX_LISTEN", value)?,
+                "MINGINX_UPSTREAMS" => {
+                    self.upstreams = value
+                        .split(',')
+                        .map(|s| s.trim().to_string().into())
+                        .collect()
+                }
+                "MINGINX_CONNECT_TIMEOUT" => {
+                    let timeout: humantime::Duration = parse("MINGINX_CONNECT_TIMEOUT", value)?;
+                    self.timeouts.connect = timeout.into();
+                }
+                "MINGINX_READ_TIMEOUT" => {
+                    let timeout: humantime::Duration = parse("MINGINX_READ_TIMEOUT", value)?;
+                    self.timeouts.read = timeout.into();
+                }
+                "MINGINX_IDLE_TIMEOUT" => {
+                    let timeout: humantime::Duration = parse("MINGINX_IDLE_TIMEOUT", value)?;
+                    self.timeouts.idle = timeout.into();
+                }
+                "MINGINX_LOG_LEVEL" => self.log.level = parse("MINGINX_LOG_LEVEL", value)?,
+                "MINGINX_OTLP_ENDPOINT" => self.log.otlp_endpoint = value,
+                "OTEL_EXPORTER_OTLP_ENDPOINT" => self.log.otlp_endpoint = value,
+                "OTEL_SERVICE_NAME" => self.log.service_name = value,
+                "OTEL_TRACES_SAMPLER_ARG" => {
+                    self.log.sample_ratio = parse("OTEL_TRACES_SAMPLER_ARG", value)?
+                }
+                "MINGINX_ACCESS_LOG" => self.access_log.path = Some(value.into()),
+                "MINGINX_ADMIN_LISTEN" => {
+                    self.admin.listen = Some(parse("MINGINX_ADMIN_LISTEN", value)?)
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The top-level `default` listener followed by the `listeners` blocks.
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        let default = ListenerConfig {
+            name: "default".to_string(),
+            listen: self.listen,
+            mode: self.mode,
+            upstreams: self.upstreams.clone(),
+            balance: self.balance,
+            groups: self.groups.clone(),
+            active_group: self.active_group.clone(),
+            virtual_servers: self.virtual_servers.clone(),
+            routes: self.routes.clone(),
+            tls: self.tls.clone(),
+        };
+        std::iter::once(default)
+            .chain(self.listeners.iter().cloned())
+            .collect()
+    }
+
+    /// Check everything at once so a broken file reports all its problems.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        let listeners = self.listeners();
+        for (i, listener) in listeners.iter().enumerate() {
+            let earlier = &listeners[..i];
+            if earlier.iter().any(|l| l.name == listener.name) {
+                errors.push(format!("duplicate listener name {:?}", listener.name));
+            }
+            if earlier.iter().any(|l| l.listen == listener.listen) {
+                errors.push(format!("duplicate listen address {}", listener.listen));
+            }
+            // Keep the default listener's errors unprefixed, as before there
+            // were other listeners.
+            let prefix = match i {
+                0 => String::new(),
+                _ => format!("listener {:?}: ", listener.name),
+            };
+            let mut listener_errors = Vec::new();
+            check_listener(listener, &mut listener_errors);
+            errors.extend(listener_errors.into_iter().map(|e| prefix.clone() + &e));
+        }
+        for (name, timeout) in [
+            ("connect", self.timeouts.connect),
+            ("read", self.timeouts.read),
+            ("idle", self.timeouts.idle),
+        ] {
+            if timeout.is_zero() {
+                errors.push(format!("timeouts.{} must be greater than zero", name));
+            }
+        }
+        if self.limits.max_connections == 0 {
+            errors.push("limits.max_connections must be greater than zero".to_string());
+        }
+        if self.socket.backlog == 0 || self.socket.buffer_size == 0 {
+            errors.push("socket.backlog and socket.buffer_size must be positive".to_string());
+        }
+        if self.socket.keepalive.is_some_and(|k| k.is_zero()) {
+            errors.push("socket.keepalive must be greater than zero".to_string());
+        }
+        if self.rate_limit.enabled
+            && (self.rate_limit.rate <= 0.0
+                || self.rate_limit.burst == 0
+                || self.rate_limit.max_clients == 0)
+        {
+            errors.push(
+                "rate_limit.rate, rate_limit.burst and rate_limit.max_clients must be positive"
+                    .to_string(),
+            );
+        }
+        if self.health.interval.is_zero() || self.health.timeout.is_zero() {
+            errors.push("health.interval and health.timeout must be greater than zero".to_string());
+        }
+        if self.health.rise == 0 || self.health.fall == 0 {
+            errors.push("health.rise and health.fall must be at least 1".to_string());
+        }
+        if let Some(mirror) = &self.http.mirror {
+            if let Err(e) = check_host_port(&mirror.upstream) {
+                errors.push(format!("http.mirror.upstream {:?}: {}", mirror.upstream, e));
+            }
+            if !(mirror.percent > 0.0 && mirror.percent <= 100.0) {
+                errors.push("http.mirror.percent must be in (0, 100]".to_string());
+            }
+        }
+        let pool = &self.http.pool;
+        if pool.enabled
+            && (pool.max_idle == 0 || pool.idle_timeout.is_zero() || pool.max_lifetime.is_zero())
+        {
+            errors.push(
+                "http.pool.max_idle, http.pool.idle_timeout and http.pool.max_lifetime must be positive"
+                    .to_string(),
+            );
+        }
+        if self.dns.enabled && self.dns.refresh.is_zero() {
+            errors.push("dns.refresh must be greater than zero".to_string());
+        }
+        if !self.log.otlp_endpoint.starts_with("http://")
+            && !self.log.otlp_endpoint.starts_with("https://")
+        {
+            errors.push(format!(
+                "log.otlp_endpoint {:?} must be an http(s) URL",
+                self.log.otlp_endpoint
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.log.sample_ratio) {
+            errors.push(format!(
+                "log.sample_ratio must be between 0 and 1, not {}",
+                self.log.sample_ratio
+            ));
+        }
+        if self.udp.listen.is_some() && self.udp.idle.is_zero() {
+            errors.push("udp.idle must be greater than zero".to_string());
+        }
+        if self.udp.listen.is_some() && self.mode == Mode::Socks5 {
+            errors
+                .push("udp forwards to upstreams, which mode = \"socks5\" has none of".to_string());
+        }
+        for user in &self.socks5.users {
+            // RFC 1929 gives each a one-byte length.
+            let fits = |s: &str| (1..=255).contains(&s.len());
+            if !fits(&user.username) || !fits(&user.password) {
+                errors.push(format!(
+                    "socks5 user {:?}: username and password must be 1 to 255 bytes",
+                    user.username
+                ));
+            }
+        }
+        if self.access_log.path.is_some() && self.access_log.max_bytes == 0 {
+            errors.push("access_log.max_bytes must be greater than zero".to_string());
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(errors)),
+        }
+    }
+}
+
+fn check_listener(listener: &ListenerConfig, errors: &mut Vec<String>) {
+    if listener.mode != Mode::Socks5 && listener.groups.is_empty() {
+        check_upstreams("upstreams", &listener.upstreams, errors);
+    }
+    if listener.mode == Mode::Socks5 && !listener.groups.is_empty() {
+        errors.push("groups don't apply to mode = \"socks5\"".to_string());
+    }
+    for (i, group) in listener.groups.iter().enumerate() {
+        if group.name.is_empty() || listener.groups[..i].iter().any(|g| g.name == group.name) {
+            errors.push(format!(
+                "groups[{}]: empty or duplicate name {:?}",
+                i, group.name
+            ));
+        }
+        check_upstreams(
+            &format!("groups[{}].upstreams", i),
+            &group.upstreams,
+            errors,
+        );
+    }
+    if let Some(active) = &listener.active_group {
+        if !listener.groups.iter().any(|g| &g.name == active) {
+            errors.push(format!("active_group {:?} is not one of groups", active));
+        }
+    }
+    if !listener.virtual_servers.is_empty() && matches!(listener.mode, Mode::Tcp | Mode::Socks5) {
+        errors.push("virtual_servers need mode = \"http\" or \"tls\"".to_string());
+    }
+    if listener.mode == Mode::Tls && listener.tls.is_some() {
+        errors.push("tls cannot be terminated in mode = \"tls\" (passthrough)".to_string());
+    }
+    if listener.mode == Mode::Tls {
+        let all = (listener.virtual_servers.iter().flat_map(|s| &s.upstreams))
+            .chain(listener.groups.iter().flat_map(|g| &g.upstreams));
+        for upstream in listener.upstreams.iter().chain(all) {
+            if upstream.tls {
+                errors.push(format!(
+                    "upstream {:?}: tls = true would encrypt passed-through TLS twice",
+                    upstream.addr
+                ));
+            }
+        }
+    }
+    if !listener.routes.is_empty() && listener.mode != Mode::Http {
+        errors.push("routes need mode = \"http\"".to_string());
+    }
+    for (i, rule) in listener.routes.iter().enumerate() {
+        let name = format!("routes[{}]", i);
+        if rule.header.is_some() == rule.cookie.is_some() {
+            errors.push(format!("{} needs exactly one of header and cookie", name));
+        }
+        if rule.equals.is_some() && rule.prefix.is_some() {
+            errors.push(format!("{} takes equals or prefix, not both", name));
+        }
+        if !rule.present && (rule.equals.is_some() || rule.prefix.is_some()) {
+            errors.push(format!("{}: present = false cannot match a value", name));
+        }
+        check_upstreams(&format!("{}.upstreams", name), &rule.upstreams, errors);
+    }
+    for (i, server) in listener.virtual_servers.iter().enumerate() {
+        let name = format!("virtual_servers[{}]", i);
+        if server.hosts.is_empty() {
+            errors.push(format!("{}.hosts must not be empty", name));
+        }
+        for host in &server.hosts {
+            if host.is_empty() || host.trim_start_matches("*.").contains('*') {
+                errors.push(format!("{}: invalid host pattern {:?}", name, host));
+            }
+        }
+        check_upstreams(&format!("{}.upstreams", name), &server.upstreams, errors);
+    }
+}
+
+fn check_upstreams(name: &str, upstreams: &[UpstreamConfig], errors: &mut Vec<String>) {
+    if upstreams.is_empty() {
+        errors.push(format!("{} must not be empty", name));
+    }
+    for upstream in upstreams {
+        if let Err(e) = check_host_port(&upstream.addr) {
+            errors.push(format!("upstream {:?}: {}", upstream.addr, e));
+        }
+        if upstream.weight == 0 {
+            errors.push(format!(
+                "upstream {:?}: weight must be at least 1",
+                upstream.addr
+            ));
+        }
+        if !upstream.tls && (upstream.sni.is_some() || upstream.ca.is_some()) {
+            errors.push(format!(
+                "upstream {:?}: sni and ca require tls = true",
+                upstream.addr
+            ));
+        }
+    }
+}
+
+fn check_host_port(addr: &str) -> Result<(), &'static str> {
+    let (host, port) = addr.rsplit_once(':').ok_or("expected host:port")?;
+    if host.is_empty() {
+        return Err("missing host");
+    }
+    port.parse::<u16>().map_err(|_| "invalid port")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_then_env_then_validate() {
+        let mut config: Config = toml::from_str(
+            r#"
+            listen = "127.0.0.1:9000"
+            mode = "http"
+            balance = "least_conn"
+            upstreams = [
+                { addr = "backend:80", weight = 3 },
+                { addr = "backend:443", tls = true, sni = "api.example.com" },
+            ]
+
+            [[virtual_servers]]
+            hosts = ["api.example.com", "*.api.example.com"]
+            upstreams = ["api:80"]
+
+            [[listeners]]
+            name = "tls"
+            listen = "127.0.0.1:9443"
+            mode = "tls"
+            upstreams = [{ addr = "tls:443", tls = true }]
+
+            [timeouts]
+            connect = "250ms"
+
+            [socket]
+            nodelay = true
+            keepalive = "75s"
+
+            [access]
+            allow = ["10.0.0.0/8", "::1"]
+
+            [health]
+            interval = "2s"
+            rise = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.timeouts.connect, Duration::from_millis(250));
+        assert!(config.socket.nodelay);
+        assert_eq!(config.socket.keepalive, Some(Duration::from_secs(75)));
+        assert_eq!(config.socket.backlog, 1024);
+        assert_eq!(config.log.level, LevelFilter::INFO);
+        assert_eq!(config.mode, Mode::Http);
+        assert_eq!(config.balance, Balance::LeastConn);
+        assert_eq!(config.virtual_servers[0].hosts.len(), 2);
+        assert_eq!(config.virtual_servers[0].balance, Balance::RoundRobin);
+        let listeners = config.listeners();
+        assert_eq!(listeners[0].name, "default");
+        assert_eq!(listeners[0].upstreams.len(), 2);
+        assert_eq!(listeners[1].mode, Mode::Tls);
+        assert_eq!(config.access.allow[1].to_string(), "::1/128");
+        assert_eq!(config.health.interval, Duration::from_secs(2));
+        assert_eq!(config.health.fall, 3);
+        assert!(!config.upstreams[0].tls);
+        assert_eq!(config.upstreams[0].weight, 3);
+        assert_eq!(config.upstreams[1].weight, 1);
+        assert!(config.upstreams[1].tls);
+        assert_eq!(config.upstreams[1].sni.as_deref(), Some("api.example.com"));
+
+        let env = [
+            ("MINGINX_UPSTREAMS", "a:1, nope"),
+            ("MINGINX_LOG_LEVEL", "debug"),
+            ("MINGINX_IDLE_TIMEOUT", "1m"),
+            ("MINGINX_OTLP_ENDPOINT", "http://minginx-collector:4317"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4317"),
+            ("OTEL_SERVICE_NAME", "edge-proxy"),
+            ("OTEL_TRACES_SAMPLER_ARG", "0.25"),
+        ];
+        config
+            .apply_env(env.map(|(k, v)| (k.to_string(), v.to_string())))
+            .unwrap();
+        assert_eq!(config.log.level, LevelFilter::DEBUG);
+        assert_eq!(config.log.otlp_endpoint, "http://minginx-collector:4317");
+        assert_eq!(config.log.service_name, "edge-proxy");
+        assert_eq!(config.log.sample_ratio, 0.25);
+        assert_eq!(config.timeouts.idle, Duration::from_secs(60));
+        let addrs: Vec<_> = config.upstreams.iter().map(|u| u.addr.as_str()).collect();
+        assert_eq!(addrs, ["a:1", "nope"]);
+        // The bad upstream, rise = 0, and TLS inside TLS passthrough.
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(e)) if e.len() == 3));
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::upstream::Upstream;
+
+/// Resolve every host name server in `upstream` now and then every
+/// `refresh` for as long as the proxy runs. Literal addresses are skipped.
+pub fn spawn(upstream: Arc<Upstream>, refresh: Duration) {
+    if !upstream.servers().iter().any(|s| s.is_named()) {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(refresh);
+        loop {
+            interval.tick().await;
+            for server in upstream.servers().iter().filter(|s| s.is_named()) {
+                match tokio::net::lookup_host(&server.addr).await {
+                    Ok(addrs) => {
+                        let addrs: Vec<_> = addrs.collect();
+                        if addrs.is_empty() {
+                            warn!(upstream = %server.addr, "no addresses, keeping the old ones");
+                        } else if server.set_resolved(addrs) {
+                            info!(upstream = %server.addr, addrs = ?server.resolved(), "resolved");
+                        }
+                    }
+                    Err(e) => {
+                        warn!(upstream = %server.addr, "lookup failed, keeping the old addresses: {}", e)
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Balance;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn resolves_host_names_only() {
+        let servers = vec![
+            "localhost:80".to_string().into(),
+            "127.0.0.2:80".to_string().into(),
+        ];
+        let upstream = Arc::new(Upstream::new(servers, Balance::RoundRobin).unwrap());
+        let literal = upstream.servers()[1].resolved();
+        assert_eq!(literal, ["127.0.0.2:80".parse().unwrap()]);
+        assert!(upstream.servers()[0].resolved().is_empty());
+
+        spawn(Arc::clone(&upstream), Duration::from_secs(60));
+        for _ in 0..100 {
+            if !upstream.servers()[0].resolved().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let named = upstream.servers()[0].resolved();
+        assert!(named.iter().all(|a| a.ip().is_loopback() && a.port() == 80));
+        assert!(!named.is_empty());
+        assert_eq!(upstream.servers()[1].resolved(), literal);
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use ecosystem::supervisor::{RestartPolicy, Supervisor};
+use tracing::{info, warn};
+
+use crate::config::HealthCheck;
+use crate::upstream::Upstream;
+
+/// Consecutive-result counter for one server: it leaves rotation after
+/// `fall` failures in a row and comes back after `rise` successes.
+#[derive(Debug, Default)]
+struct Tracker {
+    healthy: bool,
+    successes: u32,
+    failures: u32,
+}
+
+impl Tracker {
+    fn new(healthy: bool) -> Self {
+        Self {
+            healthy,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the new state when this result flips it.
+    fn observe(&mut self, ok: bool, check: &HealthCheck) -> Option<bool> {
+        if ok {
+            self.failures = 0;
+            self.successes += 1;
+            if !self.healthy && self.successes >= check.rise {
+                self.healthy = true;
+                return Some(true);
+            }
+        } else {
+            self.successes = 0;
+            self.failures += 1;
+            if self.healthy && self.failures >= check.fall {
+                self.healthy = false;
+                return Some(false);
+            }
+        }
+        None
+    }
+}
+
+/// Probe every upstream with a TCP connect each `interval` for as long as
+/// the proxy runs, under `supervisor` so a checker that dies is restarted.
+pub fn spawn(
+    supervisor: &mut Supervisor,
+    name: String,
+    upstream: Arc<Upstream>,
+    check: HealthCheck,
+) {
+    supervisor.spawn(name, RestartPolicy::default(), move || {
+        run(Arc::clone(&upstream), check.clone())
+    });
+}
+
+/// A restarted checker starts counting afresh from the servers' current
+/// state.
+async fn run(upstream: Arc<Upstream>, check: HealthCheck) -> anyhow::Result<()> {
+    let mut trackers: Vec<_> = upstream
+        .servers()
+        .iter()
+        .map(|server| Tracker::new(server.is_healthy()))
+        .collect();
+    let mut interval = tokio::time::interval(check.interval);
+    loop {
+        interval.tick().await;
+        for (server, tracker) in upstream.servers().iter().zip(&mut trackers) {
+            let connect = server.connect();
+            let ok = matches!(
+                tokio::time::timeout(check.timeout, connect).await,
+                Ok(Ok(_))
+            );
+            match tracker.observe(ok, &check) {
+                Some(true) => info!(upstream = %server.addr, "upstream is healthy again"),
+                Some(false) => warn!(upstream = %server.addr, "upstream marked unhealthy"),
+                None => continue,
+            }
+            server.set_healthy(tracker.healthy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker_needs_consecutive_results_to_flip() {
+        let check = HealthCheck {
+            fall: 2,
+            rise: 2,
+            ..Default::default()
+        };
+        let mut tracker = Tracker::new(true);
+        assert_eq!(tracker.observe(false, &check), None);
+        assert_eq!(tracker.observe(true, &check), None);
+        assert_eq!(tracker.observe(false, &check), None);
+        assert_eq!(tracker.observe(false, &check), Some(false));
+        assert_eq!(tracker.observe(true, &check), None);
+        assert_eq!(tracker.observe(true, &check), Some(true));
+    }
+}
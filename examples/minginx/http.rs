@@ -0,0 +1,672 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, bail};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::compress::{self, Encoding};
+use crate::config::HttpConfig;
+use crate::transfer::Transfer;
+
+/// Request heads larger than this are refused rather than buffered.
+const MAX_HEAD: usize = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+
+/// A client's request head as it will be sent upstream.
+#[derive(Debug)]
+pub struct Request {
+    /// The rewritten head, followed by whatever body bytes arrived in the
+    /// same reads. The rest of the body is streamed untouched by the caller.
+    pub head: Vec<u8>,
+    /// The `Host` the client asked for, before any rewrite.
+    pub host: Option<String>,
+    /// The client's headers as sent, for routing rules.
+    pub headers: Vec<(String, String)>,
+    /// A WebSocket handshake; once the upstream answers `101`, the rest of
+    /// the connection is opaque frames, which the byte copy relays as is.
+    pub websocket: bool,
+    /// How to compress the response, if compression is on and the client
+    /// accepts it.
+    pub encoding: Option<Encoding>,
+    /// Set when pooling is on and the request's end is known, so the head
+    /// asks the upstream to keep the connection open afterwards.
+    pub keep_alive: Option<KeepAlive>,
+}
+
+/// What it takes to tell where a kept-alive exchange ends.
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAlive {
+    /// Body bytes still to come from the client after `head`.
+    pub remaining: u64,
+    /// A `HEAD` request, whose response has no body whatever it says.
+    pub head_request: bool,
+}
+
+/// An upstream's response head.
+#[derive(Debug)]
+pub struct Response {
+    /// The head to send the client: as received for informational
+    /// responses, otherwise with `Connection: close`.
+    pub head: Vec<u8>,
+    /// A `1xx` response, to be followed by another.
+    pub informational: bool,
+    pub body: Body,
+    /// The upstream will keep the connection open after this response.
+    pub keep_alive: bool,
+    /// Bytes read past the head.
+    pub rest: Vec<u8>,
+}
+
+/// How a response body is delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Body {
+    Empty,
+    Length(u64),
+    Chunked,
+    /// Only the upstream closing the connection ends it.
+    Close,
+}
+
+/// What the upstream is told about the client.
+#[derive(Debug)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// The client spoke TLS to us.
+    pub tls: bool,
+    /// Subject of the client's verified certificate, under mutual TLS.
+    pub cert_subject: Option<String>,
+}
+
+impl From<SocketAddr> for Peer {
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            tls: false,
+            cert_subject: None,
+        }
+    }
+}
+
+/// Read the client's request head and rewrite it for the upstream.
+pub async fn read_request<R>(
+    client: &mut R,
+    peer: &Peer,
+    config: &HttpConfig,
+) -> anyhow::Result<Request>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buf = Vec::with_capacity(4096);
+    loop {
+        if client.read_buf(&mut buf).await? == 0 {
+            bail!("connection closed before the request head was complete");
+        }
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut req = httparse::Request::new(&mut headers);
+        if let httparse::Status::Complete(len) = req.parse(&buf)? {
+            let host = req
+                .headers
+                .iter()
+                .find(|h| h.name.eq_ignore_ascii_case("host"))
+                .map(|h| String::from_utf8_lossy(h.value).into_owned());
+            let headers = req
+                .headers
+                .iter()
+                .map(|h| {
+                    (
+                        h.name.to_string(),
+                        String::from_utf8_lossy(h.value).into_owned(),
+                    )
+                })
+                .collect();
+            let websocket = is_websocket(&req);
+            let encoding = req
+                .headers
+                .iter()
+                .find(|h| h.name.eq_ignore_ascii_case("accept-encoding"))
+                .and_then(|h| std::str::from_utf8(h.value).ok())
+                .and_then(compress::negotiate)
+                .filter(|_| config.compression.enabled && !websocket && req.method != Some("HEAD"));
+            let keep_alive = body_length(&req)
+                .and_then(|length| length.checked_sub((buf.len() - len) as u64))
+                .filter(|_| config.pool.enabled && !websocket && encoding.is_none())
+                .map(|remaining| KeepAlive {
+                    remaining,
+                    head_request: req.method == Some("HEAD"),
+                });
+            let mut head = rewrite(&req, peer, config, websocket, keep_alive.is_some())?;
+            head.extend_from_slice(&buf[len..]);
+            return Ok(Request {
+                head,
+                host,
+                headers,
+                websocket,
+                encoding,
+                keep_alive,
+            });
+        }
+        if buf.len() >= MAX_HEAD {
+            bail!("request head exceeds {} bytes", MAX_HEAD);
+        }
+    }
+}
+
+/// The request body's length, given by `Content-Length` or zero without
+/// one; `None` for chunked bodies and `Expect`, whose end takes more than
+/// counting bytes.
+fn body_length(req: &httparse::Request) -> Option<u64> {
+    let mut length = 0;
+    for header in req.headers.iter() {
+        if header.name.eq_ignore_ascii_case("transfer-encoding")
+            || header.name.eq_ignore_ascii_case("expect")
+        {
+            return None;
+        }
+        if header.name.eq_ignore_ascii_case("content-length") {
+            length = std::str::from_utf8(header.value)
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+        }
+    }
+    Some(length)
+}
+
+/// `Upgrade: websocket` together with a `Connection` header listing
+/// `upgrade`, as RFC 6455 requires.
+fn is_websocket(req: &httparse::Request) -> bool {
+    let has = |name: &str, token: &str| {
+        req.headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(name))
+            .flat_map(|h| h.value.split(|&b| b == b','))
+            .any(|value| value.trim_ascii().eq_ignore_ascii_case(token.as_bytes()))
+    };
+    has("upgrade", "websocket") && has("connection", "upgrade")
+}
+
+/// Rebuild the head with `X-Forwarded-For`, `X-Forwarded-Proto` and `Via`
+/// appended to, `Host` replaced if configured, `X-Client-Cert-Subject` set
+/// only from a verified client certificate, and `Connection: close` so the
+/// upstream ends the exchange after one response; the client then reconnects
+/// and its next request gets rewritten as well. With `keep_alive` the
+/// upstream is asked to keep the connection open for a later request instead.
+/// A WebSocket handshake keeps `Connection: Upgrade`; any other `Upgrade` is
+/// dropped. Hop-by-hop headers (RFC 9110 section 7.6.1), the fixed ones and
+/// those the client's `Connection` header names, aren't passed on.
+fn rewrite(
+    req: &httparse::Request,
+    peer: &Peer,
+    config: &HttpConfig,
+    websocket: bool,
+    keep_alive: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let method = req.method.ok_or_else(|| anyhow!("missing method"))?;
+    let path = req.path.ok_or_else(|| anyhow!("missing path"))?;
+    let version = req.version.ok_or_else(|| anyhow!("missing version"))?;
+
+    let options = connection_options(req, websocket);
+    let mut forwarded_for = Vec::new();
+    let mut via = Vec::new();
+    let mut out = format!("{} {} HTTP/1.{}\r\n", method, path, version).into_bytes();
+    for header in req.headers.iter() {
+        let name = header.name.to_ascii_lowercase();
+        if options.contains(&name) {
+            continue;
+        }
+        match name.as_str() {
+            "x-forwarded-for" => forwarded_for.push(String::from_utf8_lossy(header.value)),
+            "via" => via.push(String::from_utf8_lossy(header.value)),
+            "host" if config.host.is_some() => continue,
+            "x-forwarded-proto" | "connection" | "keep-alive" | "proxy-connection" => continue,
+            "te" | "trailer" | "proxy-authorization" => continue,
+            "x-client-cert-subject" => continue,
+            // We decide about compression; the upstream should answer plain.
+            "accept-encoding" if config.compression.enabled && !websocket => continue,
+            "upgrade" if !websocket => continue,
+            _ => {
+                out.extend_from_slice(header.name.as_bytes());
+                out.extend_from_slice(b": ");
+                out.extend_from_slice(header.value);
+                out.extend_from_slice(b"\r\n");
+            }
+        }
+    }
+
+    let peer_ip = peer.addr.ip().to_string();
+    forwarded_for.push(peer_ip.as_str().into());
+    via.push(config.via.as_str().into());
+    let mut push = |name: &str, value: &str| {
+        out.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    };
+    if let Some(host) = &config.host {
+        push("Host", host);
+    }
+    push("X-Forwarded-For", &forwarded_for.join(", "));
+    push("X-Forwarded-Proto", if peer.tls { "https" } else { "http" });
+    if let Some(subject) = &peer.cert_subject {
+        push("X-Client-Cert-Subject", subject);
+    }
+    push("Via", &via.join(", "));
+    let connection = match (websocket, keep_alive) {
+        (true, _) => "Upgrade",
+        (false, true) => "keep-alive",
+        (false, false) => "close",
+    };
+    push("Connection", connection);
+    out.extend_from_slice(b"\r\n");
+    Ok(out)
+}
+
+/// The header names listed in the request's `Connection` headers, lowercase.
+/// Framing headers are left out whatever the client says, so the upstream
+/// can't be made to read the body differently from us, and so is `Upgrade`
+/// in a WebSocket handshake.
+fn connection_options(req: &httparse::Request, websocket: bool) -> Vec<String> {
+    req.headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case("connection"))
+        .flat_map(|h| h.value.split(|&b| b == b','))
+        .map(|token| String::from_utf8_lossy(token.trim_ascii()).to_ascii_lowercase())
+        .filter(|token| match token.as_str() {
+            "" | "host" | "content-length" | "transfer-encoding" => false,
+            "upgrade" => !websocket,
+            _ => true,
+        })
+        .collect()
+}
+
+/// Read the upstream's response head to a kept-alive request, starting
+/// with `buf`, the bytes already read past any previous response.
+pub async fn read_response<R>(
+    upstream: &mut R,
+    mut buf: Vec<u8>,
+    head_request: bool,
+) -> anyhow::Result<Response>
+where
+    R: AsyncRead + Unpin,
+{
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut res = httparse::Response::new(&mut headers);
+        if let httparse::Status::Complete(len) = res.parse(&buf)? {
+            let code = res.code.ok_or_else(|| anyhow!("missing status"))?;
+            let rest = buf[len..].to_vec();
+            if (100..200).contains(&code) && code != 101 {
+                return Ok(Response {
+                    head: buf[..len].to_vec(),
+                    informational: true,
+                    body: Body::Empty,
+                    keep_alive: true,
+                    rest,
+                });
+            }
+            let body = response_body(&res, code, head_request);
+            let has = |token: &str| {
+                res.headers
+                    .iter()
+                    .filter(|h| h.name.eq_ignore_ascii_case("connection"))
+                    .flat_map(|h| h.value.split(|&b| b == b','))
+                    .any(|value| value.trim_ascii().eq_ignore_ascii_case(token.as_bytes()))
+            };
+            let keep_alive = body != Body::Close
+                && match res.version {
+                    Some(1) => !has("close"),
+                    _ => has("keep-alive"),
+                };
+            return Ok(Response {
+                head: rewrite_response(&res, code)?,
+                informational: false,
+                body,
+                keep_alive,
+                rest,
+            });
+        }
+        if buf.len() >= MAX_HEAD {
+            bail!("response head exceeds {} bytes", MAX_HEAD);
+        }
+        if upstream.read_buf(&mut buf).await? == 0 {
+            bail!("upstream closed before the response head was complete");
+        }
+    }
+}
+
+/// RFC 9112 section 6.3, minus the request side.
+fn response_body(res: &httparse::Response, code: u16, head_request: bool) -> Body {
+    if head_request || code == 204 || code == 304 {
+        return Body::Empty;
+    }
+    if code == 101 {
+        return Body::Close;
+    }
+    let header = |name: &str| {
+        res.headers
+            .iter()
+            .rev()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| String::from_utf8_lossy(h.value).trim().to_ascii_lowercase())
+    };
+    if let Some(coding) = header("transfer-encoding") {
+        return match coding.rsplit(',').next().map(str::trim) {
+            Some("chunked") => Body::Chunked,
+            _ => Body::Close,
+        };
+    }
+    match header("content-length").and_then(|length| length.parse().ok()) {
+        Some(length) => Body::Length(length),
+        None => Body::Close,
+    }
+}
+
+/// The response head with `Connection: close`, since the client side is
+/// still one request per connection.
+fn rewrite_response(res: &httparse::Response, code: u16) -> anyhow::Result<Vec<u8>> {
+    let version = res.version.ok_or_else(|| anyhow!("missing version"))?;
+    let reason = res.reason.unwrap_or_default();
+    let mut out = format!("HTTP/1.{} {} {}\r\n", version, code, reason).into_bytes();
+    for header in res.headers.iter() {
+        let name = header.name.to_ascii_lowercase();
+        if matches!(
+            name.as_str(),
+            "connection" | "keep-alive" | "proxy-connection"
+        ) {
+            continue;
+        }
+        out.extend_from_slice(header.name.as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(header.value);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"Connection: close\r\n\r\n");
+    Ok(out)
+}
+
+/// Relay a response body delimited by `body` to the client, starting with
+/// `rest`. Returns whether it ended exactly where `body` said, with nothing
+/// after it, so the upstream connection is ready for another request.
+pub async fn relay_body<R, W>(
+    upstream: &mut R,
+    client: &mut W,
+    rest: &[u8],
+    body: Body,
+    counter: &AtomicU64,
+    transfer: &Transfer,
+) -> io::Result<bool>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut left = match body {
+        Body::Length(length) => length,
+        _ => 0,
+    };
+    let mut chunked = Chunked::default();
+    let mut buf = vec![0; transfer.buffer_size];
+    let mut pending = rest;
+    loop {
+        let (take, done) = match body {
+            Body::Empty => (0, true),
+            Body::Length(_) => {
+                let take = left.min(pending.len() as u64);
+                left -= take;
+                (take as usize, left == 0)
+            }
+            Body::Chunked => match chunked.scan(pending)? {
+                Some(end) => (end, true),
+                None => (pending.len(), false),
+            },
+            Body::Close => (pending.len(), false),
+        };
+        client.write_all(&pending[..take]).await?;
+        counter.fetch_add(take as u64, Ordering::Relaxed);
+        if done {
+            return Ok(take == pending.len());
+        }
+        let n = upstream.read(&mut buf).await?;
+        if n == 0 {
+            return match body {
+                Body::Close => Ok(false),
+                _ => Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+        }
+        transfer.touch();
+        pending = &buf[..n];
+    }
+}
+
+/// Where a chunked body is up to, fed one read at a time.
+#[derive(Debug, Default, Clone, Copy)]
+enum Chunked {
+    /// Reading a chunk size line; the flag is whether a digit was seen.
+    #[default]
+    Start,
+    Size(u64),
+    /// Past the size, skipping extensions to the end of the line.
+    Extension(u64),
+    Data(u64),
+    /// The line break after chunk data.
+    DataEnd,
+    /// In the trailer section; the flag is whether at the start of a line.
+    Trailer(bool),
+}
+
+impl Chunked {
+    /// Scan `data`, returning the offset just past the end of the body if it
+    /// ends within `data`.
+    fn scan(&mut self, data: &[u8]) -> io::Result<Option<usize>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed chunked body");
+        let mut i = 0;
+        while i < data.len() {
+            let byte = data[i];
+            *self = match *self {
+                Chunked::Start | Chunked::Size(_) => {
+                    let size = match *self {
+                        Chunked::Size(size) => Some(size),
+                        _ => None,
+                    };
+                    match (byte as char).to_digit(16) {
+                        Some(digit) => {
+                            let size = size.unwrap_or(0);
+                            let size = size.checked_mul(16).ok_or_else(invalid)?;
+                            Chunked::Size(size + u64::from(digit))
+                        }
+                        None => match (size, byte) {
+                            (Some(size), b'\n') => Chunked::line_end(size),
+                            (Some(size), b'\r' | b';' | b' ' | b'\t') => Chunked::Extension(size),
+                            _ => return Err(invalid()),
+                        },
+                    }
+                }
+                Chunked::Extension(size) if byte == b'\n' => Chunked::line_end(size),
+                Chunked::Extension(size) => Chunked::Extension(size),
+                Chunked::Data(size) => {
+                    let take = size.min((data.len() - i) as u64);
+                    i += take as usize;
+                    *self = match size - take {
+                        0 => Chunked::DataEnd,
+                        left => Chunked::Data(left),
+                    };
+                    continue;
+                }
+                Chunked::DataEnd => match byte {
+                    b'\r' => Chunked::DataEnd,
+                    b'\n' => Chunked::Start,
+                    _ => return Err(invalid()),
+                },
+                Chunked::Trailer(true) if byte == b'\n' => return Ok(Some(i + 1)),
+                Chunked::Trailer(start) => {
+                    Chunked::Trailer(byte == b'\n' || (start && byte == b'\r'))
+                }
+            };
+            i += 1;
+        }
+        Ok(None)
+    }
+
+    fn line_end(size: u64) -> Self {
+        match size {
+            0 => Chunked::Trailer(true),
+            size => Chunked::Data(size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_appends_forwarding_headers() {
+        let raw = b"GET /a HTTP/1.1\r\nHost: front\r\nX-Forwarded-For: 10.0.0.1\r\nConnection: keep-alive\r\n\r\n";
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut req = httparse::Request::new(&mut headers);
+        req.parse(raw).unwrap();
+
+        let config = HttpConfig {
+            host: Some("backend".to_string()),
+            ..Default::default()
+        };
+        let peer = "192.168.1.2:5000".parse::<SocketAddr>().unwrap().into();
+        let out = rewrite(&req, &peer, &config, false, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "GET /a HTTP/1.1\r\n\
+             Host: backend\r\n\
+             X-Forwarded-For: 10.0.0.1, 192.168.1.2\r\n\
+             X-Forwarded-Proto: http\r\n\
+             Via: 1.1 minginx\r\n\
+             Connection: close\r\n\r\n"
+        );
+
+        // hop-by-hop ones, by name and as listed in Connection, are dropped;
+        // framing headers stay whatever Connection says
+        let raw = b"POST /a HTTP/1.1\r\nHost: front\r\nConnection: X-Secret, content-length\r\n\
+            X-Secret: 1\r\nTE: trailers\r\nTrailer: X-Sum\r\nProxy-Authorization: Basic eA==\r\n\
+            Content-Length: 2\r\nX-Kept: 1\r\n\r\n";
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut req = httparse::Request::new(&mut headers);
+        req.parse(raw).unwrap();
+        let out = rewrite(&req, &peer, &config, false, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        for dropped in ["X-Secret", "TE:", "Trailer", "Proxy-Authorization"] {
+            assert!(!out.contains(dropped), "{:?} in {:?}", dropped, out);
+        }
+        assert!(out.contains("Content-Length: 2\r\n"));
+        assert!(out.contains("X-Kept: 1\r\n"));
+    }
+
+    #[test]
+    fn client_cert_subject_comes_only_from_tls() {
+        let raw = b"GET / HTTP/1.1\r\nHost: app\r\nX-Client-Cert-Subject: CN=forged\r\n\r\n";
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut req = httparse::Request::new(&mut headers);
+        req.parse(raw).unwrap();
+
+        let plain = "10.0.0.9:4000".parse::<SocketAddr>().unwrap().into();
+        let out = rewrite(&req, &plain, &HttpConfig::default(), false, false).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("forged"));
+
+        let mutual = Peer {
+            addr: "10.0.0.9:4000".parse().unwrap(),
+            tls: true,
+            cert_subject: Some("CN=alice".to_string()),
+        };
+        let out = rewrite(&req, &mutual, &HttpConfig::default(), false, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("X-Forwarded-Proto: https\r\n"));
+        assert!(out.contains("X-Client-Cert-Subject: CN=alice\r\n"));
+        assert!(!out.contains("forged"));
+    }
+
+    #[test]
+    fn websocket_handshake_keeps_upgrade() {
+        let raw = b"GET /ws HTTP/1.1\r\nHost: app\r\nConnection: keep-alive, Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Key: abc\r\n\r\n";
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut req = httparse::Request::new(&mut headers);
+        req.parse(raw).unwrap();
+        assert!(is_websocket(&req));
+
+        let peer = "10.0.0.9:4000".parse::<SocketAddr>().unwrap().into();
+        let out = rewrite(&req, &peer, &HttpConfig::default(), true, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Upgrade: websocket\r\n"));
+        assert!(out.contains("Sec-WebSocket-Key: abc\r\n"));
+        assert!(out.ends_with("Connection: Upgrade\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn pooling_keeps_framed_requests_alive() {
+        let config = HttpConfig {
+            pool: crate::config::PoolConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let peer = "10.0.0.9:4000".parse::<SocketAddr>().unwrap().into();
+
+        let mut raw: &[u8] = b"POST /a HTTP/1.1\r\nHost: app\r\nContent-Length: 5\r\n\r\nab";
+        let request = read_request(&mut raw, &peer, &config).await.unwrap();
+        assert_eq!(request.keep_alive.map(|k| k.remaining), Some(3));
+        let head = String::from_utf8(request.head).unwrap();
+        assert!(head.ends_with("Connection: keep-alive\r\n\r\nab"));
+
+        let mut raw: &[u8] = b"POST /a HTTP/1.1\r\nHost: app\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let request = read_request(&mut raw, &peer, &config).await.unwrap();
+        assert!(request.keep_alive.is_none());
+        assert!(String::from_utf8(request.head)
+            .unwrap()
+            .contains("Connection: close\r\n"));
+    }
+
+    #[tokio::test]
+    async fn relays_chunked_response_up_to_its_end() {
+        let mut upstream: &[u8] =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: keep-alive\r\n\r\n\
+            5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\nTrailer: x\r\n\r\n";
+        let response = read_response(&mut upstream, Vec::new(), false)
+            .await
+            .unwrap();
+        assert_eq!(response.body, Body::Chunked);
+        assert!(response.keep_alive);
+        let head = String::from_utf8(response.head).unwrap();
+        assert!(head.ends_with("Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n"));
+
+        let transfer = Transfer::new();
+        let mut client = Vec::new();
+        let clean = relay_body(
+            &mut upstream,
+            &mut client,
+            &response.rest,
+            response.body,
+            &transfer.bytes_out,
+            &transfer,
+        )
+        .await
+        .unwrap();
+        assert!(clean);
+        assert!(client.ends_with(b" world\r\n0\r\nTrailer: x\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn response_framing() {
+        let body = |raw: &'static [u8], head_request: bool| async move {
+            let mut upstream = raw;
+            read_response(&mut upstream, Vec::new(), head_request)
+                .await
+                .map(|r| (r.body, r.keep_alive))
+                .unwrap()
+        };
+        let length = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc";
+        assert_eq!(body(length, false).await, (Body::Length(3), true));
+        assert_eq!(body(length, true).await, (Body::Empty, true));
+        let unframed = b"HTTP/1.1 200 OK\r\n\r\nabc";
+        assert_eq!(body(unframed, false).await, (Body::Close, false));
+        let closing = b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n";
+        assert_eq!(body(closing, false).await, (Body::Empty, false));
+        let old = b"HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n";
+        assert_eq!(body(old, false).await, (Body::Length(0), false));
+    }
+}
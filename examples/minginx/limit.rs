@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::info;
+
+use crate::config::{Limits, Overflow};
+
+/// Caps concurrently proxied connections. Waiting for a slot happens in the
+/// accept loop, so while we are saturated new clients pile up in the
+/// listen backlog instead of in memory.
+#[derive(Debug)]
+pub struct Limiter {
+    slots: Arc<Semaphore>,
+    max_connections: usize,
+    overflow: Overflow,
+    queue_timeout: Duration,
+}
+
+impl Limiter {
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(limits.max_connections)),
+            max_connections: limits.max_connections,
+            overflow: limits.overflow,
+            queue_timeout: limits.queue_timeout,
+        }
+    }
+
+    /// Reserve a slot for a freshly accepted connection, or `None` if it
+    /// should be turned away.
+    pub async fn admit(&self) -> Option<OwnedSemaphorePermit> {
+        let slots = Arc::clone(&self.slots);
+        match self.overflow {
+            Overflow::Reject => slots.try_acquire_owned().ok(),
+            Overflow::Queue => tokio::time::timeout(self.queue_timeout, slots.acquire_owned())
+                .await
+                .ok()?
+                .ok(),
+        }
+    }
+
+    /// Connections currently holding a slot.
+    pub fn active(&self) -> usize {
+        self.max_connections - self.slots.available_permits()
+    }
+
+    pub fn report_utilization(&self) {
+        let active = self.active();
+        let utilization = active as f64 / self.max_connections as f64;
+        info!(
+            active,
+            max_connections = self.max_connections,
+            utilization,
+            "connection slots"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reject_when_full_and_reopen_on_release() {
+        let limiter = Limiter::new(&Limits {
+            max_connections: 1,
+            overflow: Overflow::Reject,
+            queue_timeout: Duration::ZERO,
+        });
+        let permit = limiter.admit().await.unwrap();
+        assert_eq!(limiter.active(), 1);
+        assert!(limiter.admit().await.is_none());
+        drop(permit);
+        assert!(limiter.admit().await.is_some());
+    }
+}
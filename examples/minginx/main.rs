@@ -0,0 +1,833 @@
+mod access;
+mod acl;
+mod admin;
+mod compress;
+mod config;
+mod dns;
+mod health;
+mod http;
+mod limit;
+mod metrics;
+mod mirror;
+mod pool;
+mod proxy_protocol;
+mod rate_limit;
+mod server_tls;
+mod sni;
+mod socket;
+mod socks5;
+#[cfg(target_os = "linux")]
+mod splice;
+#[cfg(test)]
+mod tests;
+mod transfer;
+mod udp;
+mod upstream;
+mod vhost;
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use clap::Parser;
+use ecosystem::supervisor::Supervisor;
+use ecosystem::units::{Bytes, Millis};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::{RandomIdGenerator, Sampler, Tracer};
+use opentelemetry_sdk::{trace, Resource};
+use socket2::SockRef;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_rustls::server::TlsStream;
+use tokio_util::either::Either;
+use tracing::{debug, error, field, info, info_span, instrument, warn, Instrument, Span};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+use access::{AccessLog, AccessRecord};
+use acl::Acl;
+use compress::Encoding;
+use config::{Compression, Config, LogConfig, Mode};
+use http::{KeepAlive, Peer};
+use limit::Limiter;
+use metrics::Metrics;
+use mirror::{Mirror, Tap, Tee};
+use pool::{Pool, Pooled};
+use rate_limit::RateLimiter;
+use server_tls::ServerTls;
+use socks5::Reply;
+use transfer::{copy, copy_some, Transfer};
+use vhost::Router;
+
+const TOO_MANY_REQUESTS: &[u8] =
+    b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// TOML configuration file; built-in defaults are used without one.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+
+    let console = fmt::Layer::new().pretty().with_filter(config.log.level);
+
+    let tracer = init_tracer(&config.log)?;
+    let open_telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(open_telemetry)
+        .with(ecosystem::console::layer())
+        .init();
+
+    let config = Arc::new(config);
+    let mut listeners = Vec::new();
+    for listener in config.listeners() {
+        let router = Router::new(&listener)?;
+        let servers: Vec<_> = router.default().servers().iter().map(|s| &s.addr).collect();
+        info!(
+            "{}: listen {} ({:?}), upstreams {:?}",
+            listener.name, listener.listen, listener.mode, servers
+        );
+        listeners.push((listener, router));
+    }
+
+    // lives as long as `main`; dropping it would stop the health checks, and
+    // one given up on stops minginx too, see below
+    let mut supervisor = Supervisor::new();
+    for (listener, router) in &listeners {
+        if config.health.enabled {
+            for (i, group) in router.groups().into_iter().enumerate() {
+                let name = format!("{} health check #{}", listener.name, i);
+                health::spawn(
+                    &mut supervisor,
+                    name,
+                    Arc::clone(group),
+                    config.health.clone(),
+                );
+            }
+        }
+        if config.dns.enabled {
+            for group in router.groups() {
+                dns::spawn(Arc::clone(group), config.dns.refresh);
+            }
+        }
+    }
+
+    let access_log = match &config.access_log.path {
+        Some(path) => Some(
+            AccessLog::open(
+                path.clone(),
+                config.access_log.max_bytes,
+                config.access_log.keep,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
+    let registry = metrics::registry()?;
+    if let Some(listen) = config.admin.listen {
+        let registry = registry.clone();
+        let groups = listeners
+            .iter()
+            .map(|(listener, router)| {
+                let groups = router.groups().into_iter().cloned().collect();
+                (listener.name.clone(), groups)
+            })
+            .collect();
+        let switches = listeners
+            .iter()
+            .map(|(listener, router)| (listener.name.clone(), Arc::clone(router.switch())))
+            .collect();
+        tokio::spawn(async move {
+            if let Err(e) = admin::serve(listen, registry, groups, switches).await {
+                warn!("admin listener on {} failed: {:#}", listen, e);
+            }
+        });
+    }
+
+    if let Some(listen) = config.udp.listen {
+        let upstream = Arc::clone(listeners[0].1.default());
+        let idle = config.udp.idle;
+        tokio::spawn(async move {
+            if let Err(e) = udp::serve(listen, upstream, idle).await {
+                warn!("udp listener on {} failed: {:#}", listen, e);
+            }
+        });
+    }
+
+    let acl = Arc::new(RwLock::new(Acl::new(&config.access)));
+    let rate_limiter = config
+        .rate_limit
+        .enabled
+        .then(|| Arc::new(RateLimiter::new(&config.rate_limit)));
+    let pool = config
+        .http
+        .pool
+        .enabled
+        .then(|| Arc::new(Pool::new(&config.http.pool)));
+    if let Some(pool) = &pool {
+        pool::spawn(Arc::clone(pool));
+    }
+    let mut accepts = Vec::new();
+    for (listener, router) in listeners {
+        let socket = socket::listen(listener.listen, &config.socket)?;
+        let proxy = Arc::new(Proxy {
+            tls: listener.tls.as_ref().map(ServerTls::new).transpose()?,
+            metrics: Metrics::new(&registry, &listener.name)?,
+            name: listener.name,
+            mode: listener.mode,
+            config: Arc::clone(&config),
+            acl: Arc::clone(&acl),
+            router,
+            limiter: Limiter::new(&config.limits),
+            rate_limiter: rate_limiter.clone(),
+            mirror: config
+                .http
+                .mirror
+                .as_ref()
+                .map(|mirror| Mirror::new(mirror, &config.timeouts)),
+            pool: pool.clone(),
+            access_log: access_log.clone(),
+        });
+        accepts.push(proxy.accept(socket));
+    }
+
+    if let Some(path) = args.config {
+        tokio::spawn(reload_on_hangup(path, acl));
+    }
+
+    // Without its health check a group would keep sending clients to servers
+    // that may be down, with nobody told; better to exit and be restarted.
+    // With no checks at all, `join_next` is `None` straight away and only
+    // the listeners count.
+    tokio::select! {
+        accepted = futures::future::try_join_all(accepts) => {
+            accepted?;
+        }
+        Some(gave_up) = supervisor.join_next() => {
+            error!("{}, shutting down", gave_up);
+            bail!("{}", gave_up);
+        }
+    }
+    Ok(())
+}
+
+/// Re-read `path` on every SIGHUP and swap in its access lists. Other
+/// settings only take effect on restart; a bad file keeps the old lists.
+async fn reload_on_hangup(path: PathBuf, acl: Arc<RwLock<Acl>>) -> anyhow::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        match Config::load(Some(&path)) {
+            Ok(config) => {
+                *acl.write().unwrap() = Acl::new(&config.access);
+                info!("reloaded access lists from {}", path.display());
+            }
+            Err(e) => warn!("keeping old access lists: {:#}", anyhow!(e)),
+        }
+    }
+    Ok(())
+}
+
+/// One listener, and the state shared by every client connection it
+/// accepts. The `Arc`s are shared with the other listeners.
+#[derive(Debug)]
+struct Proxy {
+    name: String,
+    mode: Mode,
+    config: Arc<Config>,
+    /// Replaced on reload, so read it rather than `config.access`.
+    acl: Arc<RwLock<Acl>>,
+    router: Router,
+    metrics: Metrics,
+    limiter: Limiter,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    access_log: Option<AccessLog>,
+    /// Terminates client TLS when the listener has a `tls` block.
+    tls: Option<ServerTls>,
+    mirror: Option<Mirror>,
+    pool: Option<Arc<Pool>>,
+}
+
+impl Proxy {
+    /// Accept clients on `listener` until it fails.
+    async fn accept(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        loop {
+            let (client, addr) = listener.accept().await?;
+            if let Err(e) = socket::tune(&client, &self.config.socket) {
+                warn!("{}: failed to set socket options: {}", addr, e);
+            }
+            if !self.acl.read().unwrap().permits(addr.ip()) {
+                debug!("{}: denied {} by access list", self.name, addr);
+                drop(client);
+                self.metrics.denied.inc();
+                self.log_refusal(addr, "denied by access list").await;
+                continue;
+            }
+            // behind a PROXY protocol sender every client has its address,
+            // so the check waits for the header, in `serve`
+            if !self.config.proxy_protocol.accept {
+                if let Some(wait) = self.rate_limited(addr) {
+                    debug!("{}: rate limited {}", self.name, addr);
+                    // straight to the socket, which has room for this much:
+                    // tokio wouldn't know it's writable yet, and the accept
+                    // loop mustn't wait
+                    if self.mode == Mode::Http && self.tls.is_none() {
+                        let _ = SockRef::from(&client).send(TOO_MANY_REQUESTS);
+                    }
+                    drop(client);
+                    let reason = format!("rate limited, next token in {}", wait);
+                    self.log_refusal(addr, &reason).await;
+                    continue;
+                }
+            }
+            let Some(permit) = self.limiter.admit().await else {
+                warn!(
+                    "{}: connection limit reached, rejecting {}",
+                    self.name, addr
+                );
+                drop(client);
+                self.metrics.rejected.inc();
+                self.log_refusal(addr, "connection limit reached").await;
+                continue;
+            };
+            info!("{}: accepted connection: {}", self.name, addr);
+            self.limiter.report_utilization();
+            self.metrics.connections.inc();
+            self.metrics.active.inc();
+            let proxy = Arc::clone(&self);
+            let span = info_span!(
+                "connection",
+                listener = %self.name,
+                client = %addr,
+                upstream = field::Empty,
+                bytes_in = field::Empty,
+                bytes_out = field::Empty,
+                duration_ms = field::Empty,
+                reason = field::Empty,
+            );
+            let connection = async move {
+                let started = Instant::now();
+                let mut record = AccessRecord::new(&proxy.name, addr);
+                match proxy.serve(client, &mut record).await {
+                    Ok(()) => record.reason = "closed".to_string(),
+                    Err(e) => {
+                        warn!("{}: {:#}", addr, e);
+                        record.reason = format!("{:#}", e);
+                    }
+                }
+                record.duration = started.elapsed().into();
+                record.trace(&Span::current());
+                info!(
+                    "{}: {} after {}, {} in, {} out",
+                    addr, record.reason, record.duration, record.bytes_in, record.bytes_out
+                );
+                drop(permit);
+                proxy.limiter.report_utilization();
+                proxy.metrics.active.dec();
+                let bytes = &proxy.metrics.bytes;
+                bytes
+                    .with_label_values(&["in"])
+                    .inc_by(record.bytes_in.into());
+                bytes
+                    .with_label_values(&["out"])
+                    .inc_by(record.bytes_out.into());
+                if let Some(access_log) = &proxy.access_log {
+                    access_log.record(record).await;
+                }
+            };
+            tokio::spawn(connection.instrument(span));
+        }
+    }
+
+    /// Spend one of `client`'s tokens, or say how long until it has one.
+    /// Checked before reading anything from the client, so a limited one
+    /// costs neither a connection slot nor a handshake.
+    fn rate_limited(&self, client: SocketAddr) -> Option<Millis> {
+        let wait = self.rate_limiter.as_ref()?.allow(client.ip()).err()?;
+        self.metrics.rate_limited.inc();
+        Some(wait)
+    }
+
+    async fn log_refusal(&self, client: SocketAddr, reason: &str) {
+        if let Some(access_log) = &self.access_log {
+            let mut record = AccessRecord::new(&self.name, client);
+            record.reason = reason.to_string();
+            access_log.record(record).await;
+        }
+    }
+
+    /// Route one client connection to an upstream and proxy it, noting what
+    /// happened in `record` along the way.
+    async fn serve(&self, mut client: TcpStream, record: &mut AccessRecord) -> anyhow::Result<()> {
+        let config = &self.config;
+        let metrics = &self.metrics;
+        let mut local = client.local_addr()?;
+        if config.proxy_protocol.accept {
+            let read = proxy_protocol::read(&mut client);
+            let header = tokio::time::timeout(config.timeouts.read, read)
+                .await
+                .map_err(|_| anyhow!("timed out reading PROXY protocol header"))??;
+            if let Some((src, dst)) = header {
+                debug!("{} proxies for {}", record.client, src);
+                record.client = src;
+                local = dst;
+            }
+            if let Some(wait) = self.rate_limited(record.client) {
+                if self.mode == Mode::Http && self.tls.is_none() {
+                    client.write_all(TOO_MANY_REQUESTS).await?;
+                }
+                bail!("rate limited, next token in {}", wait);
+            }
+        }
+
+        let mut peer = Peer::from(record.client);
+        let mut client = match &self.tls {
+            Some(tls) => {
+                let accept = tls.accept(client);
+                let stream = tokio::time::timeout(config.timeouts.read, accept)
+                    .await
+                    .map_err(|_| anyhow!("timed out in TLS handshake"))?
+                    .context("TLS handshake failed")?;
+                peer.tls = true;
+                peer.cert_subject = server_tls::client_subject(stream.get_ref().1);
+                if let Some(subject) = &peer.cert_subject {
+                    debug!("{} authenticated as {}", record.client, subject);
+                }
+                Either::Right(stream)
+            }
+            None => Either::Left(client),
+        };
+
+        let mut head = match config.proxy_protocol.send {
+            Some(version) => proxy_protocol::encode(version, record.client, local),
+            None => Vec::new(),
+        };
+        let mut host = None;
+        let mut headers = Vec::new();
+        let mut encoding = None;
+        let mut mirror = None;
+        let mut keep_alive = None;
+        if self.mode == Mode::Http {
+            let read = http::read_request(&mut client, &peer, &config.http);
+            let request = tokio::time::timeout(config.timeouts.read, read)
+                .await
+                .map_err(|_| anyhow!("timed out reading request head"))??;
+            if let Some(shadow) = &self.mirror {
+                if !request.websocket && shadow.sample() {
+                    metrics.mirrored.inc();
+                    mirror = Some(shadow.start(request.head.clone()));
+                }
+            }
+            head.extend_from_slice(&request.head);
+            host = request.host;
+            headers = request.headers;
+            encoding = request.encoding;
+            keep_alive = request.keep_alive;
+            if request.websocket {
+                debug!("{} is upgrading to websocket", record.client);
+            }
+        }
+        if self.mode == Mode::Tls {
+            let read = sni::read_client_hello(&mut client);
+            let (hello, server_name) = tokio::time::timeout(config.timeouts.read, read)
+                .await
+                .map_err(|_| anyhow!("timed out reading ClientHello"))??;
+            head.extend_from_slice(&hello);
+            host = server_name;
+        }
+        if self.mode == Mode::Socks5 {
+            return self.serve_socks5(client, record).await;
+        }
+
+        let server = self
+            .router
+            .route(host.as_deref(), &headers)
+            .pick(record.client.ip())
+            .ok_or_else(|| anyhow!("no healthy upstream"))?;
+        record.upstream = Some(server.addr.clone());
+
+        let started = Instant::now();
+        let deadline = tokio::time::Instant::now() + config.timeouts.connect;
+        let failed = |e: anyhow::Error| {
+            server.record_failure();
+            metrics
+                .connect_failures
+                .with_label_values(&[&server.addr])
+                .inc();
+            e
+        };
+        let connected = || {
+            metrics
+                .connect_seconds
+                .with_label_values(&[&server.addr])
+                .observe(started.elapsed().as_secs_f64());
+            info!("{} -> {}", record.client, server.addr);
+        };
+
+        let relay = Relay {
+            idle: config.timeouts.idle,
+            buffer_size: config.socket.buffer_size,
+            compression: encoding.map(|encoding| (encoding, &config.http.compression)),
+            mirror,
+        };
+        let pool = self.pool.as_ref().filter(|_| server.tls.is_none());
+        if let (Some(pool), Some(keep_alive)) = (pool, &keep_alive) {
+            let conn = match pool.take(&server.addr) {
+                Some(conn) => {
+                    metrics.reused.inc();
+                    info!("{} -> {} (reused)", record.client, server.addr);
+                    conn
+                }
+                None => {
+                    let stream = connect_by(deadline, &server.addr, server.connect())
+                        .await
+                        .map_err(failed)?;
+                    socket::tune(&stream, &config.socket)?;
+                    connected();
+                    Pooled::new(stream)
+                }
+            };
+            let stream =
+                pooled_proxy(client, conn.stream, &head, keep_alive, relay, record).await?;
+            if let Some(stream) = stream {
+                let created = conn.created;
+                pool.put(&server.addr, Pooled { stream, created });
+            }
+            return Ok(());
+        }
+
+        let stream = connect_by(deadline, &server.addr, server.connect())
+            .await
+            .map_err(failed)?;
+        socket::tune(&stream, &config.socket)?;
+        match &server.tls {
+            Some(tls) => {
+                let stream = connect_by(deadline, &server.addr, tls.connect(stream))
+                    .await
+                    .map_err(failed)?;
+                connected();
+                match &keep_alive {
+                    Some(keep_alive) => {
+                        pooled_proxy(client, stream, &head, keep_alive, relay, record).await?;
+                        Ok(())
+                    }
+                    None => proxy(client, stream, &head, relay, record).await,
+                }
+            }
+            None => {
+                connected();
+                #[cfg(target_os = "linux")]
+                let client = match client {
+                    Either::Left(client)
+                        if relay.compression.is_none() && relay.mirror.is_none() =>
+                    {
+                        return splice_proxy(client, stream, &head, relay.idle, record).await
+                    }
+                    client => client,
+                };
+                proxy(client, stream, &head, relay, record).await
+            }
+        }
+    }
+
+    /// Connect wherever the SOCKS5 client asks, then relay like any other
+    /// connection. There is no upstream group, so per-upstream metrics and
+    /// the PROXY header for upstreams are skipped.
+    async fn serve_socks5(
+        &self,
+        mut client: Either<TcpStream, TlsStream<TcpStream>>,
+        record: &mut AccessRecord,
+    ) -> anyhow::Result<()> {
+        let config = &self.config;
+        let handshake = socks5::handshake(&mut client, &config.socks5.users);
+        let target = tokio::time::timeout(config.timeouts.read, handshake)
+            .await
+            .map_err(|_| anyhow!("timed out in SOCKS5 handshake"))??;
+        record.upstream = Some(target.to_string());
+
+        let connect = tokio::time::timeout(config.timeouts.connect, target.connect()).await;
+        let stream = match connect {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                socks5::reply(&mut client, Reply::from(&e), None).await?;
+                return Err(anyhow!(e).context(format!("connecting to {}", target)));
+            }
+            Err(_) => {
+                socks5::reply(&mut client, Reply::HostUnreachable, None).await?;
+                bail!("timed out connecting to {}", target);
+            }
+        };
+        socket::tune(&stream, &config.socket)?;
+        socks5::reply(&mut client, Reply::Succeeded, Some(stream.local_addr()?)).await?;
+        info!("{} -> {} (socks5)", record.client, target);
+
+        let relay = Relay {
+            idle: config.timeouts.idle,
+            buffer_size: config.socket.buffer_size,
+            compression: None,
+            mirror: None,
+        };
+        #[cfg(target_os = "linux")]
+        let client = match client {
+            Either::Left(client) => {
+                return splice_proxy(client, stream, &[], relay.idle, record).await
+            }
+            client => client,
+        };
+        proxy(client, stream, &[], relay, record).await
+    }
+}
+
+/// Await one step of connecting to `addr`, failing at `deadline`.
+#[instrument(name = "connect", skip(deadline, step))]
+async fn connect_by<T>(
+    deadline: tokio::time::Instant,
+    addr: &str,
+    step: impl Future<Output = std::io::Result<T>>,
+) -> anyhow::Result<T> {
+    match tokio::time::timeout_at(deadline, step).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(anyhow!("timed out connecting to {}", addr)),
+    }
+}
+
+/// How [`proxy`] handles one connection's bytes on their way through.
+#[derive(Debug)]
+struct Relay<'a> {
+    /// Give up once nothing has moved either way for this long.
+    idle: Duration,
+    buffer_size: usize,
+    /// Compress the response body on the way back if eligible.
+    compression: Option<(Encoding, &'a Compression)>,
+    /// Copy what the client sends to a shadow upstream as well.
+    mirror: Option<Tap>,
+}
+
+/// `head` is sent to the upstream before anything else from the client.
+/// Both directions run to completion, unless nothing moves for too long.
+async fn proxy<C, U>(
+    client: C,
+    upstream: U,
+    head: &[u8],
+    relay: Relay<'_>,
+    record: &mut AccessRecord,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite,
+    U: AsyncRead + AsyncWrite,
+{
+    let (mut client_readr, mut client_writer) = tokio::io::split(client);
+    let (mut upstream_readr, upstream_writer) = tokio::io::split(upstream);
+    let mut upstream_writer = Tee::new(upstream_writer, relay.mirror);
+    let transfer = Transfer::with_buffer_size(relay.buffer_size);
+    let idle = relay.idle;
+
+    let copies = async {
+        upstream_writer.write_all(head).await?;
+        transfer
+            .bytes_in
+            .fetch_add(head.len() as u64, Ordering::Relaxed);
+        let client_to_upstream = copy(
+            &mut client_readr,
+            &mut upstream_writer,
+            &transfer.bytes_in,
+            &transfer,
+        );
+        let upstream_to_client = async {
+            match relay.compression {
+                Some((encoding, config)) => {
+                    compress::relay(
+                        &mut upstream_readr,
+                        &mut client_writer,
+                        encoding,
+                        config,
+                        &transfer.bytes_out,
+                        &transfer,
+                    )
+                    .await
+                }
+                None => {
+                    copy(
+                        &mut upstream_readr,
+                        &mut client_writer,
+                        &transfer.bytes_out,
+                        &transfer,
+                    )
+                    .await
+                }
+            }
+        };
+        let (sent, received) = tokio::join!(client_to_upstream, upstream_to_client);
+        sent.and(received)
+    };
+    let result = tokio::select! {
+        result = copies => result.map_err(Into::into),
+        _ = transfer.idle(idle) => Err(anyhow!("idle for {:?}", idle)),
+    };
+
+    record.bytes_in = Bytes(transfer.bytes_in.load(Ordering::Relaxed));
+    record.bytes_out = Bytes(transfer.bytes_out.load(Ordering::Relaxed));
+    result
+}
+
+/// [`proxy`] for one request whose end is known, so the upstream connection
+/// can outlive it: relays the request and then the response, rewritten to
+/// close the client connection. Returns the upstream if the exchange ended
+/// cleanly and it agreed to keep the connection open.
+async fn pooled_proxy<C, U>(
+    client: C,
+    upstream: U,
+    head: &[u8],
+    keep_alive: &KeepAlive,
+    relay: Relay<'_>,
+    record: &mut AccessRecord,
+) -> anyhow::Result<Option<U>>
+where
+    C: AsyncRead + AsyncWrite,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut client_readr, mut client_writer) = tokio::io::split(client);
+    let (mut upstream_readr, upstream_writer) = tokio::io::split(upstream);
+    let mut upstream_writer = Tee::new(upstream_writer, relay.mirror);
+    let transfer = Transfer::with_buffer_size(relay.buffer_size);
+    let idle = relay.idle;
+
+    let exchange = async {
+        let send = async {
+            upstream_writer.write_all(head).await?;
+            transfer
+                .bytes_in
+                .fetch_add(head.len() as u64, Ordering::Relaxed);
+            let sent = copy_some(
+                &mut client_readr,
+                &mut upstream_writer,
+                keep_alive.remaining,
+                &transfer.bytes_in,
+                &transfer,
+            )
+            .await?;
+            if sent < keep_alive.remaining {
+                bail!("client closed before sending the whole request body");
+            }
+            Ok(())
+        };
+        let receive = async {
+            let mut rest = Vec::new();
+            loop {
+                let read = http::read_response(&mut upstream_readr, rest, keep_alive.head_request);
+                let response = read.await?;
+                transfer.touch();
+                client_writer.write_all(&response.head).await?;
+                transfer
+                    .bytes_out
+                    .fetch_add(response.head.len() as u64, Ordering::Relaxed);
+                if response.informational {
+                    rest = response.rest;
+                    continue;
+                }
+                let clean = http::relay_body(
+                    &mut upstream_readr,
+                    &mut client_writer,
+                    &response.rest,
+                    response.body,
+                    &transfer.bytes_out,
+                    &transfer,
+                )
+                .await?;
+                client_writer.shutdown().await?;
+                return anyhow::Ok(clean && response.keep_alive);
+            }
+        };
+        // The upstream may answer without reading the whole body, in which
+        // case the connection can't be reused.
+        tokio::pin!(send, receive);
+        let mut sent = false;
+        loop {
+            tokio::select! {
+                result = &mut send, if !sent => {
+                    result?;
+                    sent = true;
+                }
+                result = &mut receive => return anyhow::Ok(result? && sent),
+            }
+        }
+    };
+    let result = tokio::select! {
+        result = exchange => result,
+        _ = transfer.idle(idle) => Err(anyhow!("idle for {:?}", idle)),
+    };
+
+    record.bytes_in = Bytes(transfer.bytes_in.load(Ordering::Relaxed));
+    record.bytes_out = Bytes(transfer.bytes_out.load(Ordering::Relaxed));
+    let reusable = result?;
+    Ok(reusable.then(|| upstream_readr.unsplit(upstream_writer.into_inner())))
+}
+
+/// [`proxy`] for plain TCP upstreams when nothing needs rewriting on the
+/// way through: both directions are spliced inside the kernel.
+#[cfg(target_os = "linux")]
+async fn splice_proxy(
+    client: TcpStream,
+    mut upstream: TcpStream,
+    head: &[u8],
+    idle: Duration,
+    record: &mut AccessRecord,
+) -> anyhow::Result<()> {
+    let transfer = Transfer::new();
+
+    let copies = async {
+        upstream.write_all(head).await?;
+        transfer
+            .bytes_in
+            .fetch_add(head.len() as u64, Ordering::Relaxed);
+        let client_to_upstream = splice::copy(&client, &upstream, &transfer.bytes_in, &transfer);
+        let upstream_to_client = splice::copy(&upstream, &client, &transfer.bytes_out, &transfer);
+        let (sent, received) = tokio::join!(client_to_upstream, upstream_to_client);
+        sent.and(received)
+    };
+    let result = tokio::select! {
+        result = copies => result.map_err(Into::into),
+        _ = transfer.idle(idle) => Err(anyhow!("idle for {:?}", idle)),
+    };
+
+    record.bytes_in = Bytes(transfer.bytes_in.load(Ordering::Relaxed));
+    record.bytes_out = Bytes(transfer.bytes_out.load(Ordering::Relaxed));
+    result
+}
+
+fn init_tracer(log: &LogConfig) -> anyhow::Result<Tracer> {
+    let sampler = Sampler::TraceIdRatioBased(log.sample_ratio);
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&log.otlp_endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_id_generator(RandomIdGenerator::default())
+                .with_sampler(Sampler::ParentBased(Box::new(sampler)))
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    log.service_name.clone(),
+                )])),
+        )
+        .install_batch(Tokio)?;
+    Ok(tracer)
+}
@@ -0,0 +1,122 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+/// Aggregate proxy counters, served in the Prometheus text format on the
+/// admin listener (see [`crate::admin`]). Per-connection numbers go to the access log instead.
+#[derive(Debug)]
+pub struct Metrics {
+    pub connections: IntCounter,
+    pub rejected: IntCounter,
+    pub rate_limited: IntCounter,
+    pub denied: IntCounter,
+    pub mirrored: IntCounter,
+    pub reused: IntCounter,
+    pub active: IntGauge,
+    /// Labelled `direction` = `in` (client to upstream) or `out`.
+    pub bytes: IntCounterVec,
+    pub connect_failures: IntCounterVec,
+    pub connect_seconds: HistogramVec,
+}
+
+impl Metrics {
+    /// One listener's metrics, labelled `listener` = `name` in `registry`.
+    pub fn new(registry: &Registry, listener: &str) -> prometheus::Result<Self> {
+        let opts = |name: &str, help: &str| Opts::new(name, help).const_label("listener", listener);
+        let connections =
+            IntCounter::with_opts(opts("connections_total", "Accepted client connections"))?;
+        let rejected = IntCounter::with_opts(opts(
+            "connections_rejected_total",
+            "Connections turned away at the connection limit",
+        ))?;
+        let rate_limited = IntCounter::with_opts(opts(
+            "rate_limited_total",
+            "Connections or requests refused by the per-client rate limit",
+        ))?;
+        let denied = IntCounter::with_opts(opts(
+            "connections_denied_total",
+            "Connections refused by the client address allow/deny lists",
+        ))?;
+        let mirrored = IntCounter::with_opts(opts(
+            "mirrored_requests_total",
+            "HTTP requests copied to the shadow upstream",
+        ))?;
+        let reused = IntCounter::with_opts(opts(
+            "reused_connections_total",
+            "Requests sent over a pooled upstream connection",
+        ))?;
+        let active = IntGauge::with_opts(opts("connections_active", "Connections being proxied"))?;
+        let bytes = IntCounterVec::new(opts("bytes_total", "Bytes proxied"), &["direction"])?;
+        let connect_failures = IntCounterVec::new(
+            opts(
+                "upstream_connect_failures_total",
+                "Failed or timed out upstream connects",
+            ),
+            &["upstream"],
+        )?;
+        let connect_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "upstream_connect_seconds",
+                "Time to connect to an upstream, TLS handshake included",
+            )
+            .const_label("listener", listener),
+            &["upstream"],
+        )?;
+
+        registry.register(Box::new(connections.clone()))?;
+        registry.register(Box::new(rejected.clone()))?;
+        registry.register(Box::new(rate_limited.clone()))?;
+        registry.register(Box::new(denied.clone()))?;
+        registry.register(Box::new(mirrored.clone()))?;
+        registry.register(Box::new(reused.clone()))?;
+        registry.register(Box::new(active.clone()))?;
+        registry.register(Box::new(bytes.clone()))?;
+        registry.register(Box::new(connect_failures.clone()))?;
+        registry.register(Box::new(connect_seconds.clone()))?;
+
+        Ok(Self {
+            connections,
+            rejected,
+            rate_limited,
+            denied,
+            mirrored,
+            reused,
+            active,
+            bytes,
+            connect_failures,
+            connect_seconds,
+        })
+    }
+}
+
+/// The registry every listener's [`Metrics`] goes into.
+pub fn registry() -> prometheus::Result<Registry> {
+    Registry::new_custom(Some("minginx".to_string()), None)
+}
+
+pub fn render(registry: &Registry) -> String {
+    let mut buf = Vec::new();
+    TextEncoder::new()
+        .encode(&registry.gather(), &mut buf)
+        .expect("text encoding into a Vec cannot fail");
+    String::from_utf8(buf).expect("prometheus text format is UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_uses_namespace_and_labels() {
+        let registry = registry().unwrap();
+        let metrics = Metrics::new(&registry, "default").unwrap();
+        let other = Metrics::new(&registry, "tls").unwrap();
+        metrics.bytes.with_label_values(&["in"]).inc_by(42);
+        other.active.inc();
+        let text = render(&registry);
+        assert!(text.contains("minginx_bytes_total{direction=\"in\",listener=\"default\"} 42"));
+        assert!(text.contains("minginx_connections_active{listener=\"default\"} 0"));
+        assert!(text.contains("minginx_connections_active{listener=\"tls\"} 1"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure};
+use bytes::Bytes;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::config::{MirrorConfig, Timeouts};
+
+/// Chunks a shadow request may lag behind the real one before it is
+/// abandoned; mirroring must never slow the client down.
+const BACKLOG: usize = 64;
+
+/// Copies a share of HTTP requests to a shadow upstream and throws its
+/// responses away.
+#[derive(Debug)]
+pub struct Mirror {
+    addr: String,
+    percent: f64,
+    seen: AtomicU64,
+    connect: Duration,
+    idle: Duration,
+}
+
+impl Mirror {
+    pub fn new(config: &MirrorConfig, timeouts: &Timeouts) -> Self {
+        Self {
+            addr: config.upstream.clone(),
+            percent: config.percent,
+            seen: AtomicU64::new(0),
+            connect: timeouts.connect,
+            idle: timeouts.idle,
+        }
+    }
+
+    /// Whether to mirror the next request: exactly `percent` of them, spread
+    /// evenly rather than at random.
+    pub fn sample(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed);
+        let quota = |n: u64| (n as f64 * self.percent / 100.0) as u64;
+        quota(n + 1) > quota(n)
+    }
+
+    /// Start a shadow request with `head`; the rest of the request body
+    /// follows through the returned [`Tap`].
+    pub fn start(&self, head: Vec<u8>) -> Tap {
+        let (tx, rx) = mpsc::channel(BACKLOG);
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let shadow = Shadow {
+            addr: self.addr.clone(),
+            connect: self.connect,
+            idle: self.idle,
+            abandoned: Arc::clone(&abandoned),
+        };
+        tokio::spawn(async move {
+            if let Err(e) = shadow.run(head, rx).await {
+                debug!(upstream = %shadow.addr, "mirrored request failed: {:#}", e);
+            }
+        });
+        Tap {
+            tx: Some(tx),
+            abandoned,
+        }
+    }
+}
+
+/// Feeds one shadow request. Dropping it ends the request.
+#[derive(Debug)]
+pub struct Tap {
+    tx: Option<mpsc::Sender<Bytes>>,
+    abandoned: Arc<AtomicBool>,
+}
+
+impl Tap {
+    fn feed(&mut self, data: &[u8]) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        if tx.try_send(Bytes::copy_from_slice(data)).is_err() {
+            self.abandoned.store(true, Ordering::Relaxed);
+            self.tx = None;
+        }
+    }
+}
+
+/// Writes through to `inner`, copying every byte it accepts to a [`Tap`].
+#[derive(Debug)]
+pub struct Tee<W> {
+    inner: W,
+    tap: Option<Tap>,
+}
+
+impl<W> Tee<W> {
+    pub fn new(inner: W, tap: Option<Tap>) -> Self {
+        Self { inner, tap }
+    }
+
+    /// Stop copying, ending the shadow request, and give back the writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Tee<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(tap)) = (&poll, &mut self.tap) {
+            tap.feed(&buf[..*n]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.tap = None;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[derive(Debug)]
+struct Shadow {
+    addr: String,
+    connect: Duration,
+    idle: Duration,
+    abandoned: Arc<AtomicBool>,
+}
+
+impl Shadow {
+    async fn run(&self, head: Vec<u8>, mut rx: mpsc::Receiver<Bytes>) -> anyhow::Result<()> {
+        let connect = TcpStream::connect(&self.addr);
+        let mut stream = tokio::time::timeout(self.connect, connect)
+            .await
+            .map_err(|_| anyhow!("timed out connecting"))??;
+        stream.write_all(&head).await?;
+        while let Some(chunk) = rx.recv().await {
+            stream.write_all(&chunk).await?;
+        }
+        // The copied head may ask for keep-alive; this tells the shadow
+        // there is nothing after the one request.
+        stream.shutdown().await?;
+        ensure!(
+            !self.abandoned.load(Ordering::Relaxed),
+            "fell behind the client"
+        );
+        let mut sink = tokio::io::sink();
+        let drain = tokio::io::copy(&mut stream, &mut sink);
+        tokio::time::timeout(self.idle, drain)
+            .await
+            .map_err(|_| anyhow!("no complete response within {:?}", self.idle))??;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn mirror(addr: String, percent: f64) -> Mirror {
+        let config = MirrorConfig {
+            upstream: addr,
+            percent,
+        };
+        Mirror::new(&config, &Timeouts::default())
+    }
+
+    #[test]
+    fn samples_an_even_share() {
+        let mirror = mirror("shadow:80".to_string(), 25.0);
+        let picks: Vec<_> = (0..8).map(|_| mirror.sample()).collect();
+        assert_eq!(picks.iter().filter(|&&p| p).count(), 2);
+        assert_eq!(picks[..4].iter().filter(|&&p| p).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn tee_copies_written_bytes_to_the_shadow() -> anyhow::Result<()> {
+        let shadow = TcpListener::bind("127.0.0.1:0").await?;
+        let mirror = mirror(shadow.local_addr()?.to_string(), 100.0);
+
+        let mut real = Vec::new();
+        let mut tee = Tee::new(&mut real, Some(mirror.start(b"HEAD ".to_vec())));
+        tee.write_all(b"body").await?;
+        tee.shutdown().await?;
+        assert_eq!(real, b"body");
+
+        let (mut conn, _) = shadow.accept().await?;
+        let mut received = [0; 9];
+        let read = conn.read_exact(&mut received);
+        tokio::time::timeout(Duration::from_secs(5), read).await??;
+        assert_eq!(&received, b"HEAD body");
+        conn.shutdown().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpStream;
+use tracing::debug;
+
+use crate::config::PoolConfig;
+
+/// Idle kept-alive connections to plain TCP upstreams, keyed by upstream
+/// address and shared by every listener.
+#[derive(Debug)]
+pub struct Pool {
+    max_idle: usize,
+    idle_timeout: Duration,
+    max_lifetime: Duration,
+    idle: Mutex<HashMap<String, Vec<Idle>>>,
+}
+
+/// An upstream connection that can go back into the [`Pool`] after a clean
+/// exchange.
+#[derive(Debug)]
+pub struct Pooled {
+    pub stream: TcpStream,
+    pub created: Instant,
+}
+
+#[derive(Debug)]
+struct Idle {
+    conn: Pooled,
+    since: Instant,
+}
+
+impl Pooled {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            created: Instant::now(),
+        }
+    }
+}
+
+impl Pool {
+    pub fn new(config: &PoolConfig) -> Self {
+        Self {
+            max_idle: config.max_idle,
+            idle_timeout: config.idle_timeout,
+            max_lifetime: config.max_lifetime,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The most recently parked connection to `addr` that is still fresh and
+    /// hasn't been closed by the upstream in the meantime.
+    pub fn take(&self, addr: &str) -> Option<Pooled> {
+        let mut idle = self.idle.lock().unwrap();
+        let parked = idle.get_mut(addr)?;
+        while let Some(Idle { conn, since }) = parked.pop() {
+            if self.is_fresh(&conn, since) && is_open(&conn.stream) {
+                return Some(conn);
+            }
+        }
+        None
+    }
+
+    /// Park `conn` for reuse, evicting the longest idle connection to `addr`
+    /// if there are already `max_idle`.
+    pub fn put(&self, addr: &str, conn: Pooled) {
+        if conn.created.elapsed() >= self.max_lifetime {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let parked = idle.entry(addr.to_string()).or_default();
+        if parked.len() >= self.max_idle {
+            parked.remove(0);
+        }
+        parked.push(Idle {
+            conn,
+            since: Instant::now(),
+        });
+    }
+
+    /// Close connections that have been idle or alive for too long.
+    fn prune(&self) {
+        let mut idle = self.idle.lock().unwrap();
+        for (addr, parked) in idle.iter_mut() {
+            let before = parked.len();
+            parked.retain(|idle| self.is_fresh(&idle.conn, idle.since));
+            if parked.len() < before {
+                debug!(upstream = %addr, "closed {} idle connections", before - parked.len());
+            }
+        }
+        idle.retain(|_, parked| !parked.is_empty());
+    }
+
+    fn is_fresh(&self, conn: &Pooled, since: Instant) -> bool {
+        since.elapsed() < self.idle_timeout && conn.created.elapsed() < self.max_lifetime
+    }
+}
+
+/// An idle connection has nothing to read; data or EOF means the upstream
+/// has given up on it.
+fn is_open(stream: &TcpStream) -> bool {
+    let mut byte = [0; 1];
+    matches!(stream.try_read(&mut byte), Err(e) if e.kind() == io::ErrorKind::WouldBlock)
+}
+
+/// Prune `pool` in the background, so connections don't linger past their
+/// timeouts while no request comes along to take them.
+pub fn spawn(pool: Arc<Pool>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(pool.idle_timeout / 2);
+        loop {
+            interval.tick().await;
+            pool.prune();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn pool(max_idle: usize, idle_timeout: Duration) -> Pool {
+        Pool::new(&PoolConfig {
+            enabled: true,
+            max_idle,
+            idle_timeout,
+            ..Default::default()
+        })
+    }
+
+    async fn connect(listener: &TcpListener) -> anyhow::Result<(Pooled, TcpStream)> {
+        let stream = TcpStream::connect(listener.local_addr()?).await?;
+        let (accepted, _) = listener.accept().await?;
+        Ok((Pooled::new(stream), accepted))
+    }
+
+    #[tokio::test]
+    async fn reuses_newest_and_evicts_oldest() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let pool = pool(2, Duration::from_secs(30));
+        let mut peers = Vec::new();
+        let mut ports = Vec::new();
+        for _ in 0..3 {
+            let (conn, peer) = connect(&listener).await?;
+            ports.push(conn.stream.local_addr()?.port());
+            pool.put("app:80", conn);
+            peers.push(peer);
+        }
+
+        let port = |conn: Option<Pooled>| conn.map(|c| c.stream.local_addr().unwrap().port());
+        assert_eq!(port(pool.take("app:80")), Some(ports[2]));
+        assert_eq!(port(pool.take("app:80")), Some(ports[1]));
+        assert_eq!(port(pool.take("app:80")), None);
+        assert!(pool.take("other:80").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skips_stale_and_closed_connections() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let pool = pool(4, Duration::from_millis(50));
+
+        let (conn, _peer) = connect(&listener).await?;
+        pool.put("app:80", conn);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(pool.take("app:80").is_none());
+
+        let (conn, peer) = connect(&listener).await?;
+        pool.put("app:80", conn);
+        drop(peer);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(pool.take("app:80").is_none());
+        Ok(())
+    }
+}
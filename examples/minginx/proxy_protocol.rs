@@ -0,0 +1,160 @@
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{anyhow, bail, ensure};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::config::ProxyVersion;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// Longest legal v1 line, CRLF included.
+const V1_MAX: usize = 107;
+
+/// The PROXY protocol header telling the upstream that `src` connected to
+/// us at `dst`.
+pub fn encode(version: ProxyVersion, src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let (src, dst) = same_family(src, dst);
+    match version {
+        ProxyVersion::V1 => {
+            let family = match src {
+                SocketAddr::V4(_) => "TCP4",
+                SocketAddr::V6(_) => "TCP6",
+            };
+            format!(
+                "PROXY {} {} {} {} {}\r\n",
+                family,
+                src.ip(),
+                dst.ip(),
+                src.port(),
+                dst.port()
+            )
+            .into_bytes()
+        }
+        ProxyVersion::V2 => {
+            let mut out = V2_SIGNATURE.to_vec();
+            out.push(0x21); // version 2, PROXY command
+            let (family, addrs): (u8, Vec<u8>) = match (src.ip(), dst.ip()) {
+                (IpAddr::V4(s), IpAddr::V4(d)) => (0x11, [s.octets(), d.octets()].concat()),
+                (IpAddr::V6(s), IpAddr::V6(d)) => (0x21, [s.octets(), d.octets()].concat()),
+                _ => unreachable!("same_family returned mixed addresses"),
+            };
+            out.push(family);
+            out.extend_from_slice(&(addrs.len() as u16 + 4).to_be_bytes());
+            out.extend_from_slice(&addrs);
+            out.extend_from_slice(&src.port().to_be_bytes());
+            out.extend_from_slice(&dst.port().to_be_bytes());
+            out
+        }
+    }
+}
+
+/// Headers carry one family for both ends; map IPv4 into IPv6 if they differ.
+fn same_family(src: SocketAddr, dst: SocketAddr) -> (SocketAddr, SocketAddr) {
+    let v6 = |addr: SocketAddr| match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+        IpAddr::V6(_) => addr,
+    };
+    match src.is_ipv4() == dst.is_ipv4() {
+        true => (src, dst),
+        false => (v6(src), v6(dst)),
+    }
+}
+
+/// Read a v1 or v2 header sent by a fronting load balancer, consuming
+/// exactly its bytes. Returns the original `(src, dst)`, or `None` for
+/// `LOCAL`/`UNKNOWN` connections such as the balancer's own health checks.
+pub async fn read<R>(stream: &mut R) -> anyhow::Result<Option<(SocketAddr, SocketAddr)>>
+where
+    R: AsyncRead + Unpin,
+{
+    // Every header, even "PROXY UNKNOWN\r\n", is at least this long.
+    let mut buf = vec![0; V2_SIGNATURE.len()];
+    stream.read_exact(&mut buf).await?;
+    if buf == V2_SIGNATURE {
+        return read_v2(stream).await;
+    }
+    ensure!(buf.starts_with(b"PROXY "), "missing PROXY protocol header");
+    while !buf.ends_with(b"\r\n") {
+        ensure!(buf.len() < V1_MAX, "PROXY protocol v1 header too long");
+        buf.push(stream.read_u8().await?);
+    }
+    parse_v1(&buf[..buf.len() - 2])
+}
+
+fn parse_v1(line: &[u8]) -> anyhow::Result<Option<(SocketAddr, SocketAddr)>> {
+    let line = std::str::from_utf8(line)?;
+    let fields: Vec<_> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", src, dst, sport, dport] => {
+            let src = SocketAddr::new(src.parse()?, sport.parse()?);
+            let dst = SocketAddr::new(dst.parse()?, dport.parse()?);
+            Ok(Some((src, dst)))
+        }
+        _ => bail!("malformed PROXY protocol v1 header {:?}", line),
+    }
+}
+
+async fn read_v2<R>(stream: &mut R) -> anyhow::Result<Option<(SocketAddr, SocketAddr)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let ver_cmd = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let len = stream.read_u16().await? as usize;
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+
+    ensure!(ver_cmd >> 4 == 2, "unsupported PROXY protocol version");
+    if ver_cmd & 0x0f == 0 {
+        return Ok(None);
+    }
+    let ips = match family {
+        0x11 if len >= 12 => {
+            let ip = |at: usize| IpAddr::from(<[u8; 4]>::try_from(&body[at..at + 4]).unwrap());
+            Some((ip(0), ip(4), 8))
+        }
+        0x21 if len >= 36 => {
+            let ip = |at: usize| IpAddr::from(<[u8; 16]>::try_from(&body[at..at + 16]).unwrap());
+            Some((ip(0), ip(16), 32))
+        }
+        _ => None,
+    };
+    let (src, dst, at) =
+        ips.ok_or_else(|| anyhow!("unsupported PROXY protocol v2 address family"))?;
+    let port = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+    Ok(Some((
+        SocketAddr::new(src, port(at)),
+        SocketAddr::new(dst, port(at + 2)),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn encode_then_read_round_trips() {
+        let src: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let dst: SocketAddr = "198.51.100.2:443".parse().unwrap();
+
+        let v1 = encode(ProxyVersion::V1, src, dst);
+        assert_eq!(v1, b"PROXY TCP4 192.0.2.1 198.51.100.2 5000 443\r\n");
+
+        for version in [ProxyVersion::V1, ProxyVersion::V2] {
+            let mut header = encode(version, src, dst);
+            header.extend_from_slice(b"GET /");
+            let mut stream = header.as_slice();
+            assert_eq!(read(&mut stream).await.unwrap(), Some((src, dst)));
+            assert_eq!(stream, b"GET /");
+        }
+    }
+
+    #[tokio::test]
+    async fn mixed_families_use_ipv6() {
+        let src: SocketAddr = "192.0.2.1:5000".parse().unwrap();
+        let dst: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+        let header = encode(ProxyVersion::V2, src, dst);
+        let (src, _) = read(&mut header.as_slice()).await.unwrap().unwrap();
+        assert_eq!(src.to_string(), "[::ffff:192.0.2.1]:5000");
+    }
+}
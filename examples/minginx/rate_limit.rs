@@ -0,0 +1,122 @@
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use ecosystem::units::Millis;
+use lru::LruCache;
+
+use crate::config::RateLimit;
+
+/// Token bucket per client IP: `burst` tokens to start with, refilled at
+/// `rate` per second, one spent per connection (one request in `http`
+/// mode). The table holds at most `max_clients` addresses; the least
+/// recently seen one is evicted to make room, which at worst hands that
+/// client a fresh bucket.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<LruCache<IpAddr, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    seen: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimit) -> Self {
+        Self {
+            rate: config.rate,
+            burst: f64::from(config.burst),
+            // validation rejects 0
+            buckets: Mutex::new(LruCache::new(
+                NonZeroUsize::new(config.max_clients).unwrap_or(NonZeroUsize::MIN),
+            )),
+        }
+    }
+
+    /// Spend a token for `ip`, or say how long until it has one again.
+    pub fn allow(&self, ip: IpAddr) -> Result<(), Millis> {
+        self.allow_at(ip, Instant::now())
+    }
+
+    fn allow_at(&self, ip: IpAddr, now: Instant) -> Result<(), Millis> {
+        let mut buckets = self.buckets.lock().unwrap();
+        // a new address pushes out the least recently seen one when full
+        let bucket = buckets.get_or_insert_mut(ip, || Bucket {
+            tokens: self.burst,
+            seen: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.seen).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.seen = now;
+        if bucket.tokens < 1.0 {
+            let wait = (1.0 - bucket.tokens) / self.rate;
+            return Err(Millis((wait * 1000.0).ceil() as u64));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn bucket_refills_and_table_stays_bounded() {
+        let limiter = RateLimiter::new(&RateLimit {
+            enabled: true,
+            rate: 2.0,
+            burst: 2,
+            max_clients: 2,
+        });
+        let (a, b, c) = (
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            "10.0.0.3".parse().unwrap(),
+        );
+        let start = Instant::now();
+
+        assert!(limiter.allow_at(a, start).is_ok());
+        assert!(limiter.allow_at(a, start).is_ok());
+        assert_eq!(limiter.allow_at(a, start), Err(Millis(500)));
+        assert!(limiter
+            .allow_at(a, start + Duration::from_millis(500))
+            .is_ok());
+
+        assert!(limiter.allow_at(b, start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.allow_at(c, start + Duration::from_secs(2)).is_ok());
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert!(!buckets.contains(&a));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_seen_not_the_oldest() {
+        let limiter = RateLimiter::new(&RateLimit {
+            enabled: true,
+            rate: 1.0,
+            burst: 5,
+            max_clients: 2,
+        });
+        let (a, b, c) = (
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            "10.0.0.3".parse().unwrap(),
+        );
+        let start = Instant::now();
+        limiter.allow_at(a, start).unwrap();
+        limiter.allow_at(b, start).unwrap();
+        limiter.allow_at(a, start).unwrap();
+        limiter.allow_at(c, start).unwrap();
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.contains(&a));
+        assert!(!buckets.contains(&b));
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ServerConfig, ServerConnection};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::config::ListenerTls;
+use crate::upstream::load_ca;
+
+/// Terminates client TLS on a listener. With a `client_ca`, the handshake
+/// fails for any client without a certificate it signed.
+pub struct ServerTls {
+    acceptor: TlsAcceptor,
+    mutual: bool,
+}
+
+impl ServerTls {
+    pub fn new(config: &ListenerTls) -> anyhow::Result<Self> {
+        Ok(Self {
+            acceptor: acceptor(config)?,
+            mutual: config.client_ca.is_some(),
+        })
+    }
+
+    pub async fn accept(&self, stream: TcpStream) -> std::io::Result<TlsStream<TcpStream>> {
+        self.acceptor.accept(stream).await
+    }
+}
+
+impl fmt::Debug for ServerTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerTls")
+            .field("mutual", &self.mutual)
+            .finish()
+    }
+}
+
+fn acceptor(config: &ListenerTls) -> anyhow::Result<TlsAcceptor> {
+    let certs = load_certs(&config.cert)?;
+    let key = load_key(&config.key)?;
+    let builder = ServerConfig::builder();
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let roots = Arc::new(load_ca(path)?);
+            builder.with_client_cert_verifier(WebPkiClientVerifier::builder(roots).build()?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let server = builder.with_single_cert(certs, key).with_context(|| {
+        format!(
+            "{} does not match {}",
+            config.key.display(),
+            config.cert.display()
+        )
+    })?;
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+/// The verified client certificate's subject, e.g. `CN=alice, O=Example`.
+pub fn client_subject(connection: &ServerConnection) -> Option<String> {
+    let cert = connection.peer_certificates()?.first()?;
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    Some(cert.subject().to_string())
+}
+
+fn load_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice()).collect::<Result<Vec<_>, _>>()?;
+    anyhow::ensure!(!certs.is_empty(), "no certificates in {}", path.display());
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let pem = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())?
+        .with_context(|| format!("no private key in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+
+    fn write(dir: &Path, name: &str, pem: String) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, pem).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn mutual_tls_requires_and_reports_client_cert() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("minginx-mtls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+
+        let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate()?;
+        let ca = ca_params.self_signed(&ca_key)?;
+        let server_key = KeyPair::generate()?;
+        let server = CertificateParams::new(vec!["proxy.test".to_string()])?.signed_by(
+            &server_key,
+            &ca,
+            &ca_key,
+        )?;
+        let mut client_params = CertificateParams::new(Vec::<String>::new())?;
+        client_params.distinguished_name = DistinguishedName::new();
+        client_params
+            .distinguished_name
+            .push(DnType::CommonName, "alice");
+        let client_key = KeyPair::generate()?;
+        let client = client_params.signed_by(&client_key, &ca, &ca_key)?;
+
+        let acceptor = acceptor(&ListenerTls {
+            cert: write(&dir, "server.pem", server.pem()),
+            key: write(&dir, "server.key", server_key.serialize_pem()),
+            client_ca: Some(write(&dir, "ca.pem", ca.pem())),
+        })?;
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone())?;
+        let with_cert = ClientConfig::builder()
+            .with_root_certificates(roots.clone())
+            .with_client_auth_cert(
+                vec![client.der().clone()],
+                PrivateKeyDer::try_from(client_key.serialize_der()).map_err(anyhow::Error::msg)?,
+            )?;
+        let without_cert = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        for (config, authenticated) in [(with_cert, true), (without_cert, false)] {
+            let (near, far) = tokio::io::duplex(64 * 1024);
+            let connector = TlsConnector::from(Arc::new(config));
+            let name = "proxy.test".try_into()?;
+            let client = async {
+                let mut stream = connector.connect(name, near).await?;
+                stream.write_all(b"hi").await?;
+                stream.flush().await?;
+                let mut buf = [0; 2];
+                stream.read_exact(&mut buf).await
+            };
+            let server = async {
+                let mut stream = acceptor.accept(far).await?;
+                let subject = client_subject(stream.get_ref().1);
+                let mut buf = [0; 2];
+                stream.read_exact(&mut buf).await?;
+                stream.write_all(&buf).await?;
+                stream.flush().await?;
+                Ok::<_, std::io::Error>(subject)
+            };
+            let (client, server) = tokio::join!(client, server);
+            match authenticated {
+                true => {
+                    assert!(client.is_ok());
+                    assert_eq!(server?.as_deref(), Some("CN=alice"));
+                }
+                false => assert!(server.is_err()),
+            }
+        }
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
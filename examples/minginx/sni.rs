@@ -0,0 +1,145 @@
+use anyhow::bail;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// ClientHellos larger than this are refused rather than buffered.
+const MAX_HELLO: usize = 64 * 1024;
+const HANDSHAKE: u8 = 22;
+const CLIENT_HELLO: u8 = 1;
+const SERVER_NAME: u16 = 0;
+
+/// Read the client's TLS ClientHello without terminating TLS. Returns the
+/// raw records read, to be sent upstream first, and the SNI host name if
+/// the client sent one.
+pub async fn read_client_hello<R>(client: &mut R) -> anyhow::Result<(Vec<u8>, Option<String>)>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut raw = Vec::with_capacity(1024);
+    // The handshake message may be split across several records.
+    let mut handshake = Vec::new();
+    loop {
+        let mut header = [0; 5];
+        client.read_exact(&mut header).await?;
+        if header[0] != HANDSHAKE {
+            bail!("expected a TLS handshake record");
+        }
+        let len = usize::from(u16::from_be_bytes([header[3], header[4]]));
+        if raw.len() + header.len() + len > MAX_HELLO {
+            bail!("ClientHello exceeds {} bytes", MAX_HELLO);
+        }
+        raw.extend_from_slice(&header);
+        let start = raw.len();
+        raw.resize(start + len, 0);
+        client.read_exact(&mut raw[start..]).await?;
+        handshake.extend_from_slice(&raw[start..]);
+
+        if handshake.len() >= 4 {
+            if handshake[0] != CLIENT_HELLO {
+                bail!("expected a ClientHello");
+            }
+            let len = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]);
+            if handshake.len() >= 4 + len as usize {
+                let name = server_name(&handshake[4..4 + len as usize]);
+                return Ok((raw, name));
+            }
+        }
+    }
+}
+
+/// The `host_name` entry of the server_name extension (RFC 6066), if any.
+fn server_name(hello: &[u8]) -> Option<String> {
+    let mut rest = hello;
+    // legacy_version and random.
+    take(&mut rest, 2 + 32)?;
+    // session_id, cipher_suites, compression_methods.
+    take_vec(&mut rest, 1)?;
+    take_vec(&mut rest, 2)?;
+    take_vec(&mut rest, 1)?;
+    let mut extensions = take_vec(&mut rest, 2)?;
+    while !extensions.is_empty() {
+        let kind = u16::from_be_bytes(take(&mut extensions, 2)?.try_into().ok()?);
+        let mut data = take_vec(&mut extensions, 2)?;
+        if kind != SERVER_NAME {
+            continue;
+        }
+        let mut names = take_vec(&mut data, 2)?;
+        while !names.is_empty() {
+            let name_type = take(&mut names, 1)?[0];
+            let name = take_vec(&mut names, 2)?;
+            if name_type == 0 {
+                return std::str::from_utf8(name).ok().map(str::to_string);
+            }
+        }
+    }
+    None
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if buf.len() < n {
+        return None;
+    }
+    let (head, tail) = buf.split_at(n);
+    *buf = tail;
+    Some(head)
+}
+
+/// A vector prefixed by its big-endian length in `width` bytes.
+fn take_vec<'a>(buf: &mut &'a [u8], width: usize) -> Option<&'a [u8]> {
+    let len = take(buf, width)?
+        .iter()
+        .fold(0, |len, &b| len << 8 | usize::from(b));
+    take(buf, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal ClientHello for `name`, split over two records.
+    fn client_hello(name: &str) -> Vec<u8> {
+        let mut sni = vec![0];
+        sni.extend((name.len() as u16).to_be_bytes());
+        sni.extend(name.as_bytes());
+        let mut ext = Vec::new();
+        // An unrelated extension first: supported_versions.
+        ext.extend([0, 43, 0, 3, 2, 3, 4]);
+        ext.extend(SERVER_NAME.to_be_bytes());
+        ext.extend((sni.len() as u16 + 2).to_be_bytes());
+        ext.extend((sni.len() as u16).to_be_bytes());
+        ext.extend(sni);
+
+        let mut body = vec![3, 3];
+        body.extend([7; 32]);
+        body.extend([0, 0, 2, 0x13, 0x01, 1, 0]);
+        body.extend((ext.len() as u16).to_be_bytes());
+        body.extend(ext);
+        let mut handshake = vec![CLIENT_HELLO, 0];
+        handshake.extend((body.len() as u16).to_be_bytes());
+        handshake.extend(body);
+
+        let (first, second) = handshake.split_at(10);
+        let mut raw = Vec::new();
+        for part in [first, second] {
+            raw.extend([HANDSHAKE, 3, 1]);
+            raw.extend((part.len() as u16).to_be_bytes());
+            raw.extend(part);
+        }
+        raw
+    }
+
+    #[tokio::test]
+    async fn reads_sni_across_records() {
+        let hello = client_hello("api.example.com");
+        let mut input = hello.clone();
+        input.extend(b"encrypted");
+        let (raw, name) = read_client_hello(&mut input.as_slice()).await.unwrap();
+        assert_eq!(raw, hello);
+        assert_eq!(name.as_deref(), Some("api.example.com"));
+    }
+
+    #[tokio::test]
+    async fn rejects_plaintext() {
+        let mut input: &[u8] = b"GET / HTTP/1.1\r\n\r\n";
+        assert!(read_client_hello(&mut input).await.is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::io;
+use std::net::SocketAddr;
+
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+use crate::config::SocketConfig;
+
+/// Bind `addr` with the configured accept backlog.
+pub fn listen(addr: SocketAddr, config: &SocketConfig) -> io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(config.backlog)
+}
+
+/// Apply `nodelay` and `keepalive` to a client or upstream connection.
+pub fn tune(stream: &TcpStream, config: &SocketConfig) -> io::Result<()> {
+    stream.set_nodelay(config.nodelay)?;
+    if let Some(idle) = config.keepalive {
+        SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tune_sets_options() -> anyhow::Result<()> {
+        let config = SocketConfig {
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(75)),
+            ..Default::default()
+        };
+        let listener = listen("127.0.0.1:0".parse()?, &config)?;
+        let stream = TcpStream::connect(listener.local_addr()?).await?;
+        tune(&stream, &config)?;
+
+        assert!(stream.nodelay()?);
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive()?);
+        assert_eq!(socket.keepalive_time()?, Duration::from_secs(75));
+        Ok(())
+    }
+}
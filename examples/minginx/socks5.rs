@@ -0,0 +1,238 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{bail, ensure};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::config::Socks5User;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0x00;
+const PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE: u8 = 0xff;
+const CONNECT: u8 = 0x01;
+
+/// Where a client asked to be connected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Addr(SocketAddr),
+    /// Resolved by us, so clients needn't leak lookups.
+    Domain(String, u16),
+}
+
+impl Target {
+    pub async fn connect(&self) -> io::Result<TcpStream> {
+        match self {
+            Target::Addr(addr) => TcpStream::connect(addr).await,
+            Target::Domain(host, port) => TcpStream::connect((host.as_str(), *port)).await,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Addr(addr) => write!(f, "{}", addr),
+            Target::Domain(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// RFC 1928 reply codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    Succeeded = 0x00,
+    GeneralFailure = 0x01,
+    NetworkUnreachable = 0x03,
+    HostUnreachable = 0x04,
+    ConnectionRefused = 0x05,
+    CommandNotSupported = 0x07,
+    AddressTypeNotSupported = 0x08,
+}
+
+impl From<&io::Error> for Reply {
+    fn from(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::ConnectionRefused => Reply::ConnectionRefused,
+            io::ErrorKind::NetworkUnreachable => Reply::NetworkUnreachable,
+            io::ErrorKind::HostUnreachable | io::ErrorKind::TimedOut => Reply::HostUnreachable,
+            _ => Reply::GeneralFailure,
+        }
+    }
+}
+
+/// Agree on authentication, log the client in if `users` is not empty, and
+/// read its CONNECT request. Requests we can't serve are answered with the
+/// matching failure reply before returning the error.
+pub async fn handshake<S>(stream: &mut S, users: &[Socks5User]) -> anyhow::Result<Target>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ensure!(stream.read_u8().await? == VERSION, "not a SOCKS5 client");
+    let mut methods = vec![0; usize::from(stream.read_u8().await?)];
+    stream.read_exact(&mut methods).await?;
+    let method = match users.is_empty() {
+        true => NO_AUTH,
+        false => PASSWORD,
+    };
+    if !methods.contains(&method) {
+        stream.write_all(&[VERSION, NO_ACCEPTABLE]).await?;
+        bail!("client offers no acceptable authentication method");
+    }
+    stream.write_all(&[VERSION, method]).await?;
+    if method == PASSWORD {
+        login(stream, users).await?;
+    }
+
+    let mut request = [0; 4];
+    stream.read_exact(&mut request).await?;
+    let [version, command, _, address_type] = request;
+    ensure!(version == VERSION, "bad SOCKS5 request version {}", version);
+    let target = match address_type {
+        0x01 => {
+            let mut ip = [0; 4];
+            stream.read_exact(&mut ip).await?;
+            let ip = IpAddr::V4(Ipv4Addr::from(ip));
+            Target::Addr(SocketAddr::new(ip, stream.read_u16().await?))
+        }
+        0x03 => {
+            let mut host = vec![0; usize::from(stream.read_u8().await?)];
+            stream.read_exact(&mut host).await?;
+            let port = stream.read_u16().await?;
+            match String::from_utf8(host) {
+                Ok(host) if !host.is_empty() => Target::Domain(host, port),
+                _ => {
+                    reply(stream, Reply::GeneralFailure, None).await?;
+                    bail!("invalid domain name in SOCKS5 request");
+                }
+            }
+        }
+        0x04 => {
+            let mut ip = [0; 16];
+            stream.read_exact(&mut ip).await?;
+            let ip = IpAddr::V6(Ipv6Addr::from(ip));
+            Target::Addr(SocketAddr::new(ip, stream.read_u16().await?))
+        }
+        other => {
+            reply(stream, Reply::AddressTypeNotSupported, None).await?;
+            bail!("unsupported SOCKS5 address type {}", other);
+        }
+    };
+    if command != CONNECT {
+        reply(stream, Reply::CommandNotSupported, None).await?;
+        bail!("unsupported SOCKS5 command {}", command);
+    }
+    Ok(target)
+}
+
+/// RFC 1929 username/password subnegotiation.
+async fn login<S>(stream: &mut S, users: &[Socks5User]) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ensure!(stream.read_u8().await? == 1, "bad SOCKS5 login version");
+    let mut username = vec![0; usize::from(stream.read_u8().await?)];
+    stream.read_exact(&mut username).await?;
+    let mut password = vec![0; usize::from(stream.read_u8().await?)];
+    stream.read_exact(&mut password).await?;
+    let known = users
+        .iter()
+        .any(|u| u.username.as_bytes() == username && u.password.as_bytes() == password);
+    stream.write_all(&[1, if known { 0 } else { 1 }]).await?;
+    if !known {
+        bail!(
+            "SOCKS5 login failed for {:?}",
+            String::from_utf8_lossy(&username)
+        );
+    }
+    Ok(())
+}
+
+/// Answer the CONNECT request; `bound` is our end of the connection made
+/// for it, if any.
+pub async fn reply<S>(stream: &mut S, reply: Reply, bound: Option<SocketAddr>) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let bound = bound.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+    let mut out = vec![VERSION, reply as u8, 0];
+    match bound.ip() {
+        IpAddr::V4(ip) => {
+            out.push(0x01);
+            out.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            out.push(0x04);
+            out.extend_from_slice(&ip.octets());
+        }
+    }
+    out.extend_from_slice(&bound.port().to_be_bytes());
+    stream.write_all(&out).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alice() -> Vec<Socks5User> {
+        vec![Socks5User {
+            username: "alice".to_string(),
+            password: "secret".to_string(),
+        }]
+    }
+
+    #[tokio::test]
+    async fn connect_by_domain_without_auth() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        client.write_all(&[5, 1, 0]).await.unwrap();
+        let request = [&[5, 1, 0, 3, 11][..], b"example.com", &[0, 80]].concat();
+        client.write_all(&request).await.unwrap();
+
+        let target = handshake(&mut server, &[]).await.unwrap();
+        assert_eq!(target, Target::Domain("example.com".to_string(), 80));
+        let mut choice = [0; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [5, 0]);
+    }
+
+    #[tokio::test]
+    async fn login_checks_credentials() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        client.write_all(&[5, 2, 0, 2]).await.unwrap();
+        client.write_all(b"\x01\x05alice\x05wrong").await.unwrap();
+
+        let err = handshake(&mut server, &alice()).await.unwrap_err();
+        assert!(err.to_string().contains("login failed"));
+        let mut answers = [0; 4];
+        client.read_exact(&mut answers).await.unwrap();
+        assert_eq!(answers, [5, 2, 1, 1]);
+
+        let (mut client, mut server) = tokio::io::duplex(256);
+        client.write_all(&[5, 1, 2]).await.unwrap();
+        client.write_all(b"\x01\x05alice\x06secret").await.unwrap();
+        client
+            .write_all(&[5, 1, 0, 1, 10, 0, 0, 1, 0x1f, 0x90])
+            .await
+            .unwrap();
+        let target = handshake(&mut server, &alice()).await.unwrap();
+        assert_eq!(target, Target::Addr("10.0.0.1:8080".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn refuses_other_commands() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        client.write_all(&[5, 1, 0]).await.unwrap();
+        // BIND
+        client
+            .write_all(&[5, 2, 0, 1, 10, 0, 0, 1, 0, 80])
+            .await
+            .unwrap();
+
+        assert!(handshake(&mut server, &[]).await.is_err());
+        let mut answers = [0; 12];
+        client.read_exact(&mut answers).await.unwrap();
+        assert_eq!(&answers[2..4], [5, Reply::CommandNotSupported as u8]);
+    }
+}
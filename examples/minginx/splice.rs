@@ -0,0 +1,124 @@
+use std::io;
+use std::net::Shutdown;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use socket2::SockRef;
+use tokio::io::Interest;
+use tokio::net::TcpStream;
+
+use crate::transfer::Transfer;
+
+/// The default pipe capacity on Linux. Every splice into the pipe is drained
+/// before the next, so moving at most this much never blocks on the pipe.
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// Like [`crate::transfer::copy`], but moves the bytes socket to pipe to
+/// socket inside the kernel with splice(2), never copying them through
+/// userspace.
+pub async fn copy(
+    reader: &TcpStream,
+    writer: &TcpStream,
+    counter: &AtomicU64,
+    transfer: &Transfer,
+) -> io::Result<()> {
+    let (pipe_read, pipe_write) = pipe()?;
+    loop {
+        reader.readable().await?;
+        let read = reader.try_io(Interest::READABLE, || {
+            splice(reader.as_raw_fd(), pipe_write.as_raw_fd(), PIPE_SIZE)
+        });
+        let n = match read {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        };
+
+        let mut left = n;
+        while left > 0 {
+            writer.writable().await?;
+            let written = writer.try_io(Interest::WRITABLE, || {
+                splice(pipe_read.as_raw_fd(), writer.as_raw_fd(), left)
+            });
+            match written {
+                Ok(m) => left -= m,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+        transfer.touch();
+    }
+    SockRef::from(writer).shutdown(Shutdown::Write)
+}
+
+/// A non-blocking pipe, read end first.
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two descriptors pipe2 writes.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: pipe2 succeeded, so both descriptors are open and ours alone.
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+fn splice(from: RawFd, to: RawFd, len: usize) -> io::Result<usize> {
+    let flags = libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK;
+    // SAFETY: null offsets are allowed for sockets and pipes, and both
+    // descriptors outlive the call.
+    let n = unsafe {
+        libc::splice(
+            from,
+            std::ptr::null_mut(),
+            to,
+            std::ptr::null_mut(),
+            len,
+            flags,
+        )
+    };
+    match n {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        n => Ok(n as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    async fn pair() -> io::Result<(TcpStream, TcpStream)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let connect = TcpStream::connect(listener.local_addr()?);
+        let (connected, accepted) = tokio::join!(connect, listener.accept());
+        Ok((connected?, accepted?.0))
+    }
+
+    #[tokio::test]
+    async fn splices_between_sockets_until_eof() -> anyhow::Result<()> {
+        let (mut source, from) = pair().await?;
+        let (to, mut sink) = pair().await?;
+        let data: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+
+        let transfer = Transfer::new();
+        let send = async {
+            source.write_all(&data).await?;
+            source.shutdown().await
+        };
+        let relay = copy(&from, &to, &transfer.bytes_in, &transfer);
+        let mut received = Vec::new();
+        let receive = sink.read_to_end(&mut received);
+        let (sent, relayed, read) = tokio::join!(send, relay, receive);
+        sent?;
+        relayed?;
+        read?;
+
+        assert_eq!(received, data);
+        assert_eq!(transfer.bytes_in.load(Ordering::Relaxed), data.len() as u64);
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+//! End-to-end tests: a real proxy and mock upstreams on ephemeral ports.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::*;
+
+const WITHIN: Duration = Duration::from_secs(5);
+
+/// Start the default listener of `toml` on an ephemeral port, with `{N}` in
+/// the text replaced by the `N`th of `upstreams`.
+async fn start(toml: &str, upstreams: &[SocketAddr]) -> anyhow::Result<(SocketAddr, Arc<Proxy>)> {
+    let mut toml = toml.to_string();
+    for (i, addr) in upstreams.iter().enumerate() {
+        toml = toml.replace(&format!("{{{}}}", i), &addr.to_string());
+    }
+    let mut config: Config = toml::from_str(&toml)?;
+    config.listen = "127.0.0.1:0".parse()?;
+    config.validate()?;
+    let listener = config.listeners().remove(0);
+    let router = Router::new(&listener)?;
+    let config = Arc::new(config);
+    let socket = TcpListener::bind(listener.listen).await?;
+    let addr = socket.local_addr()?;
+    let proxy = Arc::new(Proxy {
+        tls: None,
+        metrics: Metrics::new(&metrics::registry()?, &listener.name)?,
+        name: listener.name,
+        mode: listener.mode,
+        acl: Arc::new(RwLock::new(Acl::new(&config.access))),
+        router,
+        limiter: Limiter::new(&config.limits),
+        rate_limiter: config
+            .rate_limit
+            .enabled
+            .then(|| Arc::new(RateLimiter::new(&config.rate_limit))),
+        mirror: None,
+        pool: None,
+        access_log: None,
+        config,
+    });
+    tokio::spawn(Arc::clone(&proxy).accept(socket));
+    Ok((addr, proxy))
+}
+
+/// An upstream that sends back whatever it receives.
+async fn echo_upstream() -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    Ok(addr)
+}
+
+/// An HTTP upstream answering every request with its head as the body.
+async fn head_upstream() -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut head = Vec::new();
+                while !head.ends_with(b"\r\n\r\n") {
+                    match stream.read_u8().await {
+                        Ok(byte) => head.push(byte),
+                        Err(_) => return,
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    head.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.write_all(&head).await;
+            });
+        }
+    });
+    Ok(addr)
+}
+
+/// An upstream that accepts connections and never says anything.
+async fn silent_upstream() -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+    Ok(addr)
+}
+
+/// An address nothing listens on.
+async fn dead_upstream() -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    listener.local_addr().map_err(Into::into)
+}
+
+/// Send `request`, close our side, and read everything that comes back.
+async fn exchange(proxy: SocketAddr, request: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(proxy).await?;
+    let (mut reader, mut writer) = stream.split();
+    let send = async {
+        writer.write_all(request).await?;
+        writer.shutdown().await
+    };
+    let mut response = Vec::new();
+    let receive = reader.read_to_end(&mut response);
+    tokio::time::timeout(WITHIN, async { tokio::try_join!(send, receive) }).await??;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn tcp_relays_large_payload_both_ways_intact() -> anyhow::Result<()> {
+    let upstream = echo_upstream().await?;
+    let (proxy, _) = start(r#"upstreams = ["{0}"]"#, &[upstream]).await?;
+
+    let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let echoed = exchange(proxy, &payload).await?;
+    assert_eq!(echoed.len(), payload.len());
+    assert!(echoed == payload, "payload corrupted in transit");
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_mode_injects_forwarding_headers() -> anyhow::Result<()> {
+    let upstream = head_upstream().await?;
+    let toml = r#"
+        mode = "http"
+        upstreams = ["{0}"]
+        [http]
+        host = "backend"
+    "#;
+    let (proxy, _) = start(toml, &[upstream]).await?;
+
+    let request = b"GET /x HTTP/1.1\r\nHost: app\r\nX-Forwarded-For: 10.1.1.1\r\nConnection: keep-alive\r\nX-Forwarded-Proto: https\r\n\r\n";
+    let response = String::from_utf8(exchange(proxy, request).await?)?;
+    let (status, seen) = response.split_once("\r\n\r\n").unwrap();
+    assert!(status.starts_with("HTTP/1.1 200 OK"));
+    assert!(seen.starts_with("GET /x HTTP/1.1\r\n"));
+    for header in [
+        "Host: backend\r\n",
+        "X-Forwarded-For: 10.1.1.1, 127.0.0.1\r\n",
+        "X-Forwarded-Proto: http\r\n",
+        "Via: 1.1 minginx\r\n",
+        "Connection: close\r\n",
+    ] {
+        assert!(
+            seen.contains(header),
+            "{:?} missing from {:?}",
+            header,
+            seen
+        );
+    }
+    assert!(!seen.contains("Host: app"));
+    assert!(!seen.contains("keep-alive"));
+    assert!(!seen.contains("https"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limited_clients_are_refused_before_sending_anything() -> anyhow::Result<()> {
+    let upstream = head_upstream().await?;
+    let toml = r#"
+        mode = "http"
+        upstreams = ["{0}"]
+        [rate_limit]
+        enabled = true
+        rate = 0.001
+        burst = 1
+    "#;
+    let (addr, proxy) = start(toml, &[upstream]).await?;
+
+    let response = exchange(addr, b"GET / HTTP/1.1\r\nHost: app\r\n\r\n").await?;
+    assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+
+    // no request at all: the answer can't have waited for one
+    let mut stream = TcpStream::connect(addr).await?;
+    let mut response = Vec::new();
+    tokio::time::timeout(WITHIN, stream.read_to_end(&mut response)).await??;
+    assert_eq!(response, TOO_MANY_REQUESTS);
+    assert_eq!(proxy.metrics.rate_limited.get(), 1);
+    // and it never took a connection slot
+    assert_eq!(proxy.metrics.connections.get(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn fails_over_once_health_checks_mark_upstream_down() -> anyhow::Result<()> {
+    let dead = dead_upstream().await?;
+    let live = echo_upstream().await?;
+    let toml = r#"
+        upstreams = ["{0}", "{1}"]
+        [health]
+        interval = "20ms"
+        timeout = "100ms"
+        fall = 1
+    "#;
+    let (proxy, state) = start(toml, &[dead, live]).await?;
+
+    // Round-robin sends one of these to the dead upstream.
+    let mut answered = 0;
+    for _ in 0..2 {
+        if exchange(proxy, b"ping").await.is_ok_and(|r| r == b"ping") {
+            answered += 1;
+        }
+    }
+    assert_eq!(answered, 1);
+    let group = Arc::clone(state.router.default());
+    assert_eq!(group.servers()[0].failures(), 1);
+
+    let mut supervisor = Supervisor::new();
+    health::spawn(
+        &mut supervisor,
+        "health check".to_string(),
+        Arc::clone(&group),
+        state.config.health.clone(),
+    );
+    let started = Instant::now();
+    while group.servers()[0].is_healthy() {
+        assert!(
+            started.elapsed() < WITHIN,
+            "dead upstream never marked down"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    for _ in 0..4 {
+        assert_eq!(exchange(proxy, b"ping").await?, b"ping");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn idle_timeout_closes_quiet_connections() -> anyhow::Result<()> {
+    let upstream = silent_upstream().await?;
+    let toml = r#"
+        upstreams = ["{0}"]
+        [timeouts]
+        idle = "200ms"
+    "#;
+    let (proxy, _) = start(toml, &[upstream]).await?;
+
+    let mut stream = TcpStream::connect(proxy).await?;
+    stream.write_all(b"hello?").await?;
+    let started = Instant::now();
+    let mut buf = [0; 16];
+    let read = tokio::time::timeout(WITHIN, stream.read(&mut buf)).await?;
+    assert!(
+        matches!(read, Ok(0) | Err(_)),
+        "expected the proxy to hang up"
+    );
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_timeout_drops_clients_that_send_no_request() -> anyhow::Result<()> {
+    let upstream = head_upstream().await?;
+    let toml = r#"
+        mode = "http"
+        upstreams = ["{0}"]
+        [timeouts]
+        read = "200ms"
+    "#;
+    let (proxy, _) = start(toml, &[upstream]).await?;
+
+    let mut stream = TcpStream::connect(proxy).await?;
+    stream.write_all(b"GET / HTTP/1.1\r\n").await?;
+    let mut buf = [0; 16];
+    let read = tokio::time::timeout(WITHIN, stream.read(&mut buf)).await?;
+    assert!(
+        matches!(read, Ok(0) | Err(_)),
+        "expected the proxy to hang up"
+    );
+    Ok(())
+}
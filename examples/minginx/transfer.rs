@@ -0,0 +1,133 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Byte counters and last-activity clock shared by both copy directions of
+/// one proxied connection.
+#[derive(Debug)]
+pub struct Transfer {
+    origin: Instant,
+    /// Milliseconds since `origin` when bytes last moved either way.
+    last_activity: AtomicU64,
+    /// Client to upstream.
+    pub bytes_in: AtomicU64,
+    /// Upstream to client.
+    pub bytes_out: AtomicU64,
+    /// Size of the buffer each copy direction reads into.
+    pub buffer_size: usize,
+}
+
+impl Transfer {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            last_activity: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+            buffer_size: 8 * 1024,
+        }
+    }
+
+    pub fn with_buffer_size(buffer_size: usize) -> Self {
+        Self {
+            buffer_size,
+            ..Self::new()
+        }
+    }
+
+    pub fn touch(&self) {
+        let now = self.origin.elapsed().as_millis() as u64;
+        self.last_activity.store(now, Ordering::Relaxed);
+    }
+
+    fn since_activity(&self) -> Duration {
+        let last = Duration::from_millis(self.last_activity.load(Ordering::Relaxed));
+        self.origin.elapsed().saturating_sub(last)
+    }
+
+    /// Resolves once nothing has moved in either direction for `limit`.
+    pub async fn idle(&self, limit: Duration) {
+        loop {
+            let since = self.since_activity();
+            if since >= limit {
+                return;
+            }
+            tokio::time::sleep(limit - since).await;
+        }
+    }
+}
+
+/// Like `tokio::io::copy`, but counts into `counter` and marks `transfer`
+/// active after every chunk, then shuts the writer down on EOF.
+pub async fn copy<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    counter: &AtomicU64,
+    transfer: &Transfer,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    copy_some(reader, writer, u64::MAX, counter, transfer).await?;
+    writer.shutdown().await
+}
+
+/// [`copy`] up to `limit` bytes, leaving the writer open. Returns how many
+/// were copied, fewer only if the reader hit EOF first.
+pub async fn copy_some<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    limit: u64,
+    counter: &AtomicU64,
+    transfer: &Transfer,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut buf = vec![0; transfer.buffer_size];
+    let mut copied = 0;
+    while copied < limit {
+        let want = (limit - copied).min(buf.len() as u64) as usize;
+        let n = reader.read(&mut buf[..want]).await?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buf[..n]).await?;
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+        transfer.touch();
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn idle_waits_for_quiet_period() {
+        let transfer = Transfer::new();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        transfer.touch();
+
+        let started = Instant::now();
+        transfer.idle(Duration::from_millis(50)).await;
+        assert!(started.elapsed() >= Duration::from_millis(45));
+    }
+
+    #[tokio::test]
+    async fn copy_counts_bytes() {
+        let transfer = Transfer::new();
+        let mut reader: &[u8] = b"hello";
+        let mut writer = Vec::new();
+        copy(&mut reader, &mut writer, &transfer.bytes_in, &transfer)
+            .await
+            .unwrap();
+        assert_eq!(writer, b"hello");
+        assert_eq!(transfer.bytes_in.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,146 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::upstream::Upstream;
+
+/// Largest datagram we relay; anything bigger is truncated by the kernel.
+const MAX_DATAGRAM: usize = 64 * 1024;
+/// Datagrams queued per session before new ones are dropped.
+const SESSION_QUEUE: usize = 64;
+
+type Sessions = DashMap<SocketAddr, mpsc::Sender<Bytes>>;
+
+/// Relay datagrams between clients on `listen` and the upstreams. Each
+/// client address gets a session with its own connected socket towards the
+/// upstream it was balanced to, so replies find their way back; sessions
+/// end after `idle` without traffic in either direction.
+pub async fn serve(
+    listen: SocketAddr,
+    upstream: Arc<Upstream>,
+    idle: Duration,
+) -> anyhow::Result<()> {
+    let socket = Arc::new(UdpSocket::bind(listen).await?);
+    info!("udp listen: {}", listen);
+    let sessions = Arc::new(Sessions::new());
+    let mut buf = vec![0; MAX_DATAGRAM];
+    loop {
+        let (n, client) = socket.recv_from(&mut buf).await?;
+        let datagram = Bytes::copy_from_slice(&buf[..n]);
+        let tx = sessions
+            .entry(client)
+            .or_insert_with(|| {
+                let (tx, rx) = mpsc::channel(SESSION_QUEUE);
+                let session = Session {
+                    client,
+                    listener: Arc::clone(&socket),
+                    sessions: Arc::clone(&sessions),
+                    idle,
+                };
+                tokio::spawn(session.run(Arc::clone(&upstream), rx));
+                tx
+            })
+            .clone();
+        if tx.try_send(datagram).is_err() {
+            debug!(
+                "udp session for {} is busy or closing, dropping datagram",
+                client
+            );
+        }
+    }
+}
+
+struct Session {
+    client: SocketAddr,
+    listener: Arc<UdpSocket>,
+    sessions: Arc<Sessions>,
+    idle: Duration,
+}
+
+impl Session {
+    async fn run(self, upstream: Arc<Upstream>, rx: mpsc::Receiver<Bytes>) {
+        if let Err(e) = self.relay(&upstream, rx).await {
+            warn!("udp session for {}: {:#}", self.client, e);
+        }
+        self.sessions.remove(&self.client);
+        debug!("udp session for {} ended", self.client);
+    }
+
+    async fn relay(
+        &self,
+        upstream: &Upstream,
+        mut rx: mpsc::Receiver<Bytes>,
+    ) -> anyhow::Result<()> {
+        let server = upstream
+            .pick(self.client.ip())
+            .ok_or_else(|| anyhow::anyhow!("no healthy upstream"))?;
+        anyhow::ensure!(
+            server.tls.is_none(),
+            "cannot relay udp to tls upstream {}",
+            server.addr
+        );
+
+        let local: SocketAddr = match self.client {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+            SocketAddr::V6(_) => "[::]:0".parse()?,
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(&server.addr).await?;
+        info!("udp {} -> {}", self.client, server.addr);
+
+        let mut buf = vec![0; MAX_DATAGRAM];
+        loop {
+            tokio::select! {
+                datagram = rx.recv() => match datagram {
+                    Some(datagram) => {
+                        socket.send(&datagram).await?;
+                    }
+                    None => return Ok(()),
+                },
+                n = socket.recv(&mut buf) => {
+                    self.listener.send_to(&buf[..n?], self.client).await?;
+                }
+                _ = tokio::time::sleep(self.idle) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Balance;
+
+    #[tokio::test]
+    async fn relays_replies_back_to_client() -> anyhow::Result<()> {
+        let echo = UdpSocket::bind("127.0.0.1:0").await?;
+        let upstream = Upstream::new(
+            vec![echo.local_addr()?.to_string().into()],
+            Balance::RoundRobin,
+        )?;
+        tokio::spawn(async move {
+            let mut buf = [0; 64];
+            let (n, from) = echo.recv_from(&mut buf).await.unwrap();
+            echo.send_to(&buf[..n], from).await.unwrap();
+        });
+
+        let probe = std::net::UdpSocket::bind("127.0.0.1:0")?;
+        let listen = probe.local_addr()?;
+        drop(probe);
+        tokio::spawn(serve(listen, Arc::new(upstream), Duration::from_secs(1)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        client.send_to(b"ping", listen).await?;
+        let mut buf = [0; 64];
+        let n = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buf)).await??;
+        assert_eq!(&buf[..n], b"ping");
+        Ok(())
+    }
+}
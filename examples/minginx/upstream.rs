@@ -0,0 +1,422 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::Context;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::config::{Balance, UpstreamConfig};
+
+/// Picks the upstream for each new connection according to [`Balance`],
+/// skipping any server the health checker has taken out of rotation.
+#[derive(Debug)]
+pub struct Upstream {
+    servers: Vec<Server>,
+    balance: Balance,
+    /// Running "current weight" per server, for round-robin.
+    current: Mutex<Vec<i64>>,
+    /// Where least-connections starts scanning, so ties rotate.
+    next: AtomicUsize,
+}
+
+#[derive(Debug)]
+pub struct Server {
+    pub addr: String,
+    pub weight: u32,
+    pub tls: Option<Tls>,
+    healthy: AtomicBool,
+    /// Taken out of rotation by an operator; in-flight connections finish.
+    drained: AtomicBool,
+    active: AtomicUsize,
+    /// Connections handed out and connects that failed, since startup.
+    connections: AtomicU64,
+    failures: AtomicU64,
+    /// What `addr` last resolved to; empty until the first lookup, in which
+    /// case connects resolve `addr` themselves.
+    resolved: RwLock<Vec<SocketAddr>>,
+}
+
+/// How many times `ip_hash` rehashes past unhealthy servers before giving
+/// up on stickiness and falling back to round-robin.
+const IP_HASH_TRIES: u32 = 20;
+
+/// A picked server, counted as in flight until dropped.
+#[derive(Debug)]
+pub struct Lease<'a> {
+    server: &'a Server,
+}
+
+/// Client side of a re-encrypted upstream connection.
+pub struct Tls {
+    connector: TlsConnector,
+    name: ServerName<'static>,
+}
+
+impl Upstream {
+    /// `servers` is only empty for `socks5` listeners, which never pick.
+    /// Fails if a TLS upstream's CA bundle or server name is unusable.
+    pub fn new(servers: Vec<UpstreamConfig>, balance: Balance) -> anyhow::Result<Self> {
+        let servers = servers
+            .into_iter()
+            .map(|config| {
+                let tls = match config.tls {
+                    true => Some(Tls::new(&config)?),
+                    false => None,
+                };
+                let resolved = config.addr.parse().into_iter().collect();
+                Ok(Server {
+                    resolved: RwLock::new(resolved),
+                    addr: config.addr,
+                    weight: config.weight,
+                    tls,
+                    healthy: AtomicBool::new(true),
+                    drained: AtomicBool::new(false),
+                    active: AtomicUsize::new(0),
+                    connections: AtomicU64::new(0),
+                    failures: AtomicU64::new(0),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            current: Mutex::new(vec![0; servers.len()]),
+            servers,
+            balance,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// `None` when every server is currently unhealthy or drained. `client`
+    /// only matters for `ip_hash`.
+    pub fn pick(&self, client: IpAddr) -> Option<Lease<'_>> {
+        let server = match self.balance {
+            Balance::RoundRobin => self.round_robin()?,
+            Balance::LeastConn => self.least_conn()?,
+            Balance::IpHash => self.ip_hash(client)?,
+        };
+        server.active.fetch_add(1, Ordering::Relaxed);
+        server.connections.fetch_add(1, Ordering::Relaxed);
+        Some(Lease { server })
+    }
+
+    /// Smooth weighted round-robin, as in nginx: with equal weights this is
+    /// plain rotation, with unequal ones the heavier servers' turns are
+    /// spread out rather than bunched.
+    fn round_robin(&self) -> Option<&Server> {
+        let mut current = self.current.lock().unwrap();
+        let mut total = 0;
+        let mut best: Option<usize> = None;
+        for (i, server) in self.servers.iter().enumerate() {
+            if !server.is_available() {
+                continue;
+            }
+            current[i] += i64::from(server.weight);
+            total += i64::from(server.weight);
+            if best.is_none_or(|b| current[i] > current[b]) {
+                best = Some(i);
+            }
+        }
+        let best = best?;
+        current[best] -= total;
+        Some(&self.servers[best])
+    }
+
+    /// Fewest in-flight connections relative to weight; ties go to whichever
+    /// comes first from a rotating starting point.
+    fn least_conn(&self) -> Option<&Server> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let len = self.servers.len();
+        let load = |server: &Server| (server.active() as u64, u64::from(server.weight));
+        (0..len)
+            .map(|i| &self.servers[(start + i) % len])
+            .filter(|server| server.is_available())
+            .reduce(|best, server| {
+                let (best_active, best_weight) = load(best);
+                let (active, weight) = load(server);
+                match active * best_weight < best_active * weight {
+                    true => server,
+                    false => best,
+                }
+            })
+    }
+
+    /// Hash the client address onto the weighted server list; if that lands
+    /// on an unavailable server, rehash so the client moves somewhere stable
+    /// rather than somewhere random.
+    fn ip_hash(&self, client: IpAddr) -> Option<&Server> {
+        let total: u64 = self.servers.iter().map(|s| u64::from(s.weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        for attempt in 0..IP_HASH_TRIES {
+            let mut hasher = DefaultHasher::new();
+            (client, attempt).hash(&mut hasher);
+            let mut point = hasher.finish() % total;
+            let server = self
+                .servers
+                .iter()
+                .find(|server| {
+                    let weight = u64::from(server.weight);
+                    if point < weight {
+                        return true;
+                    }
+                    point -= weight;
+                    false
+                })
+                .expect("point is below the total weight");
+            if server.is_available() {
+                return Some(server);
+            }
+        }
+        self.round_robin()
+    }
+
+    pub fn servers(&self) -> &[Server] {
+        &self.servers
+    }
+}
+
+impl Server {
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::Relaxed);
+    }
+
+    pub fn is_drained(&self) -> bool {
+        self.drained.load(Ordering::Relaxed)
+    }
+
+    pub fn set_drained(&self, drained: bool) {
+        self.drained.store(drained, Ordering::Relaxed);
+    }
+
+    /// Healthy and not drained, so new connections may go here.
+    pub fn is_available(&self) -> bool {
+        self.is_healthy() && !self.is_drained()
+    }
+
+    /// Connections currently proxied to this server.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    pub fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether `addr` is a host name rather than a literal address.
+    pub fn is_named(&self) -> bool {
+        self.addr.parse::<SocketAddr>().is_err()
+    }
+
+    pub fn resolved(&self) -> Vec<SocketAddr> {
+        self.resolved.read().unwrap().clone()
+    }
+
+    /// Replace the resolved addresses, returning whether they changed.
+    pub fn set_resolved(&self, mut addrs: Vec<SocketAddr>) -> bool {
+        addrs.sort();
+        addrs.dedup();
+        let mut resolved = self.resolved.write().unwrap();
+        if *resolved == addrs {
+            return false;
+        }
+        *resolved = addrs;
+        true
+    }
+
+    /// Connect to the resolved addresses in turn, or to `addr` by name if
+    /// it hasn't been resolved yet.
+    pub async fn connect(&self) -> std::io::Result<TcpStream> {
+        let resolved = self.resolved();
+        match resolved.is_empty() {
+            true => TcpStream::connect(&self.addr).await,
+            false => TcpStream::connect(&resolved[..]).await,
+        }
+    }
+}
+
+impl Deref for Lease<'_> {
+    type Target = Server;
+
+    fn deref(&self) -> &Server {
+        self.server
+    }
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        self.server.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Tls {
+    fn new(config: &UpstreamConfig) -> anyhow::Result<Self> {
+        let roots = match &config.ca {
+            Some(path) => load_ca(path)?,
+            None => RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            },
+        };
+        let client = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let host = match &config.sni {
+            Some(sni) => sni.as_str(),
+            None => config.addr.rsplit_once(':').map_or("", |(host, _)| host),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let name = ServerName::try_from(host.to_string())
+            .with_context(|| format!("invalid TLS server name {:?}", host))?;
+
+        Ok(Self {
+            connector: TlsConnector::from(Arc::new(client)),
+            name,
+        })
+    }
+
+    pub async fn connect(&self, stream: TcpStream) -> std::io::Result<TlsStream<TcpStream>> {
+        self.connector.connect(self.name.clone(), stream).await
+    }
+}
+
+impl fmt::Debug for Tls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tls").field("name", &self.name).finish()
+    }
+}
+
+pub fn load_ca(path: &Path) -> anyhow::Result<RootCertStore> {
+    let pem = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+        roots.add(cert?)?;
+    }
+    anyhow::ensure!(!roots.is_empty(), "no certificates in {}", path.display());
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    fn upstream(addrs: &[&str], balance: Balance) -> Upstream {
+        let servers = addrs.iter().map(|a| a.to_string().into()).collect();
+        Upstream::new(servers, balance).unwrap()
+    }
+
+    #[test]
+    fn pick_rotates_through_servers() {
+        let upstream = upstream(&["a:1", "b:2"], Balance::RoundRobin);
+        let picks: Vec<_> = (0..5)
+            .map(|_| upstream.pick(CLIENT).unwrap().addr.clone())
+            .collect();
+        assert_eq!(picks, ["a:1", "b:2", "a:1", "b:2", "a:1"]);
+    }
+
+    #[test]
+    fn pick_skips_unhealthy_servers() {
+        let upstream = upstream(&["a:1", "b:2"], Balance::RoundRobin);
+        upstream.servers()[0].set_healthy(false);
+        assert_eq!(upstream.pick(CLIENT).unwrap().addr, "b:2");
+        assert_eq!(upstream.pick(CLIENT).unwrap().addr, "b:2");
+
+        upstream.servers()[1].set_healthy(false);
+        assert!(upstream.pick(CLIENT).is_none());
+    }
+
+    #[test]
+    fn drained_servers_finish_but_get_nothing_new() {
+        let upstream = upstream(&["a:1", "b:2"], Balance::RoundRobin);
+        let lease = upstream.pick(CLIENT).unwrap();
+        upstream.servers()[0].set_drained(true);
+        assert_eq!(upstream.pick(CLIENT).unwrap().addr, "b:2");
+        assert_eq!(upstream.pick(CLIENT).unwrap().addr, "b:2");
+        assert_eq!(upstream.servers()[0].active(), 1);
+        drop(lease);
+
+        upstream.servers()[0].set_drained(false);
+        let picks: Vec<_> = (0..2)
+            .map(|_| upstream.pick(CLIENT).unwrap().addr.clone())
+            .collect();
+        assert!(picks.contains(&"a:1".to_string()));
+        assert_eq!(upstream.servers()[0].connections(), 2);
+    }
+
+    #[test]
+    fn pick_spreads_weighted_turns() {
+        let mut heavy = UpstreamConfig::from("a:1".to_string());
+        heavy.weight = 5;
+        let upstream = Upstream::new(
+            vec![heavy, "b:2".to_string().into(), "c:3".to_string().into()],
+            Balance::RoundRobin,
+        )
+        .unwrap();
+        let picks: Vec<_> = (0..7)
+            .map(|_| upstream.pick(CLIENT).unwrap().addr.clone())
+            .collect();
+        assert_eq!(picks, ["a:1", "a:1", "b:2", "a:1", "c:3", "a:1", "a:1"]);
+    }
+
+    #[test]
+    fn least_conn_avoids_busy_servers() {
+        let upstream = upstream(&["a:1", "b:2", "c:3"], Balance::LeastConn);
+        let a = upstream.pick(CLIENT).unwrap();
+        let b = upstream.pick(CLIENT).unwrap();
+        assert_eq!((a.addr.as_str(), b.addr.as_str()), ("a:1", "b:2"));
+
+        assert_eq!(upstream.pick(CLIENT).unwrap().addr, "c:3");
+        drop(a);
+        assert_eq!(upstream.pick(CLIENT).unwrap().addr, "a:1");
+        assert_eq!(upstream.servers()[1].active(), 1);
+    }
+
+    #[test]
+    fn ip_hash_is_sticky_and_rehashes_on_failure() {
+        let upstream = upstream(&["a:1", "b:2", "c:3"], Balance::IpHash);
+        let client: IpAddr = "10.1.2.3".parse().unwrap();
+        let first = upstream.pick(client).unwrap().addr.clone();
+        for _ in 0..5 {
+            assert_eq!(upstream.pick(client).unwrap().addr, first);
+        }
+
+        let pinned = upstream.servers().iter().find(|s| s.addr == first).unwrap();
+        pinned.set_healthy(false);
+        let moved = upstream.pick(client).unwrap().addr.clone();
+        assert_ne!(moved, first);
+        assert_eq!(upstream.pick(client).unwrap().addr, moved);
+    }
+
+    #[test]
+    fn tls_server_name_defaults_to_host() {
+        let config = UpstreamConfig {
+            tls: true,
+            ..UpstreamConfig::from("example.com:443".to_string())
+        };
+        let upstream = Upstream::new(vec![config], Balance::RoundRobin).unwrap();
+        let tls = upstream.servers()[0].tls.as_ref().unwrap();
+        assert_eq!(tls.name, ServerName::try_from("example.com").unwrap());
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::config::{ListenerConfig, Mode, RouteRule};
+use crate::upstream::Upstream;
+
+/// Chooses the upstream group for a request by the `routes` rules on its
+/// headers, then by its `Host` header, or the SNI name in `tls` mode;
+/// anything unmatched goes to the top-level `upstreams`, or the active one
+/// of the listener's `groups`.
+#[derive(Debug)]
+pub struct Router {
+    default: Arc<Switch>,
+    rules: Vec<(RouteRule, Arc<Upstream>)>,
+    exact: HashMap<String, Arc<Upstream>>,
+    /// `(".example.com", group)` for `*.example.com`, longest suffix first.
+    wildcards: Vec<(String, Arc<Upstream>)>,
+}
+
+impl Router {
+    pub fn new(config: &ListenerConfig) -> anyhow::Result<Self> {
+        let default = Arc::new(Switch::new(config)?);
+        let mut exact = HashMap::new();
+        let mut wildcards = Vec::new();
+        for server in &config.virtual_servers {
+            let group = Arc::new(Upstream::new(server.upstreams.clone(), server.balance)?);
+            for host in &server.hosts {
+                let host = host.to_ascii_lowercase();
+                match host.strip_prefix('*') {
+                    Some(suffix) => wildcards.push((suffix.to_string(), Arc::clone(&group))),
+                    None => {
+                        exact.insert(host, Arc::clone(&group));
+                    }
+                }
+            }
+        }
+        wildcards.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        let rules = config
+            .routes
+            .iter()
+            .map(|rule| {
+                let group = Upstream::new(rule.upstreams.clone(), rule.balance)?;
+                Ok((rule.clone(), Arc::new(group)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            default,
+            rules,
+            exact,
+            wildcards,
+        })
+    }
+
+    /// `host` as sent by the client, port and case included; `headers` are
+    /// the request's, empty outside `http` mode.
+    pub fn route(&self, host: Option<&str>, headers: &[(String, String)]) -> &Arc<Upstream> {
+        if let Some((_, group)) = self.rules.iter().find(|(rule, _)| matches(rule, headers)) {
+            return group;
+        }
+        let Some(host) = host else {
+            return self.default();
+        };
+        let host = strip_port(host).trim_end_matches('.').to_ascii_lowercase();
+        if let Some(group) = self.exact.get(&host) {
+            return group;
+        }
+        self.wildcards
+            .iter()
+            .find(|(suffix, _)| host.len() > suffix.len() && host.ends_with(suffix.as_str()))
+            .map_or(self.default(), |(_, group)| group)
+    }
+
+    pub fn default(&self) -> &Arc<Upstream> {
+        self.default.active()
+    }
+
+    pub fn switch(&self) -> &Arc<Switch> {
+        &self.default
+    }
+
+    /// Every upstream group: the default ones first, active or not.
+    pub fn groups(&self) -> Vec<&Arc<Upstream>> {
+        let mut groups: Vec<_> = self.default.groups().iter().map(|(_, g)| g).collect();
+        for group in self
+            .rules
+            .iter()
+            .map(|(_, g)| g)
+            .chain(self.exact.values())
+            .chain(self.wildcards.iter().map(|(_, g)| g))
+        {
+            if !groups.iter().any(|known| Arc::ptr_eq(known, group)) {
+                groups.push(group);
+            }
+        }
+        groups
+    }
+}
+
+/// A listener's default upstream group: its top-level `upstreams`, named
+/// "default", or whichever of its `groups` is active. Switching only steers
+/// new connections, so the old group drains as its connections finish.
+#[derive(Debug)]
+pub struct Switch {
+    groups: Vec<(String, Arc<Upstream>)>,
+    active: AtomicUsize,
+}
+
+impl Switch {
+    fn new(config: &ListenerConfig) -> anyhow::Result<Self> {
+        let groups = match (config.mode, config.groups.is_empty()) {
+            (Mode::Socks5, _) => vec![("default".to_string(), Vec::new(), config.balance)],
+            (_, true) => vec![(
+                "default".to_string(),
+                config.upstreams.clone(),
+                config.balance,
+            )],
+            (_, false) => config
+                .groups
+                .iter()
+                .map(|g| (g.name.clone(), g.upstreams.clone(), g.balance))
+                .collect(),
+        };
+        let groups = groups
+            .into_iter()
+            .map(|(name, upstreams, balance)| {
+                Ok((name, Arc::new(Upstream::new(upstreams, balance)?)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let active = config
+            .active_group
+            .as_ref()
+            .and_then(|active| groups.iter().position(|(name, _)| name == active))
+            .unwrap_or(0);
+        Ok(Self {
+            groups,
+            active: AtomicUsize::new(active),
+        })
+    }
+
+    pub fn active(&self) -> &Arc<Upstream> {
+        &self.groups[self.active.load(Ordering::Relaxed)].1
+    }
+
+    pub fn active_name(&self) -> &str {
+        &self.groups[self.active.load(Ordering::Relaxed)].0
+    }
+
+    pub fn groups(&self) -> &[(String, Arc<Upstream>)] {
+        &self.groups
+    }
+
+    /// Send new connections to the group called `name`. Returns the group
+    /// that had them until now, or `None` if there is no such group.
+    pub fn activate(&self, name: &str) -> Option<&(String, Arc<Upstream>)> {
+        let next = self.groups.iter().position(|(n, _)| n == name)?;
+        let previous = self.active.swap(next, Ordering::Relaxed);
+        Some(&self.groups[previous])
+    }
+}
+
+fn matches(rule: &RouteRule, headers: &[(String, String)]) -> bool {
+    let name = rule.header.as_deref().unwrap_or("cookie");
+    let named = headers
+        .iter()
+        .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str());
+    let mut values: Vec<&str> = match &rule.cookie {
+        None => named.collect(),
+        Some(cookie) => named
+            .flat_map(|pairs| pairs.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .filter(|(name, _)| name == cookie)
+            .map(|(_, value)| value)
+            .collect(),
+    };
+    if let Some(expected) = &rule.equals {
+        values.retain(|value| value == expected);
+    }
+    if let Some(prefix) = &rule.prefix {
+        values.retain(|value| value.starts_with(prefix.as_str()));
+    }
+    values.is_empty() != rule.present
+}
+
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host.split_once(']').map_or(host, |(ip, _)| &ip[1..]);
+    }
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    use super::*;
+
+    #[test]
+    fn exact_beats_wildcard_and_longest_wildcard_wins() {
+        let config: Config = toml::from_str(
+            r#"
+            mode = "http"
+            upstreams = ["default:80"]
+
+            [[virtual_servers]]
+            hosts = ["*.example.com"]
+            upstreams = ["any:80"]
+
+            [[virtual_servers]]
+            hosts = ["*.api.example.com", "www.example.com"]
+            upstreams = ["api:80"]
+            "#,
+        )
+        .unwrap();
+        let router = Router::new(&config.listeners()[0]).unwrap();
+        let routed = |host| router.route(host, &[]).servers()[0].addr.as_str();
+
+        assert_eq!(routed(Some("WWW.example.com:8080")), "api:80");
+        assert_eq!(routed(Some("v1.api.example.com")), "api:80");
+        assert_eq!(routed(Some("shop.example.com")), "any:80");
+        assert_eq!(routed(Some("example.com")), "default:80");
+        assert_eq!(routed(None), "default:80");
+        assert_eq!(router.groups().len(), 3);
+    }
+
+    #[test]
+    fn header_and_cookie_rules_come_first_in_order() {
+        let config: Config = toml::from_str(
+            r#"
+            mode = "http"
+            upstreams = ["default:80"]
+
+            [[virtual_servers]]
+            hosts = ["app.example.com"]
+            upstreams = ["app:80"]
+
+            [[routes]]
+            cookie = "canary"
+            equals = "1"
+            upstreams = ["canary:80"]
+
+            [[routes]]
+            header = "X-Tenant"
+            prefix = "acme"
+            upstreams = ["acme:80"]
+
+            [[routes]]
+            header = "authorization"
+            present = false
+            upstreams = ["public:80"]
+            "#,
+        )
+        .unwrap();
+        let router = Router::new(&config.listeners()[0]).unwrap();
+        let routed = |headers: &[(&str, &str)]| {
+            let headers: Vec<_> = headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect();
+            let group = router.route(Some("app.example.com"), &headers);
+            group.servers()[0].addr.clone()
+        };
+
+        let auth = ("Authorization", "Bearer x");
+        assert_eq!(routed(&[auth, ("Cookie", "a=b; canary=1")]), "canary:80");
+        assert_eq!(routed(&[auth, ("Cookie", "canary=0")]), "app:80");
+        assert_eq!(routed(&[auth, ("x-tenant", "acme-eu")]), "acme:80");
+        assert_eq!(routed(&[]), "public:80");
+        assert_eq!(router.groups().len(), 5);
+    }
+
+    #[test]
+    fn switching_groups_moves_default_traffic() {
+        let config: Config = toml::from_str(
+            r#"
+            active_group = "green"
+
+            [[groups]]
+            name = "blue"
+            upstreams = ["blue:80"]
+
+            [[groups]]
+            name = "green"
+            upstreams = ["green:80"]
+            "#,
+        )
+        .unwrap();
+        let router = Router::new(&config.listeners()[0]).unwrap();
+        let routed = || router.route(None, &[]).servers()[0].addr.clone();
+
+        assert_eq!(routed(), "green:80");
+        let (previous, _) = router.switch().activate("blue").unwrap();
+        assert_eq!(previous, "green");
+        assert_eq!(routed(), "blue:80");
+        assert_eq!(router.switch().active_name(), "blue");
+        assert!(router.switch().activate("red").is_none());
+        assert_eq!(router.groups().len(), 2);
+    }
+}
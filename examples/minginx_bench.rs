@@ -0,0 +1,253 @@
+//! Measures what minginx costs over talking to the upstream directly.
+//!
+//! Start the upstream with `--serve` and minginx in front of it, e.g.
+//!
+//! ```text
+//! cargo run --release --example minginx_bench -- --serve --only-upstream &
+//! cargo run --release --example minginx -- --config examples/minginx/minginx.toml &
+//! cargo run --release --example minginx_bench -- --proxy 127.0.0.1:8082
+//! ```
+//!
+//! Each request is a fresh connection carrying one HTTP/1.1 exchange, so the
+//! same run works against minginx in `tcp` or `http` mode. A latency round of
+//! tiny responses gives the overhead per connection; a throughput round of
+//! large ones, minus that, gives the overhead per byte.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure};
+use clap::Parser;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const MAX_HEAD: usize = 8 * 1024;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// The minginx listener to measure.
+    #[arg(long, default_value = "127.0.0.1:8082")]
+    proxy: SocketAddr,
+    /// The upstream minginx forwards to, also measured directly.
+    #[arg(long, default_value = "127.0.0.1:8081")]
+    upstream: SocketAddr,
+    /// Serve the upstream from this process instead of expecting one.
+    #[arg(long)]
+    serve: bool,
+    /// With `--serve`, only serve the upstream until interrupted.
+    #[arg(long, requires = "serve")]
+    only_upstream: bool,
+    /// Connections in the latency round.
+    #[arg(long, default_value_t = 2000)]
+    requests: usize,
+    /// Connections in the throughput round.
+    #[arg(long, default_value_t = 32)]
+    transfers: usize,
+    /// Response body size in the throughput round, in bytes.
+    #[arg(long, default_value_t = 16 * 1024 * 1024)]
+    size: u64,
+    /// Connections kept in flight at once.
+    #[arg(long, default_value_t = 16)]
+    concurrency: usize,
+}
+
+/// Timings of one round against one target.
+#[derive(Debug)]
+struct Round {
+    /// Per-connection times, sorted.
+    times: Vec<Duration>,
+    elapsed: Duration,
+    bytes: u64,
+}
+
+impl Round {
+    fn mean(&self) -> Duration {
+        self.times.iter().sum::<Duration>() / self.times.len() as u32
+    }
+
+    fn percentile(&self, p: f64) -> Duration {
+        let rank = ((self.times.len() - 1) as f64 * p / 100.0).round() as usize;
+        self.times[rank]
+    }
+
+    /// Body bytes per second over the whole round.
+    fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    ensure!(
+        args.requests > 0 && args.transfers > 0 && args.concurrency > 0,
+        "--requests, --transfers and --concurrency must be positive"
+    );
+
+    if args.serve {
+        let listener = TcpListener::bind(args.upstream).await?;
+        let server = tokio::spawn(serve(listener));
+        if args.only_upstream {
+            println!("serving upstream on {}", args.upstream);
+            return server.await?;
+        }
+    }
+
+    // Warm both paths up, so neither round pays for first connections.
+    round(args.upstream, 64, 0, args.concurrency).await?;
+    round(args.proxy, 64, 0, args.concurrency).await?;
+
+    let direct = round(args.upstream, args.requests, 0, args.concurrency).await?;
+    let proxied = round(args.proxy, args.requests, 0, args.concurrency).await?;
+    println!(
+        "latency, {} connections with empty responses:",
+        args.requests
+    );
+    for (name, r) in [("direct", &direct), ("minginx", &proxied)] {
+        println!(
+            "  {:<8} mean {:>10.1?}  p50 {:>10.1?}  p99 {:>10.1?}  {:>8.0} conn/s",
+            name,
+            r.mean(),
+            r.percentile(50.0),
+            r.percentile(99.0),
+            r.times.len() as f64 / r.elapsed.as_secs_f64(),
+        );
+    }
+    let per_connection = proxied.mean().saturating_sub(direct.mean());
+    println!("  overhead {:.1?} per connection", per_connection);
+
+    let direct = round(args.upstream, args.transfers, args.size, args.concurrency).await?;
+    let proxied = round(args.proxy, args.transfers, args.size, args.concurrency).await?;
+    println!(
+        "throughput, {} connections with {} byte responses:",
+        args.transfers, args.size
+    );
+    for (name, r) in [("direct", &direct), ("minginx", &proxied)] {
+        println!(
+            "  {:<8} mean {:>10.1?}  {:>10.1} MiB/s",
+            name,
+            r.mean(),
+            r.throughput() / (1024.0 * 1024.0),
+        );
+    }
+    let extra = proxied.mean().saturating_sub(direct.mean());
+    let per_byte = extra.saturating_sub(per_connection).as_nanos() as f64 / args.size as f64;
+    println!("  overhead {:.3} ns per byte", per_byte);
+    Ok(())
+}
+
+/// Make `count` connections to `target`, `concurrency` at a time, each
+/// fetching a `size` byte body.
+async fn round(
+    target: SocketAddr,
+    count: usize,
+    size: u64,
+    concurrency: usize,
+) -> anyhow::Result<Round> {
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let mut workers = Vec::new();
+    for _ in 0..concurrency.min(count) {
+        let next = Arc::clone(&next);
+        workers.push(tokio::spawn(async move {
+            let mut times = Vec::new();
+            while next.fetch_add(1, Ordering::Relaxed) < count {
+                let started = Instant::now();
+                fetch(target, size).await?;
+                times.push(started.elapsed());
+            }
+            anyhow::Ok(times)
+        }));
+    }
+    let mut times = Vec::with_capacity(count);
+    for worker in workers {
+        times.extend(worker.await??);
+    }
+    let elapsed = started.elapsed();
+    times.sort();
+    Ok(Round {
+        times,
+        elapsed,
+        bytes: size * count as u64,
+    })
+}
+
+/// One connection, one request, the whole response read.
+async fn fetch(target: SocketAddr, size: u64) -> anyhow::Result<()> {
+    let mut stream = TcpStream::connect(target).await?;
+    stream.set_nodelay(true)?;
+    let request = format!(
+        "GET /{} HTTP/1.1\r\nHost: bench\r\nConnection: close\r\n\r\n",
+        size
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut received = 0;
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        received += n as u64;
+    }
+    if received < size {
+        bail!("{}: got {} bytes, expected over {}", target, received, size);
+    }
+    Ok(())
+}
+
+/// The upstream: `GET /<n>` answers with `n` zero bytes, keeping the
+/// connection open unless the request says otherwise.
+async fn serve(listener: TcpListener) -> anyhow::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        stream.set_nodelay(true)?;
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream).await {
+                eprintln!("upstream: {:#}", e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream) -> anyhow::Result<()> {
+    let body = vec![0; 64 * 1024];
+    let mut buf = Vec::with_capacity(1024);
+    loop {
+        let end = loop {
+            if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+            ensure!(buf.len() < MAX_HEAD, "request head too large");
+            if stream.read_buf(&mut buf).await? == 0 {
+                return Ok(());
+            }
+        };
+        let head = String::from_utf8_lossy(&buf[..end]).to_ascii_lowercase();
+        let size: u64 = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|path| path.trim_start_matches('/').parse().ok())
+            .unwrap_or(0);
+        let close = head.contains("\r\nconnection: close\r\n");
+        buf.drain(..end);
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n{}\r\n",
+            size,
+            if close { "Connection: close\r\n" } else { "" }
+        );
+        stream.write_all(response.as_bytes()).await?;
+        let mut left = size;
+        while left > 0 {
+            let n = left.min(body.len() as u64) as usize;
+            stream.write_all(&body[..n]).await?;
+            left -= n as u64;
+        }
+        if close {
+            return Ok(stream.shutdown().await?);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! [`MyEnum`](crate::MyEnum) made generic, which is where derives stop being
+//! obvious: `Display` picks up an `L: Display, R: Display` bound from the
+//! format strings, and `From` can only be derived for one of the variants.
+//!
+//! ```text
+//! let n: Either<i32, &str> = 1.into();       // From<L>, derived
+//! let s = Either::<i32, &str>::right("one"); // no From<R>
+//! assert_eq!(n.to_string(), "left(1)");
+//! assert_eq!(s.to_string(), "right(one)");
+//! ```
+
+use derive_more::{Display, From};
+
+/// One of two values. Deriving `From` on both variants would generate
+/// `From<L>` and `From<R>`, which overlap when `L` and `R` are the same
+/// type, so `Right` opts out and is built with [`Either::right`]. The
+/// derived `From<L>` also covers `L = Result<_, _>`, so turning a result
+/// into an `Either` is [`Either::from_result`] rather than another `From`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, From)]
+pub enum Either<L, R> {
+    #[display(fmt = "left({})", _0)]
+    Left(L),
+    #[display(fmt = "right({})", _0)]
+    #[from(ignore)]
+    Right(R),
+}
+
+impl<L, R> Either<L, R> {
+    pub fn right(value: R) -> Self {
+        Either::Right(value)
+    }
+
+    /// `Ok` on the right, as is usual for `Either`.
+    pub fn from_result(result: Result<R, L>) -> Self {
+        match result {
+            Ok(value) => Either::Right(value),
+            Err(e) => Either::Left(e),
+        }
+    }
+
+    /// The left value, or `self` back if there isn't one.
+    pub fn into_left(self) -> Result<L, Self> {
+        match self {
+            Either::Left(value) => Ok(value),
+            right => Err(right),
+        }
+    }
+
+    pub fn flip(self) -> Either<R, L> {
+        match self {
+            Either::Left(value) => Either::Right(value),
+            Either::Right(value) => Either::Left(value),
+        }
+    }
+
+    pub fn map_left<T>(self, f: impl FnOnce(L) -> T) -> Either<T, R> {
+        match self {
+            Either::Left(value) => Either::Left(f(value)),
+            Either::Right(value) => Either::Right(value),
+        }
+    }
+}
+
+impl<T> Either<T, T> {
+    /// Whichever value there is, both sides being the same type.
+    pub fn into_inner(self) -> T {
+        match self {
+            Either::Left(value) | Either::Right(value) => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_either_side() {
+        let n: Either<i32, &str> = 1.into();
+        let s = Either::<i32, &str>::right("one");
+        assert_eq!(n.to_string(), "left(1)");
+        assert_eq!(s.to_string(), "right(one)");
+    }
+
+    #[test]
+    fn converts_without_overlapping_impls() {
+        let same: Either<u8, u8> = 7.into();
+        assert_eq!(same, Either::Left(7));
+        assert_eq!(same.flip().into_inner(), 7);
+
+        let ok = Either::from_result(Ok::<_, String>(2));
+        assert_eq!(ok, Either::Right(2));
+        let err = Either::<_, i32>::from_result(Err("nope".to_string()));
+        assert_eq!(err.map_left(|e| e.len()), Either::Left(4));
+
+        // From<L> with L a Result wraps it whole instead of splitting it
+        let wrapped: Either<Result<i32, String>, ()> = Ok(2).into();
+        assert_eq!(wrapped.into_left(), Ok(Ok(2)));
+        assert_eq!(
+            Either::<i32, &str>::right("x").into_left(),
+            Err(Either::Right("x"))
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! derive_more's Display, Error and From standing in for thiserror: each
+//! layer says what it was doing, and leaves what went wrong below it to
+//! `source`, so the whole chain reads as `anyhow` prints it with `{:#}`.
+
+use std::{
+    io::{self, BufRead},
+    num::ParseIntError,
+};
+
+use derive_more::{Display, Error, From};
+
+use crate::MyInt;
+
+/// What a score sheet can get wrong, even when it reads and parses.
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum ScoreError {
+    #[display(fmt = "line {} has no score", line)]
+    Unscored { line: usize },
+    #[display(fmt = "{} scored {}, below zero", name, score)]
+    Negative { name: String, score: i32 },
+    #[display(fmt = "the total is more than {}", MyInt::MAX)]
+    Overflow,
+}
+
+/// A single unnamed field, or one named `source`, is the source; `From`
+/// is derived for each variant but the one that needs a line number too.
+#[derive(Debug, Display, Error, From)]
+pub enum AppError {
+    #[display(fmt = "reading scores")]
+    Io(io::Error),
+    #[display(fmt = "line {}: score is not a number", line)]
+    #[from(ignore)]
+    Parse { line: usize, source: ParseIntError },
+    #[display(fmt = "invalid scores")]
+    Score(ScoreError),
+}
+
+/// The total of a sheet of `name,score` lines.
+pub fn total_scores(sheet: impl BufRead) -> Result<MyInt, AppError> {
+    let mut scores = Vec::new();
+    for (i, line) in sheet.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        scores.push(score(i + 1, &line)?);
+    }
+    Ok(MyInt::checked_sum(scores).ok_or(ScoreError::Overflow)?)
+}
+
+fn score(line: usize, text: &str) -> Result<MyInt, AppError> {
+    let (name, score) = text.split_once(',').ok_or(ScoreError::Unscored { line })?;
+    let score: i32 = score
+        .trim()
+        .parse()
+        .map_err(|source| AppError::Parse { line, source })?;
+    if score < 0 {
+        let name = name.trim().to_string();
+        return Err(ScoreError::Negative { name, score }.into());
+    }
+    Ok(score.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read};
+
+    use super::*;
+
+    /// The message of each error in the chain, outermost first.
+    fn chain(e: AppError) -> String {
+        format!("{:#}", anyhow::Error::from(e))
+    }
+
+    #[test]
+    fn totals_a_sheet() {
+        let sheet = "alice,3\n\nbob, 4\n";
+        assert_eq!(total_scores(sheet.as_bytes()).unwrap(), MyInt(7));
+    }
+
+    #[test]
+    fn chains_each_layer() {
+        let err = total_scores("alice,3\nbob,four".as_bytes()).unwrap_err();
+        assert_eq!(
+            chain(err),
+            "line 2: score is not a number: invalid digit found in string"
+        );
+        let err = total_scores("alice,3\nbob,-2".as_bytes()).unwrap_err();
+        assert_eq!(chain(err), "invalid scores: bob scored -2, below zero");
+        let err = total_scores("alice".as_bytes()).unwrap_err();
+        assert_eq!(chain(err), "invalid scores: line 1 has no score");
+        let sheet = format!("alice,{}\nbob,1", i32::MAX);
+        let err = total_scores(sheet.as_bytes()).unwrap_err();
+        assert_eq!(
+            chain(err),
+            "invalid scores: the total is more than 2147483647"
+        );
+    }
+
+    #[test]
+    fn io_errors_convert_with_question_mark() {
+        struct Unplugged;
+
+        impl Read for Unplugged {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "unplugged"))
+            }
+        }
+
+        let err = total_scores(BufReader::new(Unplugged)).unwrap_err();
+        assert!(matches!(err, AppError::Io(_)));
+        assert_eq!(chain(err), "reading scores: unplugged");
+    }
+}
@@ -0,0 +1,177 @@
+mod either;
+mod errors;
+
+use derive_more::{Add, Display, From, Into, Mul, Neg, Sub, Sum};
+
+use either::Either;
+use errors::total_scores;
+
+/// An `i32` that adds, subtracts, scales, negates and sums as one, overflow
+/// included: the derived operators panic on it in debug builds and wrap in
+/// release. The `checked_` and `saturating_` methods don't depend on the
+/// build.
+#[derive(
+    Debug, Clone, Copy, Display, PartialEq, Eq, PartialOrd, Ord, From, Into, Add, Sub, Mul, Neg, Sum,
+)]
+pub struct MyInt(i32);
+
+#[derive(PartialEq, From, Add, Display)]
+pub enum MyEnum {
+    #[display(fmt = "int: {_0}")]
+    Int(i32),
+    Uint(u32),
+    #[display(fmt = "nothing")]
+    Nothing,
+}
+
+impl MyInt {
+    pub const MIN: Self = Self(i32::MIN);
+    pub const MAX: Self = Self(i32::MAX);
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    pub fn checked_mul(self, rhs: i32) -> Option<Self> {
+        self.0.checked_mul(rhs).map(Self)
+    }
+
+    pub fn checked_neg(self) -> Option<Self> {
+        self.0.checked_neg().map(Self)
+    }
+
+    /// The sum, unless it overflows along the way.
+    pub fn checked_sum(iter: impl IntoIterator<Item = Self>) -> Option<Self> {
+        iter.into_iter().try_fold(Self(0), Self::checked_add)
+    }
+
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+
+    pub fn saturating_mul(self, rhs: i32) -> Self {
+        Self(self.0.saturating_mul(rhs))
+    }
+
+    pub fn saturating_neg(self) -> Self {
+        Self(self.0.saturating_neg())
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let m_int = MyInt(17);
+    let sum = m_int + 15.into();
+    println!("sum: {}", sum);
+    println!("difference: {}", sum - MyInt(40));
+    println!("product: {}", -(sum * 3));
+    println!(
+        "total: {}",
+        [MyInt(1), MyInt(2), MyInt(3)].into_iter().sum::<MyInt>()
+    );
+
+    println!("checked: {:?}", MyInt::MAX.checked_add(MyInt(1)));
+    println!("saturating: {}", MyInt::MAX.saturating_add(MyInt(1)));
+    println!(
+        "checked sum: {:?}",
+        MyInt::checked_sum([MyInt::MAX, MyInt(1), MyInt(-1)])
+    );
+
+    let sides: [Either<MyInt, &str>; 2] = [m_int.into(), Either::right("seventeen")];
+    for side in sides {
+        println!("either: {}", side);
+    }
+    for text in ["17", "seventeen"] {
+        let parsed = Either::from_result(text.parse::<i32>()).map_left(|e| e.to_string());
+        println!("parsed: {}, flipped: {}", parsed, parsed.clone().flip());
+        if let Err(right) = parsed.into_left() {
+            println!("either way: {}", right.map_left(|_| 0).flip().into_inner());
+        }
+    }
+
+    let total = total_scores("alice,3\nbob,4\n".as_bytes())?;
+    println!("total score: {}", total);
+    for sheet in ["alice,3\nbob,four", "alice,3\nbob,-2"] {
+        if let Err(e) = total_scores(sheet.as_bytes()) {
+            println!("{:#}", anyhow::Error::from(e));
+        }
+    }
+    Ok(())
+}
+
+// TODO: strum
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, UnwindSafe};
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Whether `mine` comes to what `theirs` does on plain `i32`s, or
+    /// panics where that does.
+    fn same_as_i32(
+        mine: impl FnOnce() -> MyInt + UnwindSafe,
+        theirs: impl FnOnce() -> i32 + UnwindSafe,
+    ) -> bool {
+        let mine = panic::catch_unwind(mine).ok();
+        let theirs = panic::catch_unwind(theirs).ok();
+        mine == theirs.map(MyInt)
+    }
+
+    proptest! {
+        #[test]
+        fn operators_match_i32(a: i32, b: i32) {
+            prop_assert!(same_as_i32(|| MyInt(a) + MyInt(b), || a + b));
+            prop_assert!(same_as_i32(|| MyInt(a) - MyInt(b), || a - b));
+            prop_assert!(same_as_i32(|| MyInt(a) * b, || a * b));
+            prop_assert!(same_as_i32(|| -MyInt(a), || -a));
+        }
+
+        #[test]
+        fn sums_match_i32(xs: Vec<i32>) {
+            let mine = xs.iter().copied().map(MyInt);
+            let theirs = xs.iter();
+            prop_assert!(same_as_i32(|| mine.clone().sum(), || theirs.sum()));
+            prop_assert_eq!(
+                MyInt::checked_sum(mine),
+                xs.iter().try_fold(0i32, |sum, x| sum.checked_add(*x)).map(MyInt)
+            );
+        }
+
+        #[test]
+        fn checked_matches_i32(a: i32, b: i32) {
+            prop_assert_eq!(MyInt(a).checked_add(MyInt(b)), a.checked_add(b).map(MyInt));
+            prop_assert_eq!(MyInt(a).checked_sub(MyInt(b)), a.checked_sub(b).map(MyInt));
+            prop_assert_eq!(MyInt(a).checked_mul(b), a.checked_mul(b).map(MyInt));
+            prop_assert_eq!(MyInt(a).checked_neg(), a.checked_neg().map(MyInt));
+        }
+
+        #[test]
+        fn saturating_matches_i32(a: i32, b: i32) {
+            prop_assert_eq!(MyInt(a).saturating_add(MyInt(b)), MyInt(a.saturating_add(b)));
+            prop_assert_eq!(MyInt(a).saturating_sub(MyInt(b)), MyInt(a.saturating_sub(b)));
+            prop_assert_eq!(MyInt(a).saturating_mul(b), MyInt(a.saturating_mul(b)));
+            prop_assert_eq!(MyInt(a).saturating_neg(), MyInt(a.saturating_neg()));
+        }
+    }
+
+    #[test]
+    fn overflow_is_caught_at_the_edges() {
+        assert_eq!(MyInt::MAX.checked_add(MyInt(1)), None);
+        assert_eq!(MyInt::MIN.checked_sub(MyInt(1)), None);
+        assert_eq!(MyInt::MIN.checked_neg(), None);
+        assert_eq!(MyInt::MIN.saturating_neg(), MyInt::MAX);
+        assert_eq!(MyInt::MIN.saturating_mul(-1), MyInt::MAX);
+        let min = std::hint::black_box(i32::MIN);
+        assert!(same_as_i32(|| -MyInt(min), || -min));
+    }
+}
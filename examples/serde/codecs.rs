@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use anyhow::ensure;
+use clap::ValueEnum;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Binary encodings to weigh for the chat wire format and caches, with JSON
+/// for scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    Json,
+    Bincode,
+    #[value(name = "msgpack")]
+    MessagePack,
+    Cbor,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 4] = [Codec::Json, Codec::Bincode, Codec::MessagePack, Codec::Cbor];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            Codec::Bincode => "bincode",
+            Codec::MessagePack => "msgpack",
+            Codec::Cbor => "cbor",
+        }
+    }
+
+    /// MessagePack keeps field names, like the other self-describing
+    /// formats; bincode writes values alone, in field order.
+    pub fn encode<T: Serialize>(self, value: &T) -> anyhow::Result<Vec<u8>> {
+        let bytes = match self {
+            Codec::Json => serde_json::to_vec(value)?,
+            Codec::Bincode => bincode::serialize(value)?,
+            Codec::MessagePack => rmp_serde::to_vec_named(value)?,
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)?;
+                bytes
+            }
+        };
+        Ok(bytes)
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> anyhow::Result<T> {
+        let value = match self {
+            Codec::Json => serde_json::from_slice(bytes)?,
+            Codec::Bincode => bincode::deserialize(bytes)?,
+            Codec::MessagePack => rmp_serde::from_slice(bytes)?,
+            Codec::Cbor => ciborium::from_reader(bytes)?,
+        };
+        Ok(value)
+    }
+}
+
+/// How one codec did on a value: its encoded size, and the mean time to
+/// encode and to decode it.
+#[derive(Debug)]
+pub struct Report {
+    pub codec: Codec,
+    pub size: usize,
+    pub encode: Duration,
+    pub decode: Duration,
+}
+
+/// Encodes and decodes `value` `rounds` times with every codec, failing if
+/// any of them doesn't give it back unchanged.
+pub fn compare<T>(value: &T, rounds: u32) -> anyhow::Result<Vec<Report>>
+where
+    T: Serialize + DeserializeOwned + PartialEq,
+{
+    Codec::ALL
+        .into_iter()
+        .map(|codec| {
+            let bytes = codec.encode(value)?;
+            ensure!(
+                codec.decode::<T>(&bytes)? == *value,
+                "{} changed the value on the way through",
+                codec.name()
+            );
+
+            let start = Instant::now();
+            for _ in 0..rounds {
+                codec.encode(value)?;
+            }
+            let encode = start.elapsed() / rounds;
+            let start = Instant::now();
+            for _ in 0..rounds {
+                codec.decode::<T>(&bytes)?;
+            }
+            let decode = start.elapsed() / rounds;
+
+            Ok(Report {
+                codec,
+                size: bytes.len(),
+                encode,
+                decode,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{sample, User, WorkState};
+
+    use super::*;
+
+    #[test]
+    fn every_codec_round_trips_every_state() {
+        for state in [
+            WorkState::Working("Rust".to_string()),
+            WorkState::OnLeave("2026-11-02T09:30:00.123456789Z".parse().unwrap()),
+            WorkState::Terminated,
+        ] {
+            let user = User { state, ..sample() };
+            for codec in Codec::ALL {
+                let bytes = codec.encode(&user).unwrap();
+                let decoded: User = codec.decode(&bytes).unwrap();
+                assert_eq!(decoded, user, "{:?}", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_codecs_are_smaller_than_json() {
+        let reports = compare(&sample(), 1).unwrap();
+        let json = reports[0].size;
+        assert_eq!(reports[0].codec, Codec::Json);
+        for report in &reports[1..] {
+            assert!(report.size < json, "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        for codec in Codec::ALL {
+            let bytes = codec.encode(&sample()).unwrap();
+            let truncated = &bytes[..bytes.len() / 2];
+            assert!(codec.decode::<User>(truncated).is_err(), "{:?}", codec);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! [`WorkState`] in each of serde's enum representations, for services
+//! outside Rust that expect one or another:
+//!
+//! - externally tagged, serde's default, `{"onLeave": "2026-11-02T..."}`:
+//!   reads back anything, but is an odd shape outside Rust;
+//! - internally tagged, `{"type": "onLeave", ...}`: the usual shape for
+//!   JSON APIs, but only for variants holding structs or nothing, so it
+//!   can't write `Working` or `OnLeave` at all;
+//! - adjacently tagged, `{"type": "onLeave", "details": "2026-11-02T..."}`:
+//!   what the example stores, a fixed shape for any variant;
+//! - untagged, `"2026-11-02T..."`: the bare value, simplest to produce, but
+//!   read back as the first variant that fits, here always `Working`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::WorkState;
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WorkState", rename_all = "camelCase")]
+enum External {
+    Working(String),
+    OnLeave(DateTime<Utc>),
+    Terminated,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WorkState", rename_all = "camelCase", tag = "type")]
+enum Internal {
+    Working(String),
+    OnLeave(DateTime<Utc>),
+    Terminated,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WorkState", rename_all = "camelCase")]
+#[serde(tag = "type", content = "details")]
+enum Adjacent {
+    Working(String),
+    OnLeave(DateTime<Utc>),
+    Terminated,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WorkState", untagged)]
+enum Untagged {
+    Working(String),
+    OnLeave(DateTime<Utc>),
+    Terminated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    External,
+    Internal,
+    Adjacent,
+    Untagged,
+}
+
+impl Representation {
+    pub const ALL: [Representation; 4] = [
+        Representation::External,
+        Representation::Internal,
+        Representation::Adjacent,
+        Representation::Untagged,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Representation::External => "external",
+            Representation::Internal => "internal",
+            Representation::Adjacent => "adjacent",
+            Representation::Untagged => "untagged",
+        }
+    }
+
+    pub fn write(self, state: &WorkState) -> serde_json::Result<Value> {
+        let serializer = serde_json::value::Serializer;
+        match self {
+            Representation::External => External::serialize(state, serializer),
+            Representation::Internal => Internal::serialize(state, serializer),
+            Representation::Adjacent => Adjacent::serialize(state, serializer),
+            Representation::Untagged => Untagged::serialize(state, serializer),
+        }
+    }
+
+    pub fn read(self, value: Value) -> serde_json::Result<WorkState> {
+        match self {
+            Representation::External => External::deserialize(value),
+            Representation::Internal => Internal::deserialize(value),
+            Representation::Adjacent => Adjacent::deserialize(value),
+            Representation::Untagged => Untagged::deserialize(value),
+        }
+    }
+}
+
+/// A state as one representation writes it, and what reading that back
+/// gives, if it could be written.
+#[derive(Debug)]
+pub struct Form {
+    pub representation: Representation,
+    pub written: serde_json::Result<Value>,
+    pub read_back: Option<serde_json::Result<WorkState>>,
+}
+
+/// `state` in every representation.
+pub fn forms(state: &WorkState) -> Vec<Form> {
+    Representation::ALL
+        .into_iter()
+        .map(|representation| {
+            let written = representation.write(state);
+            let read_back = written
+                .as_ref()
+                .ok()
+                .map(|value| representation.read(value.clone()));
+            Form {
+                representation,
+                written,
+                read_back,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn on_leave() -> WorkState {
+        WorkState::OnLeave("2026-11-02T09:30:00Z".parse().unwrap())
+    }
+
+    fn states() -> [WorkState; 3] {
+        [
+            WorkState::Working("Rust".to_string()),
+            on_leave(),
+            WorkState::Terminated,
+        ]
+    }
+
+    #[test]
+    fn tagged_forms_round_trip() {
+        for representation in [Representation::External, Representation::Adjacent] {
+            for state in states() {
+                let value = representation.write(&state).unwrap();
+                assert_eq!(representation.read(value).unwrap(), state);
+            }
+        }
+        let external = Representation::External.write(&on_leave()).unwrap();
+        assert_eq!(external, json!({"onLeave": "2026-11-02T09:30:00Z"}));
+        let adjacent = Representation::Adjacent.write(&on_leave()).unwrap();
+        assert_eq!(
+            adjacent,
+            json!({"type": "onLeave", "details": "2026-11-02T09:30:00Z"})
+        );
+    }
+
+    #[test]
+    fn internal_tags_only_fit_unit_variants() {
+        let internal = Representation::Internal;
+        let terminated = internal.write(&WorkState::Terminated).unwrap();
+        assert_eq!(terminated, json!({"type": "terminated"}));
+        assert_eq!(internal.read(terminated).unwrap(), WorkState::Terminated);
+        assert!(internal
+            .write(&WorkState::Working("Rust".to_string()))
+            .is_err());
+        assert!(internal.write(&on_leave()).is_err());
+    }
+
+    #[test]
+    fn untagged_forms_lose_the_variant() {
+        let untagged = Representation::Untagged;
+        assert_eq!(untagged.write(&on_leave()).unwrap(), "2026-11-02T09:30:00Z");
+        assert_eq!(untagged.write(&WorkState::Terminated).unwrap(), Value::Null);
+
+        let form = &forms(&on_leave())[3];
+        assert_eq!(form.representation, untagged);
+        let read_back = form.read_back.as_ref().unwrap().as_ref().unwrap();
+        assert_eq!(
+            *read_back,
+            WorkState::Working("2026-11-02T09:30:00Z".to_string())
+        );
+    }
+}
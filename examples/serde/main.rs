@@ -0,0 +1,634 @@
+//! Convert a user between JSON, YAML and TOML:
+//!
+//! ```text
+//! cargo run --example serde -- --sample --to yaml > alice.yaml
+//! cargo run --example serde -- --to toml alice.yaml
+//! ```
+//!
+//! Or see how big and how fast a user is in binary formats:
+//!
+//! ```text
+//! cargo run --release --example serde -- --sample --compare
+//! ```
+//!
+//! Or how its work state looks in each of serde's enum representations:
+//!
+//! ```text
+//! cargo run --example serde -- --sample --enums
+//! ```
+//!
+//! Users are stored as `{"version": 2, "data": {...}}`, and documents of
+//! older versions are upgraded as they are read.
+
+mod codecs;
+mod enums;
+
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset, Months, NaiveDate, NaiveDateTime, Utc};
+use clap::{Parser, ValueEnum};
+use serde::de::{Error as _, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use serde_with::base64::Base64;
+use serde_with::{serde_as, DeserializeAs, DisplayFromStr, DurationSeconds, Map, SerializeAs};
+
+#[serde_as]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    name: String,
+    /// Age is worked out from this; version 1 stored the age instead.
+    #[serde_as(as = "AnyDateTime")]
+    dob: NaiveDate,
+    skills: Vec<String>,
+    state: WorkState,
+    /// A whole number of seconds rather than serde's `{secs, nanos}`.
+    #[serde_as(as = "DurationSeconds<u64>")]
+    notice_period: Duration,
+    /// A base64 string rather than an array of numbers.
+    #[serde_as(as = "Base64")]
+    avatar: Vec<u8>,
+    /// A string, so JavaScript clients don't round it to a double.
+    #[serde_as(as = "DisplayFromStr")]
+    employee_no: u64,
+    /// An object keyed by site, but kept in the order given.
+    #[serde_as(as = "Map<_, _>")]
+    profiles: Vec<(String, String)>,
+}
+
+#[derive(Debug, PartialEq)]
+enum WorkState {
+    Working(String),
+    OnLeave(DateTime<Utc>),
+    Terminated,
+}
+
+/// [`WorkState`] as text formats have it, `{"type": "onLeave", "details": ...}`.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WorkState")]
+#[serde(rename_all = "camelCase", tag = "type", content = "details")]
+enum TaggedWorkState {
+    Working(String),
+    OnLeave(#[serde_as(as = "AnyDateTime")] DateTime<Utc>),
+    Terminated,
+}
+
+/// [`WorkState`] as binary formats have it: some, like bincode, can't look
+/// variants up by name, so they get serde's default, the variant's index.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "WorkState")]
+enum IndexedWorkState {
+    Working(String),
+    OnLeave(#[serde_as(as = "AnyDateTime")] DateTime<Utc>),
+    Terminated,
+}
+
+impl Serialize for WorkState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            TaggedWorkState::serialize(self, serializer)
+        } else {
+            IndexedWorkState::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for WorkState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            TaggedWorkState::deserialize(deserializer)
+        } else {
+            IndexedWorkState::deserialize(deserializer)
+        }
+    }
+}
+
+/// Reads dates and times whichever way the sender wrote them: RFC 3339,
+/// `%Y-%m-%d %H:%M:%S` or a bare `%Y-%m-%d` in UTC, or unix seconds, as a
+/// number or a string. Writes RFC 3339, or just the date for dates.
+pub struct AnyDateTime;
+
+impl SerializeAs<DateTime<Utc>> for AnyDateTime {
+    fn serialize_as<S: Serializer>(
+        source: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        source.serialize(serializer)
+    }
+}
+
+impl<'de> DeserializeAs<'de, DateTime<Utc>> for AnyDateTime {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        Ok(any_date_time(deserializer)?.to_utc())
+    }
+}
+
+impl SerializeAs<NaiveDate> for AnyDateTime {
+    fn serialize_as<S: Serializer>(source: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        source.serialize(serializer)
+    }
+}
+
+/// Times of day are dropped, keeping the date where the time was written.
+impl<'de> DeserializeAs<'de, NaiveDate> for AnyDateTime {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        Ok(any_date_time(deserializer)?.date_naive())
+    }
+}
+
+/// Binary formats can't say what they hold, but then they were written by
+/// this code, as a string.
+fn any_date_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DateTime<FixedOffset>, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(AnyDateTimeVisitor)
+    } else {
+        deserializer.deserialize_str(AnyDateTimeVisitor)
+    }
+}
+
+struct AnyDateTimeVisitor;
+
+impl<'de> Visitor<'de> for AnyDateTimeVisitor {
+    type Value = DateTime<FixedOffset>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an RFC 3339 or \"%Y-%m-%d %H:%M:%S\" date and time, or unix seconds")
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, secs: i64) -> Result<Self::Value, E> {
+        DateTime::from_timestamp(secs, 0)
+            .map(|utc| utc.fixed_offset())
+            .ok_or_else(|| E::custom(format!("{} seconds is out of range", secs)))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, secs: u64) -> Result<Self::Value, E> {
+        let secs = i64::try_from(secs).map_err(E::custom)?;
+        self.visit_i64(secs)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<Self::Value, E> {
+        if let Ok(secs) = text.parse() {
+            return self.visit_i64(secs);
+        }
+        let utc = |naive: NaiveDateTime| naive.and_utc().fixed_offset();
+        DateTime::parse_from_rfc3339(text)
+            .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").map(utc))
+            .or_else(|_| NaiveDate::parse_from_str(text, "%Y-%m-%d").map(|date| utc(date.into())))
+            .map_err(|_| E::invalid_value(serde::de::Unexpected::Str(text), &self))
+    }
+}
+
+/// A model stored along with the version of it that wrote it, so that
+/// documents outlive changes to the model.
+#[derive(Debug, PartialEq)]
+pub struct Versioned<T>(pub T);
+
+/// A model whose older versions can be brought up to date.
+pub trait Migrate: Sized {
+    /// The version written now.
+    const VERSION: u32;
+
+    /// Reads `data` as written by `version`, which is at most the current one.
+    fn upgrade(version: u32, data: Value) -> serde_json::Result<Self>;
+}
+
+impl<T: Migrate + Serialize> Serialize for Versioned<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Envelope<'a, T> {
+            version: u32,
+            data: &'a T,
+        }
+
+        let data = &self.0;
+        Envelope {
+            version: T::VERSION,
+            data,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Migrate> Deserialize<'de> for Versioned<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Envelope {
+            version: u32,
+            data: Value,
+        }
+
+        let Envelope { version, data } = Envelope::deserialize(deserializer)?;
+        if version == 0 || version > T::VERSION {
+            let message = format!("unknown version {}, expected 1 to {}", version, T::VERSION);
+            return Err(D::Error::custom(message));
+        }
+        T::upgrade(version, data)
+            .map(Versioned)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl Migrate for User {
+    const VERSION: u32 = 2;
+
+    fn upgrade(version: u32, mut data: Value) -> serde_json::Result<Self> {
+        if version < 2 {
+            data = dob_from_age(data)?;
+        }
+        serde_json::from_value(data)
+    }
+}
+
+/// Version 2 swapped `age` for `dob`. A version 1 user gets the latest date
+/// of birth their age allows, as of the upgrade.
+fn dob_from_age(mut data: Value) -> serde_json::Result<Value> {
+    let Some(user) = data.as_object_mut() else {
+        return Err(serde_json::Error::custom("expected a user object"));
+    };
+    let age = user
+        .remove("age")
+        .ok_or_else(|| serde_json::Error::missing_field("age"))?;
+    let age: u8 = serde_json::from_value(age)?;
+    let dob = Utc::now()
+        .date_naive()
+        .checked_sub_months(Months::new(12 * u32::from(age)))
+        .ok_or_else(|| serde_json::Error::custom("age out of range"))?;
+    user.insert("dob".to_string(), dob.to_string().into());
+    Ok(data)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    /// Each format is read into its own value tree first, to smooth over
+    /// what the models can't take as is.
+    fn read(self, text: &str) -> anyhow::Result<Versioned<User>> {
+        let user = match self {
+            Format::Json => serde_json::from_str(text)?,
+            Format::Yaml => serde_yaml::from_value(untag(serde_yaml::from_str(text)?))?,
+            Format::Toml => stringify_datetimes(toml::from_str(text)?).try_into()?,
+        };
+        Ok(user)
+    }
+
+    fn write(self, user: &Versioned<User>) -> anyhow::Result<String> {
+        let text = match self {
+            Format::Json => serde_json::to_string_pretty(user)? + "\n",
+            Format::Yaml => serde_yaml::to_string(user)?,
+            Format::Toml => toml::to_string(user)?,
+        };
+        Ok(text)
+    }
+}
+
+/// YAML writers may give enums as tagged values, `state: !onLeave <date>`,
+/// rather than the `{type, details}` mapping the models use.
+fn untag(value: serde_yaml::Value) -> serde_yaml::Value {
+    use serde_yaml::{Mapping, Value};
+
+    match value {
+        Value::Tagged(tagged) => {
+            let tag = tagged.tag.to_string();
+            let mut variant = Mapping::new();
+            variant.insert("type".into(), tag.trim_start_matches('!').into());
+            if !tagged.value.is_null() {
+                variant.insert("details".into(), untag(tagged.value));
+            }
+            Value::Mapping(variant)
+        }
+        Value::Sequence(items) => Value::Sequence(items.into_iter().map(untag).collect()),
+        Value::Mapping(entries) => Value::Mapping(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, untag(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// TOML has dates and datetimes of its own, `dob = 1994-05-17`, which chrono
+/// only takes in their string form.
+fn stringify_datetimes(value: toml::Value) -> toml::Value {
+    use toml::Value;
+
+    match value {
+        Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        Value::Array(items) => Value::Array(items.into_iter().map(stringify_datetimes).collect()),
+        Value::Table(entries) => Value::Table(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, stringify_datetimes(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// File to read a user from, standard input without one.
+    input: Option<PathBuf>,
+    /// Format of the input; guessed from its extension, or else JSON.
+    #[arg(long, value_enum)]
+    from: Option<Format>,
+    /// Format to write the user to standard output in.
+    #[arg(long, value_enum, default_value = "json")]
+    to: Format,
+    /// Write a made-up user instead of reading one.
+    #[arg(long, conflicts_with_all = ["input", "from"])]
+    sample: bool,
+    /// Instead of converting the user, time encoding and decoding it in
+    /// binary formats and report their sizes.
+    #[arg(long, conflicts_with = "to")]
+    compare: bool,
+    /// Instead of converting the user, show its work state in each of
+    /// serde's enum representations.
+    #[arg(long, conflicts_with_all = ["to", "compare"])]
+    enums: bool,
+}
+
+/// Times are means over this many rounds.
+const ROUNDS: u32 = 10_000;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let user = match (args.sample, &args.input) {
+        (true, _) => Versioned(sample()),
+        (false, Some(path)) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let from = args.from.or_else(|| Format::from_path(path));
+            from.unwrap_or(Format::Json).read(&text)?
+        }
+        (false, None) => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            args.from.unwrap_or(Format::Json).read(&text)?
+        }
+    };
+    if args.compare {
+        println!(
+            "{:<8} {:>6} {:>12} {:>12}",
+            "codec", "bytes", "encode", "decode"
+        );
+        for report in codecs::compare(&user.0, ROUNDS)? {
+            println!(
+                "{:<8} {:>6} {:>12.2?} {:>12.2?}",
+                report.codec.name(),
+                report.size,
+                report.encode,
+                report.decode
+            );
+        }
+    } else if args.enums {
+        for form in enums::forms(&user.0.state) {
+            let name = form.representation.name();
+            match (form.written, form.read_back) {
+                (Ok(value), Some(Ok(state))) if state == user.0.state => {
+                    println!("{:<8} {}", name, value)
+                }
+                (Ok(value), Some(Ok(state))) => {
+                    println!("{:<8} {}, read back as {:?}", name, value, state)
+                }
+                (Ok(value), _) => println!("{:<8} {}, unreadable", name, value),
+                (Err(e), _) => println!("{:<8} can't be written: {}", name, e),
+            }
+        }
+    } else {
+        print!("{}", args.to.write(&user)?);
+    }
+    Ok(())
+}
+
+fn sample() -> User {
+    User {
+        name: "Alice".to_string(),
+        dob: NaiveDate::from_ymd_opt(1994, 5, 17).unwrap(),
+        skills: vec!["Rust".to_string(), "Go".to_string()],
+        state: WorkState::OnLeave(Utc::now()),
+        notice_period: Duration::from_secs(30 * 24 * 60 * 60),
+        avatar: vec![0x89, b'P', b'N', b'G'],
+        employee_no: 9_007_199_254_740_993,
+        profiles: vec![
+            ("github".to_string(), "alice".to_string()),
+            ("mastodon".to_string(), "@alice@example.social".to_string()),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn alice() -> User {
+        User {
+            name: "Alice".to_string(),
+            dob: NaiveDate::from_ymd_opt(1994, 5, 17).unwrap(),
+            skills: vec!["Rust".to_string()],
+            state: WorkState::Terminated,
+            notice_period: Duration::from_secs(90),
+            avatar: b"hi!".to_vec(),
+            employee_no: 9_007_199_254_740_993,
+            profiles: vec![
+                ("zulip".to_string(), "alice".to_string()),
+                ("github".to_string(), "alice-dev".to_string()),
+            ],
+        }
+    }
+
+    /// `field` serializes as `expected` and reads back unchanged.
+    fn round_trips(field: &str, expected: Value) {
+        let user = alice();
+        let value = serde_json::to_value(&user).unwrap();
+        assert_eq!(value[field], expected);
+        assert_eq!(serde_json::from_value::<User>(value).unwrap(), user);
+    }
+
+    #[test]
+    fn duration_as_seconds() {
+        round_trips("noticePeriod", json!(90));
+    }
+
+    #[test]
+    fn bytes_as_base64() {
+        round_trips("avatar", json!("aGkh"));
+    }
+
+    #[test]
+    fn number_as_string() {
+        round_trips("employeeNo", json!("9007199254740993"));
+        let mut json = serde_json::to_value(alice()).unwrap();
+        json["employeeNo"] = json!("12ab");
+        assert!(serde_json::from_value::<User>(json).is_err());
+    }
+
+    #[test]
+    fn pairs_as_map() {
+        round_trips("profiles", json!({"zulip": "alice", "github": "alice-dev"}));
+        let json = serde_json::to_string(&alice()).unwrap();
+        assert!(json.find("zulip") < json.find("github"));
+    }
+
+    #[test]
+    fn converts_between_every_pair_of_formats() {
+        let formats = [Format::Json, Format::Yaml, Format::Toml];
+        for state in [
+            WorkState::Working("Rust".to_string()),
+            WorkState::OnLeave("2026-11-02T09:30:00Z".parse().unwrap()),
+            WorkState::Terminated,
+        ] {
+            let user = Versioned(User { state, ..alice() });
+            for from in formats {
+                for to in formats {
+                    let text = from.write(&user).unwrap();
+                    let read = from.read(&text).unwrap();
+                    let converted = to.read(&to.write(&read).unwrap()).unwrap();
+                    assert_eq!(converted, user, "{:?} to {:?}", from, to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reads_native_toml_datetimes() {
+        let text = r#"
+            version = 2
+
+            [data]
+            name = "Alice"
+            dob = 1994-05-17
+            skills = []
+            noticePeriod = 90
+            avatar = ""
+            employeeNo = "7"
+            profiles = {}
+            state = { type = "onLeave", details = 2026-11-02T09:30:00Z }
+        "#;
+        let Versioned(user) = Format::Toml.read(text).unwrap();
+        assert_eq!(user.dob, alice().dob);
+        let back = "2026-11-02T09:30:00Z".parse().unwrap();
+        assert_eq!(user.state, WorkState::OnLeave(back));
+    }
+
+    #[test]
+    fn reads_yaml_tagged_enums() {
+        let user = Format::Yaml.write(&Versioned(alice())).unwrap();
+        for (state, expected) in [
+            ("!working Rust", WorkState::Working("Rust".to_string())),
+            ("!terminated", WorkState::Terminated),
+            ("{type: terminated}", WorkState::Terminated),
+        ] {
+            let text = user.replace(
+                "  state:\n    type: terminated\n",
+                &format!("  state: {}\n", state),
+            );
+            assert_ne!(text, user);
+            assert_eq!(Format::Yaml.read(&text).unwrap().0.state, expected);
+        }
+    }
+
+    #[test]
+    fn reads_dates_and_times_in_any_format() {
+        let leave: DateTime<Utc> = "2026-11-02T09:30:00Z".parse().unwrap();
+        for written in [
+            json!("2026-11-02T09:30:00Z"),
+            json!("2026-11-02T18:30:00+09:00"),
+            json!("2026-11-02 09:30:00"),
+            json!(1793611800),
+            json!("1793611800"),
+        ] {
+            let mut value = serde_json::to_value(alice()).unwrap();
+            value["dob"] = written.clone();
+            value["state"] = json!({"type": "onLeave", "details": written});
+            let user: User = serde_json::from_value(value).unwrap();
+            assert_eq!(user.dob, NaiveDate::from_ymd_opt(2026, 11, 2).unwrap());
+            assert_eq!(user.state, WorkState::OnLeave(leave));
+        }
+
+        let mut value = serde_json::to_value(alice()).unwrap();
+        value["dob"] = json!("2026-11-02");
+        value["state"] = json!({"type": "onLeave", "details": "2026-11-02"});
+        let user: User = serde_json::from_value(value).unwrap();
+        let midnight = user.dob.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        assert_eq!(user.state, WorkState::OnLeave(midnight));
+
+        let mut value = serde_json::to_value(alice()).unwrap();
+        value["dob"] = json!("02/11/2026");
+        let err = serde_json::from_value::<User>(value).unwrap_err();
+        assert!(err.to_string().contains("unix seconds"), "{}", err);
+    }
+
+    #[test]
+    fn writes_rfc_3339() {
+        let leave = "2026-11-02T09:30:00Z".parse().unwrap();
+        let user = User {
+            state: WorkState::OnLeave(leave),
+            ..alice()
+        };
+        let value = serde_json::to_value(user).unwrap();
+        assert_eq!(value["dob"], "1994-05-17");
+        assert_eq!(value["state"]["details"], "2026-11-02T09:30:00Z");
+    }
+
+    #[test]
+    fn upgrades_version_1_users() {
+        let mut data = serde_json::to_value(alice()).unwrap();
+        data.as_object_mut().unwrap().remove("dob");
+        data["age"] = json!(30);
+        let stored = json!({"version": 1, "data": data});
+
+        let Versioned(user) = serde_json::from_value::<Versioned<User>>(stored).unwrap();
+        let today = Utc::now().date_naive();
+        assert_eq!(today.years_since(user.dob), Some(30));
+        assert_eq!(
+            User {
+                dob: alice().dob,
+                ..user
+            },
+            alice()
+        );
+
+        let written = serde_json::to_value(Versioned(alice())).unwrap();
+        assert_eq!(written["version"], 2);
+        assert!(written["data"].get("age").is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let data = serde_json::to_value(alice()).unwrap();
+        for version in [0, 3] {
+            let stored = json!({"version": version, "data": data});
+            let err = serde_json::from_value::<Versioned<User>>(stored).unwrap_err();
+            assert!(err.to_string().contains("unknown version"), "{}", err);
+        }
+        let stored = json!({"version": 1, "data": data});
+        assert!(serde_json::from_value::<Versioned<User>>(stored).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
-use opentelemetry::KeyValue;
+use anyhow::anyhow;
+use opentelemetry::{global, KeyValue};
 use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::runtime::Tokio;
 use opentelemetry_sdk::trace::{RandomIdGenerator, Tracer};
@@ -10,21 +11,50 @@ use tracing::{info, instrument};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-/// Fixme: not work
+/// Two futures on a single-threaded runtime of their own thread, traced over
+/// OTLP. The pitfalls it works around:
+///
+/// - A current-thread runtime only runs anything inside `block_on`. A task
+///   `spawn`ed onto it makes progress while `block_on` is waiting, and is
+///   dropped unfinished with the runtime, so `spawn1` is joined through its
+///   handle before `block_on` returns.
+/// - There is one thread, so `expensive_op` blocking it holds up everything
+///   else: `spawn2`'s 100ms sleep ends only once `spawn1` gets back to it.
+/// - The batch span exporter is a task itself, spawned by `install_batch`
+///   (which panics outside a runtime). On the worker runtime it would be
+///   starved like the rest, and dropped with its queue; so it runs on a
+///   runtime of its own that outlives the worker, and the provider is
+///   flushed and shut down before exiting.
 fn main() -> anyhow::Result<()> {
-    let tracer = init_tracer()?;
-    let opentelemetry = tracing_opentelemetry::layer().with_tracer(tracer);
-    tracing_subscriber::registry().with(opentelemetry).init();
+    let telemetry = Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()?;
+    let tracer = {
+        let _guard = telemetry.enter();
+        init_tracer()?
+    };
+    let opentelemetry = tracing_opentelemetry::layer().with_tracer(tracer.clone());
+    tracing_subscriber::registry()
+        .with(opentelemetry)
+        .with(ecosystem::console::layer())
+        .init();
+    ecosystem::console::wait_for_client();
 
-    let handle = std::thread::spawn(|| {
-        let rt = Builder::new_current_thread().enable_all().build().unwrap();
-        rt.spawn(async { spawn1().await });
-
-        rt.block_on(async { spawn2().await })
+    let handle = std::thread::spawn(|| -> anyhow::Result<()> {
+        let rt = Builder::new_current_thread().enable_all().build()?;
+        let spawned = rt.spawn(spawn1());
+        rt.block_on(async {
+            spawn2().await;
+            spawned.await
+        })?;
+        Ok(())
     });
+    handle
+        .join()
+        .map_err(|_| anyhow!("runtime thread panicked"))??;
 
-    handle.join().unwrap();
-
+    shutdown_tracer(&tracer);
     Ok(())
 }
 
@@ -64,3 +94,16 @@ fn init_tracer() -> anyhow::Result<Tracer> {
         .install_batch(Tokio)?;
     Ok(tracer)
 }
+
+/// Export the spans still queued. Called from outside any runtime: shutting
+/// down blocks until the exporter task, on its own runtime, is done.
+fn shutdown_tracer(tracer: &Tracer) {
+    if let Some(provider) = tracer.provider() {
+        for result in provider.force_flush() {
+            if let Err(e) = result {
+                eprintln!("flushing spans: {}", e);
+            }
+        }
+    }
+    global::shutdown_tracer_provider();
+}
@@ -2,8 +2,15 @@ use std::thread::JoinHandle;
 use std::time::Duration;
 
 use tokio::runtime::Builder;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
 fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(ecosystem::console::layer())
+        .init();
+    ecosystem::console::wait_for_client();
+
     let handle = thread_tokio_rt();
 
     handle.join().unwrap();
@@ -1,43 +1,189 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use clap::builder::RangedU64ValueParser;
+use clap::{Parser, ValueEnum};
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::MissedTickBehavior;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// An async producer feeding a pool of blocking workers, printing how many
+/// tasks a second the pool gets through. With work that takes `work_ms`,
+/// that is at most `workers * 1000 / work_ms`, as long as the blocking
+/// thread pool (512 threads by default) has room for them all.
+///
+/// Produce faster than that (`--rate`, or flat out without it) and the
+/// queue fills: with `--policy block` the producer then waits, slowing to
+/// the pool's pace; with `--policy drop` it keeps its pace and the tasks
+/// that don't fit are lost.
+#[derive(Debug, Parser)]
+struct Args {
+    /// Number of blocking workers taking tasks off the channel.
+    #[arg(long, default_value_t = 4, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    workers: usize,
+    /// Tasks the channel holds before the producer has to wait.
+    #[arg(long, default_value_t = 42, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    capacity: usize,
+    /// How long each task keeps a worker busy, in milliseconds.
+    #[arg(long, default_value_t = 500)]
+    work_ms: u64,
+    /// Tasks produced a second; as many as the channel takes if not given.
+    #[arg(long, value_parser = parse_rate)]
+    rate: Option<f64>,
+    /// What the producer does when the channel is full.
+    #[arg(long, value_enum, default_value_t = Policy::Block)]
+    policy: Policy,
+}
+
+/// A rate the producer can tick at: more than zero, and not so little that
+/// the wait between tasks overflows a `Duration`.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if rate > 0.0 && Duration::try_from_secs_f64(1.0 / rate).is_ok() {
+        Ok(rate)
+    } else {
+        Err("expected a positive number of tasks a second".to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Policy {
+    /// Wait for room, holding the producer back.
+    Block,
+    /// Drop the task and carry on.
+    Drop,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    produced: AtomicU64,
+    dropped: AtomicU64,
+    done: AtomicU64,
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let (tx, rx) = mpsc::channel(42);
-    let handler = worker(rx);
-    tokio::spawn(async move {
-        let mut i = 0;
-        loop {
-            i += 1;
-            println!("sending task {}", i);
-            tx.send(format!("task {}", i)).await?;
-        }
-        #[allow(unreachable_code)]
-        Ok::<(), anyhow::Error>(())
-    });
+    tracing_subscriber::registry()
+        .with(ecosystem::console::layer())
+        .init();
+    let args = Args::parse();
+    let (tx, rx) = mpsc::channel(args.capacity);
+    let stats = Arc::new(Stats::default());
+    let handler = pool(
+        args.workers,
+        rx,
+        Duration::from_millis(args.work_ms),
+        stats.clone(),
+    );
+    tokio::spawn(report(tx.clone(), stats.clone()));
+    tokio::spawn(produce(tx, args.rate, args.policy, stats));
 
-    handler.await.unwrap();
+    handler.await?;
 
     Ok(())
 }
 
-async fn worker(mut rx: Receiver<String>) -> anyhow::Result<()> {
-    // thread::spawn(move || {
-    //     while let Some(s) = rx.blocking_recv() {
-    //         thread::sleep(Duration::from_millis(500));
-    //         println!("received: {}", s);
-    //     }
-    // })
-
-    tokio::task::spawn_blocking(move || {
-        while let Some(s) = rx.blocking_recv() {
-            thread::sleep(Duration::from_millis(500));
-            println!("received: {}", s);
+async fn produce(
+    tx: Sender<String>,
+    rate: Option<f64>,
+    policy: Policy,
+    stats: Arc<Stats>,
+) -> anyhow::Result<()> {
+    let mut interval = rate.map(|rate| {
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        // after waiting on a full channel, carry on at the rate rather than
+        // catching up in a burst
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+    for i in 1.. {
+        if let Some(interval) = &mut interval {
+            interval.tick().await;
+        }
+        let task = format!("task {}", i);
+        match policy {
+            Policy::Block => tx.send(task).await?,
+            Policy::Drop => match tx.try_send(task) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    // without a rate, give the workers a chance to take some
+                    if interval.is_none() {
+                        tokio::task::yield_now().await;
+                    }
+                    continue;
+                }
+                Err(e @ TrySendError::Closed(_)) => return Err(e.into()),
+            },
         }
-    })
-    .await?;
+        stats.produced.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// `workers` blocking threads sharing one receiver: whoever holds the lock
+/// waits in `blocking_recv`, the others wait for the lock, so each task
+/// goes to exactly one of them.
+async fn pool(
+    workers: usize,
+    rx: Receiver<String>,
+    work: Duration,
+    stats: Arc<Stats>,
+) -> anyhow::Result<()> {
+    let rx = Arc::new(Mutex::new(rx));
+    let handles: Vec<_> = (0..workers)
+        .map(|id| {
+            let rx = rx.clone();
+            let stats = stats.clone();
+            tokio::task::spawn_blocking(move || worker(id, &rx, work, &stats))
+        })
+        .collect();
+    for handle in handles {
+        handle.await?;
+    }
     Ok(())
 }
+
+fn worker(id: usize, rx: &Mutex<Receiver<String>>, work: Duration, stats: &Stats) {
+    loop {
+        // the guard is dropped before the work starts, freeing the channel
+        // for the next worker
+        let Some(s) = rx.lock().unwrap().blocking_recv() else {
+            break;
+        };
+        thread::sleep(work);
+        stats.done.fetch_add(1, Ordering::Relaxed);
+        println!("worker {} received: {}", id, s);
+    }
+}
+
+/// Print the tasks done in each second, and on average since the start,
+/// with what was produced and dropped and how full the queue is.
+async fn report(tx: Sender<String>, stats: Arc<Stats>) {
+    let start = Instant::now();
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    interval.tick().await;
+    let (mut last_done, mut last_produced) = (0, 0);
+    loop {
+        interval.tick().await;
+        let done = stats.done.load(Ordering::Relaxed);
+        let produced = stats.produced.load(Ordering::Relaxed);
+        let average = done as f64 / start.elapsed().as_secs_f64();
+        println!(
+            "throughput: {} tasks/s, {:.1} tasks/s on average; produced {}/s, \
+             dropped {} in all, queue {}/{}",
+            done - last_done,
+            average,
+            produced - last_produced,
+            stats.dropped.load(Ordering::Relaxed),
+            tx.max_capacity() - tx.capacity(),
+            tx.max_capacity()
+        );
+        (last_done, last_produced) = (done, produced);
+    }
+}
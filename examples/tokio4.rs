@@ -0,0 +1,122 @@
+//! Graceful shutdown of a long-running service: a producer and a few workers
+//! run in a `TaskTracker` until ctrl-c (or SIGTERM) cancels a shared
+//! `CancellationToken`. Workers then finish the job in hand and stop, and
+//! the service waits for them, but no longer than `DEADLINE`.
+//!
+//! ```text
+//! cargo run --example tokio4          # until ctrl-c
+//! cargo run --example tokio4 -- 3     # or for 3 seconds
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+const WORKERS: usize = 4;
+/// How long workers get to finish once cancelled.
+const DEADLINE: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Job {
+    id: u64,
+    work: Duration,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(fmt::layer().with_filter(LevelFilter::INFO))
+        .with(ecosystem::console::layer())
+        .init();
+    let run_for = std::env::args()
+        .nth(1)
+        .map(|secs| secs.parse().map(Duration::from_secs))
+        .transpose()?;
+
+    let token = CancellationToken::new();
+    let tracker = TaskTracker::new();
+    let (tx, rx) = mpsc::channel(WORKERS);
+    let rx = Arc::new(Mutex::new(rx));
+
+    tracker.spawn(produce(tx, token.clone()));
+    for worker in 0..WORKERS {
+        tracker.spawn(work(worker, rx.clone(), token.clone()));
+    }
+    // nothing else gets tracked, so `wait` can return once these are done
+    tracker.close();
+
+    shutdown_signal(run_for).await?;
+    info!("shutting down, waiting up to {:?}", DEADLINE);
+    token.cancel();
+    match tokio::time::timeout(DEADLINE, tracker.wait()).await {
+        Ok(()) => info!("all tasks finished"),
+        Err(_) => warn!("{} task(s) still running, exiting anyway", tracker.len()),
+    }
+    Ok(())
+}
+
+/// Ctrl-c, SIGTERM, or `run_for` elapsing, whichever comes first.
+async fn shutdown_signal(run_for: Option<Duration>) -> anyhow::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let elapsed = async {
+        match run_for {
+            Some(run_for) => tokio::time::sleep(run_for).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+        _ = elapsed => {}
+    }
+    Ok(())
+}
+
+/// Hands out a job every 200ms until cancelled. Dropping `tx` on the way
+/// out tells the workers no more are coming.
+async fn produce(tx: mpsc::Sender<Job>, token: CancellationToken) {
+    let mut interval = tokio::time::interval(Duration::from_millis(200));
+    for id in 1.. {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        let work = Duration::from_millis(300 * (id % 5 + 1));
+        // a full queue would block the send past cancellation otherwise
+        tokio::select! {
+            _ = token.cancelled() => break,
+            sent = tx.send(Job { id, work }) => if sent.is_err() { break },
+        }
+    }
+    info!("producer stopped");
+}
+
+/// Takes jobs until cancelled; a job already started is finished first.
+async fn work(worker: usize, rx: Arc<Mutex<mpsc::Receiver<Job>>>, token: CancellationToken) {
+    loop {
+        // biased, or a queued job could still win over cancellation
+        let job = tokio::select! {
+            biased;
+            _ = token.cancelled() => break,
+            job = async { rx.lock().await.recv().await } => job,
+        };
+        let Some(job) = job else {
+            break;
+        };
+        tokio::time::sleep(job.work).await;
+        info!(
+            "worker {} finished job {} in {:?}",
+            worker, job.id, job.work
+        );
+    }
+    info!("worker {} stopped", worker);
+}
@@ -0,0 +1,127 @@
+//! `tokio::select!` racing a slow operation against a timeout and a
+//! cancellation signal, and what happens to the branches that lose:
+//!
+//! - a future that loses is dropped where it stands, so anything it holds
+//!   is released by `Drop` (see [`Connection`]);
+//! - a spawned task that loses is not: dropping its `JoinHandle` detaches
+//!   it, so it has to be aborted, and awaited to know it's gone;
+//! - in a loop, only cancel-safe futures such as `mpsc::Receiver::recv` may
+//!   be recreated every time round, as [`idle`] does to notice a quiet
+//!   client.
+
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[derive(Debug)]
+enum Outcome {
+    Done(&'static str),
+    TimedOut,
+    Cancelled,
+}
+
+/// Stands in for whatever a slow operation holds open.
+struct Connection(&'static str);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        println!("  {}: connection closed", self.0);
+    }
+}
+
+async fn slow_operation(name: &'static str, work: Duration) -> &'static str {
+    let _connection = Connection(name);
+    sleep(work).await;
+    name
+}
+
+/// `work` against `timeout` and a token cancelled after `cancel_after`, with
+/// a little more of it spawned alongside that has to go when the race ends.
+async fn race(
+    name: &'static str,
+    work: Duration,
+    timeout: Duration,
+    cancel_after: Duration,
+) -> Outcome {
+    let token = CancellationToken::new();
+    let canceller = tokio::spawn({
+        let token = token.clone();
+        async move {
+            sleep(cancel_after).await;
+            token.cancel();
+        }
+    });
+    let spawned = tokio::spawn(slow_operation("spawned", work + Duration::from_millis(100)));
+
+    let outcome = tokio::select! {
+        done = slow_operation(name, work) => Outcome::Done(done),
+        _ = sleep(timeout) => Outcome::TimedOut,
+        _ = token.cancelled() => Outcome::Cancelled,
+    };
+
+    // the in-line operation, if it lost, is already dropped; the tasks are not
+    canceller.abort();
+    if !spawned.is_finished() {
+        spawned.abort();
+        match spawned.await {
+            Err(e) if e.is_cancelled() => println!("  spawned: aborted"),
+            _ => println!("  spawned: finished before the abort"),
+        }
+    }
+    outcome
+}
+
+/// Reads messages until none comes for `timeout`. `recv` is cancel-safe:
+/// a message isn't lost when the deadline wins, so it can be recreated each
+/// time round while the deadline itself only moves when something arrives.
+async fn idle(mut rx: mpsc::Receiver<String>, timeout: Duration) {
+    let mut deadline = Instant::now() + timeout;
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Some(msg) => {
+                    println!("  got {:?}", msg);
+                    deadline = Instant::now() + timeout;
+                }
+                None => return println!("  sender gone"),
+            },
+            _ = sleep_until(deadline) => return println!("  idle for {:?}", timeout),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(ecosystem::console::layer())
+        .init();
+    ecosystem::console::wait_for_client();
+    let ms = Duration::from_millis;
+    for (name, work, timeout, cancel_after) in [
+        ("fast", ms(100), ms(300), ms(500)),
+        ("slow", ms(1000), ms(300), ms(500)),
+        ("cancelled", ms(1000), ms(800), ms(200)),
+    ] {
+        println!("{}:", name);
+        match race(name, work, timeout, cancel_after).await {
+            Outcome::Done(by) => println!("  => done by {}", by),
+            outcome => println!("  => {:?}", outcome),
+        }
+    }
+
+    println!("idle:");
+    let (tx, rx) = mpsc::channel(8);
+    let reader = tokio::spawn(idle(rx, ms(250)));
+    for gap in [50, 100, 200, 400] {
+        sleep(ms(gap)).await;
+        if tx.send(format!("after {}ms", gap)).await.is_err() {
+            break;
+        }
+    }
+    reader.await?;
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! The same batch of jobs spread over a few workers three ways, with when
+//! each job finished summarised per strategy:
+//!
+//! - `shared`: one channel, every worker taking the next job off it as soon
+//!   as it is free;
+//! - `round-robin`: a channel per worker, job `i` going to worker
+//!   `i % workers` whatever that worker is busy with;
+//! - `joinset`: no workers at all, a task per job in a `JoinSet` that is
+//!   never allowed more than `workers` in flight.
+//!
+//! Every `--long-every`th job takes ten times as long. Round-robin lines
+//! those up behind each other on a single worker while the rest sit idle;
+//! the other two hand the next job to whoever is free and finish together.
+//! Round-robin's median still looks best, the short jobs on the other
+//! workers never waiting behind a long one; it's the tail that shows it.
+//! A job's work is a blocking sleep standing in for CPU-bound work, so it
+//! runs on the blocking pool as it would have to.
+//!
+//! ```text
+//! cargo run --example tokio6 -- --workers 4 --jobs 200
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::builder::RangedU64ValueParser;
+use clap::Parser;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinSet;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Jobs run at once.
+    #[arg(long, default_value_t = 4, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    workers: usize,
+    /// Jobs in the batch.
+    #[arg(long, default_value_t = 200, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    jobs: usize,
+    /// How long an ordinary job takes, in milliseconds.
+    #[arg(long, default_value_t = 5)]
+    work_ms: u64,
+    /// Every this many jobs, one takes ten times as long.
+    #[arg(long, default_value_t = 8, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    long_every: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Job {
+    id: usize,
+    work: Duration,
+}
+
+/// When each job finished, measured from the start of the batch.
+type Finished = Vec<Duration>;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(ecosystem::console::layer())
+        .init();
+    ecosystem::console::wait_for_client();
+    let args = Args::parse();
+    let jobs: Vec<Job> = (0..args.jobs)
+        .map(|id| {
+            let factor = if id % args.long_every == 0 { 10 } else { 1 };
+            Job {
+                id,
+                work: Duration::from_millis(args.work_ms * factor),
+            }
+        })
+        .collect();
+    let total: Duration = jobs.iter().map(|job| job.work).sum();
+    println!(
+        "{} jobs, {:?} of work in all, {} workers: {:?} at best",
+        jobs.len(),
+        total,
+        args.workers,
+        total.div_f64(args.workers as f64)
+    );
+
+    report("shared", time(shared(args.workers, &jobs)).await?);
+    report("round-robin", time(round_robin(args.workers, &jobs)).await?);
+    report("joinset", time(join_set(args.workers, &jobs)).await?);
+    Ok(())
+}
+
+/// Runs one strategy, turning the instants its jobs finished at into times
+/// since it started, sorted.
+async fn time(
+    strategy: impl Future<Output = anyhow::Result<Vec<Instant>>>,
+) -> anyhow::Result<Finished> {
+    let start = Instant::now();
+    let mut finished: Finished = strategy.await?.into_iter().map(|at| at - start).collect();
+    finished.sort_unstable();
+    Ok(finished)
+}
+
+/// The job's work, off the async workers.
+async fn run(job: Job) -> anyhow::Result<Instant> {
+    tokio::task::spawn_blocking(move || {
+        thread::sleep(job.work);
+        Instant::now()
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("job {} failed: {}", job.id, e))
+}
+
+async fn shared(workers: usize, jobs: &[Job]) -> anyhow::Result<Vec<Instant>> {
+    let (tx, rx) = mpsc::channel(jobs.len());
+    for job in jobs {
+        tx.send(*job).await?;
+    }
+    drop(tx);
+
+    let rx = Arc::new(Mutex::new(rx));
+    let mut handles = Vec::with_capacity(workers);
+    for _ in 0..workers {
+        let rx = rx.clone();
+        handles.push(tokio::spawn(async move {
+            let mut finished = Vec::new();
+            loop {
+                // a statement of its own, so the guard is dropped before the
+                // job runs; in a `while let` it would live through the body
+                // and the workers would take turns
+                let job = rx.lock().await.recv().await;
+                let Some(job) = job else {
+                    break;
+                };
+                finished.push(run(job).await?);
+            }
+            Ok::<_, anyhow::Error>(finished)
+        }));
+    }
+    collect(handles).await
+}
+
+async fn round_robin(workers: usize, jobs: &[Job]) -> anyhow::Result<Vec<Instant>> {
+    let mut senders = Vec::with_capacity(workers);
+    let mut handles = Vec::with_capacity(workers);
+    for _ in 0..workers {
+        let (tx, mut rx) = mpsc::channel(jobs.len());
+        senders.push(tx);
+        handles.push(tokio::spawn(async move {
+            let mut finished = Vec::new();
+            while let Some(job) = rx.recv().await {
+                finished.push(run(job).await?);
+            }
+            Ok::<_, anyhow::Error>(finished)
+        }));
+    }
+    for (job, tx) in jobs.iter().zip(senders.iter().cycle()) {
+        tx.send(*job).await?;
+    }
+    drop(senders);
+    collect(handles).await
+}
+
+async fn join_set(workers: usize, jobs: &[Job]) -> anyhow::Result<Vec<Instant>> {
+    let mut set = JoinSet::new();
+    let mut finished = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        // at the limit, wait for one to finish before starting the next
+        if set.len() == workers {
+            if let Some(done) = set.join_next().await {
+                finished.push(done??);
+            }
+        }
+        set.spawn(run(*job));
+    }
+    while let Some(done) = set.join_next().await {
+        finished.push(done??);
+    }
+    Ok(finished)
+}
+
+async fn collect(
+    handles: Vec<tokio::task::JoinHandle<anyhow::Result<Vec<Instant>>>>,
+) -> anyhow::Result<Vec<Instant>> {
+    let mut finished = Vec::new();
+    for handle in handles {
+        finished.extend(handle.await??);
+    }
+    Ok(finished)
+}
+
+fn report(name: &str, finished: Finished) {
+    let at = |p: usize| finished[(finished.len() - 1) * p / 100];
+    println!(
+        "{:>12}: p50 {:>8.1?}  p90 {:>8.1?}  p99 {:>8.1?}  all done {:>8.1?}",
+        name,
+        at(50),
+        at(90),
+        at(99),
+        at(100)
+    );
+}
@@ -0,0 +1,127 @@
+//! Structured concurrency with `JoinSet`: tasks spawned on a set belong to
+//! it, rather than running detached as with `tokio::spawn`.
+//!
+//! - `join_next` hands back how each task ended, including a panic, which a
+//!   detached task would only print to stderr before vanishing;
+//! - a set that is dropped aborts whatever it still holds, so no task can
+//!   outlive the scope that started it;
+//! - on shutdown, `abort_all` and draining the set (or `shutdown`) waits
+//!   until every task is really gone, not just asked to stop.
+//!
+//! The chat server's accept loops hold their clients the same way.
+
+use std::any::Any;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(ecosystem::console::layer())
+        .init();
+    ecosystem::console::wait_for_client();
+
+    // a panicking task prints through the hook as well; keep the output to
+    // what the set reports
+    std::panic::set_hook(Box::new(|_| {}));
+
+    println!("outcomes:");
+    outcomes().await;
+    println!("drop:");
+    dropped().await;
+    println!("shutdown:");
+    shutdown().await;
+    Ok(())
+}
+
+/// Every task's result comes back, in the order they finish.
+async fn outcomes() {
+    let mut set = JoinSet::new();
+    set.spawn(async {
+        sleep(Duration::from_millis(10)).await;
+        Ok("done")
+    });
+    set.spawn(async {
+        sleep(Duration::from_millis(20)).await;
+        Err(anyhow!("gave up"))
+    });
+    set.spawn(async {
+        sleep(Duration::from_millis(30)).await;
+        panic!("bug");
+    });
+    while let Some(result) = set.join_next().await {
+        match result {
+            Ok(Ok(value)) => println!("  finished: {}", value),
+            Ok(Err(e)) => println!("  failed: {}", e),
+            Err(e) if e.is_panic() => println!("  panicked: {}", panic_message(e.into_panic())),
+            Err(e) => println!("  cancelled: {}", e),
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<&str>() {
+        Ok(message) => message.to_string(),
+        Err(payload) => payload
+            .downcast::<String>()
+            .map(|message| *message)
+            .unwrap_or_else(|_| "(not a string)".to_string()),
+    }
+}
+
+/// A detached task keeps running after its scope is gone; one on a set is
+/// aborted with it.
+async fn dropped() {
+    let ticks = Arc::new(AtomicUsize::new(0));
+    {
+        let mut set = JoinSet::new();
+        set.spawn(tick(ticks.clone()));
+        tokio::spawn(tick(ticks.clone()));
+        sleep(Duration::from_millis(55)).await;
+    }
+    let at_drop = ticks.load(Ordering::Relaxed);
+    sleep(Duration::from_millis(50)).await;
+    println!(
+        "  {} ticks when the scope ended, {} since: only the detached task is left",
+        at_drop,
+        ticks.load(Ordering::Relaxed) - at_drop
+    );
+}
+
+async fn tick(ticks: Arc<AtomicUsize>) {
+    loop {
+        sleep(Duration::from_millis(10)).await;
+        ticks.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Abort what is left and wait for it to be gone. A task finishing before
+/// the abort lands still reports its own result.
+async fn shutdown() {
+    let mut set = JoinSet::new();
+    for id in 1..=4 {
+        set.spawn(async move {
+            sleep(Duration::from_millis(40 * id)).await;
+            id
+        });
+    }
+    sleep(Duration::from_millis(100)).await;
+    println!("  aborting, {} task(s) not yet joined", set.len());
+    set.abort_all();
+    let (mut finished, mut aborted) = (0, 0);
+    while let Some(result) = set.join_next().await {
+        match result {
+            Ok(_) => finished += 1,
+            Err(e) if e.is_cancelled() => aborted += 1,
+            Err(e) => println!("  panicked: {}", e),
+        }
+    }
+    println!("  {} finished, {} aborted, none left", finished, aborted);
+}
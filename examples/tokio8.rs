@@ -0,0 +1,75 @@
+//! A supervisor keeping three children going:
+//!
+//! - `ticker` runs fine and is left alone;
+//! - `flaky` panics on its first two runs and is restarted after 200ms,
+//!   then 400ms, before a run that lasts;
+//! - `broken` fails every time, and is given up on after three restarts.
+//!
+//! See `ecosystem::supervisor` for the policy; minginx runs its health
+//! checks under the same supervisor.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use ecosystem::supervisor::{RestartPolicy, Supervisor};
+use tokio::time::sleep;
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::registry()
+        .with(fmt::layer().with_filter(LevelFilter::INFO))
+        .with(ecosystem::console::layer())
+        .init();
+    ecosystem::console::wait_for_client();
+
+    // the panics are reported by the supervisor
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let policy = RestartPolicy {
+        backoff: Duration::from_millis(200),
+        max_backoff: Duration::from_secs(2),
+        max_restarts: 3,
+        ..Default::default()
+    };
+    let mut supervisor = Supervisor::new();
+
+    supervisor.spawn("ticker", policy.clone(), || async {
+        for tick in 1.. {
+            sleep(Duration::from_millis(500)).await;
+            info!("tick {}", tick);
+        }
+        Ok(())
+    });
+
+    let runs = Arc::new(AtomicU32::new(0));
+    supervisor.spawn("flaky", policy.clone(), move || {
+        let run = runs.fetch_add(1, Ordering::Relaxed) + 1;
+        async move {
+            sleep(Duration::from_millis(100)).await;
+            if run <= 2 {
+                panic!("run {} fell over", run);
+            }
+            info!("flaky is up on run {}", run);
+            std::future::pending().await
+        }
+    });
+
+    supervisor.spawn("broken", policy, || async {
+        sleep(Duration::from_millis(50)).await;
+        Err(anyhow!("can't reach the database"))
+    });
+
+    if let Some(gave_up) = supervisor.join_next().await {
+        info!("{}", gave_up);
+    }
+    sleep(Duration::from_secs(1)).await;
+    info!("{} children still running, stopping", supervisor.len());
+    Ok(())
+}
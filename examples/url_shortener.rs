@@ -4,6 +4,7 @@ use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{debug_handler, Json, Router};
+use ecosystem::ids::SlugId;
 use log::warn;
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
@@ -58,7 +59,7 @@ struct ShortenResp {
 #[derive(Debug, Default, sqlx::FromRow)]
 #[sqlx(default)]
 struct UrlRecord {
-    id: String,
+    id: SlugId,
     url: String,
 }
 
@@ -78,10 +79,10 @@ impl AppState {
         Ok(Self { db })
     }
 
-    async fn shorten(&self, url: String) -> anyhow::Result<String, AppError> {
+    async fn shorten(&self, url: String) -> anyhow::Result<SlugId, AppError> {
         let sql = "INSERT INTO urls(id, url) VALUES($1, $2) ON CONFLICT(url) \
         DO UPDATE SET url=EXCLUDED.url RETURNING id";
-        let mut id = nanoid!(6);
+        let mut id = SlugId::from(nanoid!(6));
         let url = Arc::new(url);
         let url_cloned = url.clone();
         loop {
@@ -97,13 +98,13 @@ impl AppState {
                 }
                 Err(e) => {
                     warn!("duplicate id generated({}): {}", id, e);
-                    id = nanoid!(6); // regenerate id
+                    id = nanoid!(6).into(); // regenerate id
                 }
             }
         }
     }
 
-    async fn get_url(&self, id: String) -> anyhow::Result<String, AppError> {
+    async fn get_url(&self, id: SlugId) -> anyhow::Result<String, AppError> {
         let record: UrlRecord = sqlx::query_as("SELECT * FROM urls WHERE id = $1")
             .bind(id)
             .fetch_one(&self.db)
@@ -136,7 +137,7 @@ async fn main() -> anyhow::Result<()> {
 
 #[debug_handler]
 async fn redirect(
-    Path(id): Path<String>,
+    Path(id): Path<SlugId>,
     State(pg): State<AppState>,
 ) -> anyhow::Result<impl IntoResponse, AppError> {
     let url = pg.get_url(id).await?;
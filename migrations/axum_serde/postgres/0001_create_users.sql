@@ -0,0 +1,8 @@
+-- `skills` holds a JSON array of strings, as text so the same queries work
+-- on SQLite.
+CREATE TABLE IF NOT EXISTS users (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL,
+    age SMALLINT NOT NULL CHECK (age BETWEEN 0 AND 255),
+    skills TEXT NOT NULL DEFAULT '[]'
+);
@@ -0,0 +1,2 @@
+-- A WorkState as JSON; everyone already here is working.
+ALTER TABLE users ADD COLUMN state TEXT NOT NULL DEFAULT '{"type":"working","details":""}';
@@ -0,0 +1,12 @@
+-- One row per PATCH, never updated or deleted: `changes` holds a JSON object
+-- of the fields touched, each with its value `from` and `to`. `at` is RFC
+-- 3339 text, like everything else shared with SQLite.
+CREATE TABLE IF NOT EXISTS user_changes (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    version BIGINT NOT NULL,
+    actor TEXT NOT NULL,
+    at TEXT NOT NULL,
+    changes TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS user_changes_by_user ON user_changes (user_id, version);
@@ -0,0 +1,2 @@
+-- Empty for users who gave no address, so nothing ever binds a NULL.
+ALTER TABLE users ADD COLUMN email TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,7 @@
+-- `skills` holds a JSON array of strings.
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    age INTEGER NOT NULL CHECK (age BETWEEN 0 AND 255),
+    skills TEXT NOT NULL DEFAULT '[]'
+);
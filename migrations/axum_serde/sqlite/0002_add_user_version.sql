@@ -0,0 +1,2 @@
+-- Bumped on every update; clients send it back in `If-Match`.
+ALTER TABLE users ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,211 @@
+//! A bounded channel pair between async tasks and a thread that blocks:
+//! async code hands work to the thread over one channel and gets results
+//! back over the other. The thread must not be a runtime worker (use
+//! `std::thread::spawn` or `spawn_blocking`), as its end blocks.
+//!
+//! Shutdown runs in order: [`BlockingBridge::close`] stops new work, the
+//! thread sees `None` once it has drained what was already queued, and its
+//! last results are still there to [`BlockingBridge::recv`] before that
+//! returns `None` too.
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{SendError, SendTimeoutError};
+
+/// The async end: sends work of type `T`, receives results of type `R`.
+#[derive(Debug)]
+pub struct BlockingBridge<T, R = T> {
+    tx: Option<mpsc::Sender<T>>,
+    rx: mpsc::Receiver<R>,
+}
+
+/// The blocking end, for the thread: receives work, sends results back.
+#[derive(Debug)]
+pub struct BlockingEnd<T, R = T> {
+    rx: mpsc::Receiver<T>,
+    tx: mpsc::Sender<R>,
+    handle: Handle,
+}
+
+impl<T, R> BlockingBridge<T, R> {
+    /// Both ends, each channel holding up to `capacity` values. Must be
+    /// called from within a runtime, which the blocking end uses to wait
+    /// with a timeout.
+    pub fn new(capacity: usize) -> (Self, BlockingEnd<T, R>) {
+        let (work_tx, work_rx) = mpsc::channel(capacity);
+        let (result_tx, result_rx) = mpsc::channel(capacity);
+        let bridge = Self {
+            tx: Some(work_tx),
+            rx: result_rx,
+        };
+        let end = BlockingEnd {
+            rx: work_rx,
+            tx: result_tx,
+            handle: Handle::current(),
+        };
+        (bridge, end)
+    }
+
+    /// Queue work, waiting for room. Fails, handing `value` back, once the
+    /// bridge is closed or the thread has let go of its end.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        match &self.tx {
+            Some(tx) => tx.send(value).await,
+            None => Err(SendError(value)),
+        }
+    }
+
+    /// [`send`](Self::send), giving up after `timeout`.
+    pub async fn send_timeout(
+        &self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), SendTimeoutError<T>> {
+        match &self.tx {
+            Some(tx) => tx.send_timeout(value, timeout).await,
+            None => Err(SendTimeoutError::Closed(value)),
+        }
+    }
+
+    /// The next result, or `None` once the thread has let go of its end and
+    /// every result it sent has been received.
+    pub async fn recv(&mut self) -> Option<R> {
+        self.rx.recv().await
+    }
+
+    /// Send no more work. What is queued is still handed to the thread,
+    /// and its results can still be received.
+    pub fn close(&mut self) {
+        self.tx = None;
+    }
+}
+
+impl<T, R> BlockingEnd<T, R> {
+    /// The next piece of work, blocking until there is one. `None` once the
+    /// bridge is closed or dropped and the queue is empty.
+    pub fn recv(&mut self) -> Option<T> {
+        self.rx.blocking_recv()
+    }
+
+    /// [`recv`](Self::recv), giving up after `timeout`.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let rx = &mut self.rx;
+        // `timeout` sets its timer up when called, so it has to be called
+        // inside `block_on`, where the runtime is entered
+        let received = self
+            .handle
+            .block_on(async { tokio::time::timeout(timeout, rx.recv()).await });
+        match received {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => Err(RecvTimeoutError::Disconnected),
+            Err(_) => Err(RecvTimeoutError::Timeout),
+        }
+    }
+
+    /// Send a result back, blocking while the async end has a full queue.
+    /// Fails, handing `value` back, once that end is dropped.
+    pub fn send(&self, value: R) -> Result<(), SendError<R>> {
+        self.tx.blocking_send(value)
+    }
+
+    /// [`send`](Self::send), giving up after `timeout`.
+    pub fn send_timeout(&self, value: R, timeout: Duration) -> Result<(), SendTimeoutError<R>> {
+        self.handle.block_on(self.tx.send_timeout(value, timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// Doubles whatever it's given until there is no more.
+    fn doubler(mut end: BlockingEnd<u32>) {
+        while let Some(n) = end.recv() {
+            if end.send(n * 2).is_err() {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn close_drains_queued_work_before_the_thread_stops() {
+        let (mut bridge, end) = BlockingBridge::new(8);
+        for n in 1..=3 {
+            bridge.send(n).await.unwrap();
+        }
+        bridge.close();
+        assert_eq!(bridge.send(4).await, Err(SendError(4)));
+
+        let worker = thread::spawn(move || doubler(end));
+        let mut results = Vec::new();
+        while let Some(n) = bridge.recv().await {
+            results.push(n);
+        }
+        assert_eq!(results, [2, 4, 6]);
+        worker.join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn thread_gone_fails_sends_and_ends_results() {
+        let (mut bridge, end) = BlockingBridge::<u32>::new(1);
+        thread::spawn(move || {
+            end.send(7).unwrap();
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(bridge.send(1).await, Err(SendError(1)));
+        assert_eq!(bridge.recv().await, Some(7));
+        assert_eq!(bridge.recv().await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn dropping_the_async_end_stops_the_thread() {
+        let (bridge, mut end) = BlockingBridge::<u32>::new(1);
+        bridge.send(1).await.unwrap();
+        drop(bridge);
+
+        let worker = thread::spawn(move || {
+            let first = end.recv();
+            let sent = end.send(2);
+            (first, sent, end.recv())
+        });
+        let (first, sent, next) = tokio::task::spawn_blocking(move || worker.join().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(first, Some(1));
+        assert_eq!(sent, Err(SendError(2)));
+        assert_eq!(next, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn timeouts_on_both_ends() {
+        let (bridge, mut end) = BlockingBridge::<u32>::new(1);
+        let short = Duration::from_millis(20);
+
+        bridge.send(1).await.unwrap();
+        assert!(matches!(
+            bridge.send_timeout(2, short).await,
+            Err(SendTimeoutError::Timeout(2))
+        ));
+
+        let worker = thread::spawn(move || {
+            let first = end.recv_timeout(short);
+            let second = end.recv_timeout(short);
+            end.send(10).unwrap();
+            let full = end.send_timeout(11, short);
+            (first, second, full)
+        });
+        let (first, second, full) = tokio::task::spawn_blocking(move || worker.join().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(first, Ok(1));
+        assert_eq!(second, Err(RecvTimeoutError::Timeout));
+        assert!(matches!(full, Err(SendTimeoutError::Timeout(11))));
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use super::PeerAddr;
+use crate::observed::{self, ObservedSender};
+
+/// Lifecycle events and room changes worth keeping a record of.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    Join,
+    Leave,
+    Nick { from: String, to: String },
+    Topic { topic: String },
+}
+
+#[derive(Debug, Serialize)]
+struct AuditRecord {
+    at: DateTime<Utc>,
+    actor: String,
+    peer: String,
+    #[serde(flatten)]
+    event: AuditEvent,
+}
+
+/// Append-only JSON-lines audit trail, one record per line, rotated by size
+/// (`audit.jsonl` → `audit.jsonl.1` → ... → `audit.jsonl.<keep>`). Records
+/// are written by a background task so file I/O never stalls a client.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    tx: ObservedSender<AuditRecord>,
+}
+
+impl AuditLog {
+    pub async fn open(
+        path: impl Into<PathBuf>,
+        max_bytes: u64,
+        keep: usize,
+    ) -> anyhow::Result<Self> {
+        let path = path.into();
+        let file = open_append(&path).await?;
+        let written = file.metadata().await?.len();
+        let (tx, rx) = observed::channel("audit", 1024);
+        let writer = Writer {
+            path,
+            file,
+            written,
+            max_bytes,
+            keep,
+        };
+        tokio::spawn(writer.run(rx));
+        Ok(Self { tx })
+    }
+
+    pub async fn record(&self, actor: &str, peer: PeerAddr, event: AuditEvent) {
+        let record = AuditRecord {
+            at: Utc::now(),
+            actor: actor.to_string(),
+            peer: peer.to_string(),
+            event,
+        };
+        if self.tx.send(record).await.is_err() {
+            warn!("audit writer gone, dropping record");
+        }
+    }
+}
+
+async fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+struct Writer {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl Writer {
+    async fn run(mut self, mut rx: mpsc::Receiver<AuditRecord>) {
+        while let Some(record) = rx.recv().await {
+            if let Err(e) = self.write(&record).await {
+                error!(
+                    "failed writing audit record to {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    async fn write(&mut self, record: &AuditRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate().await?;
+        }
+        self.file.write_all(&line).await?;
+        self.file.flush().await?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    async fn rotate(&mut self) -> anyhow::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        for n in (1..self.keep).rev() {
+            match tokio::fs::rename(rotated(n), rotated(n + 1)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        if self.keep > 0 {
+            tokio::fs::rename(&self.path, rotated(1)).await?;
+        } else {
+            tokio::fs::remove_file(&self.path).await?;
+        }
+        self.file = open_append(&self.path).await?;
+        self.written = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+
+use crate::ids::UserId;
+
+#[derive(Debug)]
+pub enum Message {
+    UserJoin(UserId),
+    UserLeft(UserId),
+    Chat { user_name: UserId, content: String },
+}
+
+impl Message {
+    pub fn chat(user_name: UserId, content: String) -> Self {
+        Self::Chat { user_name, content }
+    }
+    pub fn user_join(user_name: UserId) -> Self {
+        Self::UserJoin(user_name)
+    }
+    pub fn user_left(user_name: UserId) -> Self {
+        Self::UserLeft(user_name)
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Message::UserJoin(name) => write!(f, "{} joined the chat.", name),
+            Message::UserLeft(name) => write!(f, "{} left the chat.", name),
+            Message::Chat { user_name, content } => write!(f, "{}:{}", user_name, content),
+        }
+    }
+}
+
+/// Fan-out of chat events to every connected client over a tokio broadcast
+/// channel, as used by the `chat_mpsc_broadcast` and `chat_grpc` examples.
+#[derive(Debug, Clone)]
+pub struct MessageBus {
+    tx: Sender<Arc<Message>>,
+}
+
+impl MessageBus {
+    pub fn new() -> Self {
+        let (tx, _) = channel(512);
+        Self { tx }
+    }
+
+    pub fn get_sender(&self) -> Sender<Arc<Message>> {
+        self.tx.clone()
+    }
+
+    pub fn get_receiver(&self) -> Receiver<Arc<Message>> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for MessageBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+use std::io;
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder, LinesCodec, LinesCodecError};
+
+use super::style::strip_ansi;
+
+/// Largest frame we accept once compression is on, before or after
+/// decompression, so a hostile client can't make us allocate without bound.
+const MAX_FRAME: usize = 1024 * 1024;
+
+/// Newline-delimited text by default; after a client negotiates
+/// `CAP zstd` every line is sent as a length-prefixed zstd frame instead.
+/// Callers keep sending and receiving `String`s either way.
+///
+/// Outgoing ANSI escapes are stripped unless the client negotiated
+/// `CAP color`, so plain terminals and telnet never see them.
+#[derive(Debug, Default)]
+pub struct ChatCodec {
+    lines: LinesCodec,
+    zstd: bool,
+    color: bool,
+}
+
+impl ChatCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switch both directions to zstd frames. Anything already flushed stays
+    /// plain text, so send the acknowledgement before calling this.
+    pub fn enable_zstd(&mut self) {
+        self.zstd = true;
+    }
+
+    pub fn is_zstd(&self) -> bool {
+        self.zstd
+    }
+
+    pub fn enable_color(&mut self) {
+        self.color = true;
+    }
+
+    pub fn is_color(&self) -> bool {
+        self.color
+    }
+}
+
+fn invalid_data(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> LinesCodecError {
+    LinesCodecError::Io(io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl Decoder for ChatCodec {
+    type Item = String;
+    type Error = LinesCodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<String>, LinesCodecError> {
+        if !self.zstd {
+            return self.lines.decode(src);
+        }
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        if len > MAX_FRAME {
+            return Err(LinesCodecError::MaxLineLengthExceeded);
+        }
+        if src.len() < 4 + len {
+            src.reserve(4 + len - src.len());
+            return Ok(None);
+        }
+        src.advance(4);
+        let frame = src.split_to(len);
+        let line = zstd::bulk::decompress(&frame, MAX_FRAME).map_err(invalid_data)?;
+        String::from_utf8(line).map(Some).map_err(invalid_data)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<String>, LinesCodecError> {
+        if !self.zstd {
+            return self.lines.decode_eof(src);
+        }
+        self.decode(src)
+    }
+}
+
+impl<T: AsRef<str>> Encoder<T> for ChatCodec {
+    type Error = LinesCodecError;
+
+    fn encode(&mut self, line: T, dst: &mut BytesMut) -> Result<(), LinesCodecError> {
+        let line = match self.color {
+            true => Cow::Borrowed(line.as_ref()),
+            false => strip_ansi(line.as_ref()),
+        };
+        if !self.zstd {
+            return self.lines.encode(line, dst);
+        }
+        let frame = zstd::bulk::compress(line.as_ref().as_bytes(), 0)?;
+        dst.reserve(4 + frame.len());
+        dst.put_u32(frame.len() as u32);
+        dst.extend_from_slice(&frame);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zstd_frames_round_trip_after_negotiation() {
+        let mut codec = ChatCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode("CAP ACK zstd", &mut buf).unwrap();
+        assert_eq!(&buf[..], b"CAP ACK zstd\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap().as_deref(),
+            Some("CAP ACK zstd")
+        );
+
+        codec.enable_zstd();
+        let long = "history ".repeat(512);
+        codec.encode(long.as_str(), &mut buf).unwrap();
+        assert!(buf.len() < long.len());
+
+        // a partial frame waits for more bytes
+        let mut partial = buf.split_to(buf.len() - 1);
+        assert_eq!(codec.decode(&mut partial).unwrap(), None);
+        partial.unsplit(buf);
+        assert_eq!(codec.decode(&mut partial).unwrap(), Some(long));
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+
+use async_trait::async_trait;
+use reqwest::Url;
+
+use super::server::valid_name;
+use super::{Message, PeerAddr, Server};
+use crate::ids::UserId;
+
+/// Who issued a command, and where replies go.
+pub struct CommandContext<'a> {
+    pub server: &'a Server,
+    pub addr: PeerAddr,
+    /// The issuer's current name; `/nick` updates it in place.
+    pub name: &'a mut UserId,
+}
+
+impl CommandContext<'_> {
+    /// Send a server notice to the issuer only.
+    pub async fn reply(&self, text: impl Into<String> + Send) -> anyhow::Result<()> {
+        let msg = Message::system(text.into());
+        self.server.send_to(self.addr, &msg).await?;
+        Ok(())
+    }
+}
+
+/// A slash command such as `/msg`. Implement this and register it with a
+/// [`CommandRegistry`]; `/help` picks it up automatically.
+#[async_trait]
+pub trait Command: Send + Sync + 'static {
+    type Args: Send;
+
+    /// Name without the leading slash.
+    const NAME: &'static str;
+    /// Argument synopsis shown by `/help` and on parse errors.
+    const USAGE: &'static str;
+    const HELP: &'static str;
+
+    /// Parse everything after the command name, `None` if it's malformed.
+    fn parse(&self, args: &str) -> Option<Self::Args>;
+
+    async fn execute(&self, ctx: &mut CommandContext<'_>, args: Self::Args) -> anyhow::Result<()>;
+}
+
+/// Object-safe view of a [`Command`] so differently typed commands can live
+/// in one registry.
+#[async_trait]
+trait DynCommand: Send + Sync {
+    fn usage(&self) -> &'static str;
+    fn help(&self) -> &'static str;
+    async fn run(&self, ctx: &mut CommandContext<'_>, args: &str) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl<C: Command> DynCommand for C {
+    fn usage(&self) -> &'static str {
+        C::USAGE
+    }
+
+    fn help(&self) -> &'static str {
+        C::HELP
+    }
+
+    async fn run(&self, ctx: &mut CommandContext<'_>, args: &str) -> anyhow::Result<()> {
+        match self.parse(args) {
+            Some(args) => self.execute(ctx, args).await,
+            None => ctx.reply(format!("usage: /{} {}", C::NAME, C::USAGE)).await,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Box<dyn DynCommand>>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The commands every chat server understands.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(Help)
+            .register(Msg)
+            .register(Nick)
+            .register(Topic)
+            .register(Webhook);
+        registry
+    }
+
+    pub fn register<C: Command>(&mut self, command: C) -> &mut Self {
+        self.commands.insert(C::NAME, Box::new(command));
+        self
+    }
+
+    pub fn help(&self) -> String {
+        self.commands
+            .iter()
+            .map(|(name, cmd)| {
+                let synopsis = format!("/{} {}", name, cmd.usage());
+                format!("{:<24} {}", synopsis.trim_end(), cmd.help())
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Run a command line (without the leading slash).
+    pub async fn dispatch(&self, ctx: &mut CommandContext<'_>, line: &str) -> anyhow::Result<()> {
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        match self.commands.get(name) {
+            Some(cmd) => cmd.run(ctx, args.trim()).await,
+            None => {
+                ctx.reply(format!("unknown command /{}, try /help", name))
+                    .await
+            }
+        }
+    }
+}
+
+impl Debug for CommandRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.commands.keys()).finish()
+    }
+}
+
+struct Help;
+
+#[async_trait]
+impl Command for Help {
+    type Args = ();
+
+    const NAME: &'static str = "help";
+    const USAGE: &'static str = "";
+    const HELP: &'static str = "list available commands";
+
+    fn parse(&self, _args: &str) -> Option<()> {
+        Some(())
+    }
+
+    async fn execute(&self, ctx: &mut CommandContext<'_>, _args: ()) -> anyhow::Result<()> {
+        for line in ctx.server.commands().help().lines() {
+            ctx.reply(line).await?;
+        }
+        Ok(())
+    }
+}
+
+struct Msg;
+
+#[async_trait]
+impl Command for Msg {
+    type Args = (UserId, String);
+
+    const NAME: &'static str = "msg";
+    const USAGE: &'static str = "<user> <text>";
+    const HELP: &'static str = "send a private message, queued if the user is offline";
+
+    fn parse(&self, args: &str) -> Option<(UserId, String)> {
+        let (to, content) = args.split_once(' ')?;
+        Some((to.into(), content.trim_start().to_string()))
+    }
+
+    async fn execute(
+        &self,
+        ctx: &mut CommandContext<'_>,
+        (to, content): (UserId, String),
+    ) -> anyhow::Result<()> {
+        ctx.server.direct(ctx.addr, ctx.name, &to, &content).await
+    }
+}
+
+struct Nick;
+
+#[async_trait]
+impl Command for Nick {
+    type Args = UserId;
+
+    const NAME: &'static str = "nick";
+    const USAGE: &'static str = "<name>";
+    const HELP: &'static str = "change your name";
+
+    fn parse(&self, args: &str) -> Option<UserId> {
+        valid_name(args).then(|| args.into())
+    }
+
+    async fn execute(&self, ctx: &mut CommandContext<'_>, new: UserId) -> anyhow::Result<()> {
+        if ctx.server.find_by_name(&new).is_some() {
+            return ctx.reply(format!("{} is already taken", new)).await;
+        }
+        if !ctx.server.store().claim(ctx.name, &new).await? {
+            return ctx
+                .reply(format!("{} is registered to someone else", new))
+                .await;
+        }
+        ctx.server.rename(ctx.addr, ctx.name, &new).await?;
+        *ctx.name = new;
+        Ok(())
+    }
+}
+
+struct Topic;
+
+#[async_trait]
+impl Command for Topic {
+    type Args = Option<String>;
+
+    const NAME: &'static str = "topic";
+    const USAGE: &'static str = "[text]";
+    const HELP: &'static str = "show or set the room topic";
+
+    fn parse(&self, args: &str) -> Option<Option<String>> {
+        Some((!args.is_empty()).then(|| args.to_string()))
+    }
+
+    async fn execute(
+        &self,
+        ctx: &mut CommandContext<'_>,
+        topic: Option<String>,
+    ) -> anyhow::Result<()> {
+        match topic {
+            Some(topic) => ctx.server.set_topic(ctx.addr, ctx.name, topic).await,
+            None => match ctx.server.topic() {
+                Some(topic) => ctx.reply(format!("topic: {}", topic)).await,
+                None => ctx.reply("no topic set").await,
+            },
+        }
+    }
+}
+
+struct Webhook;
+
+#[async_trait]
+impl Command for Webhook {
+    type Args = Option<Url>;
+
+    const NAME: &'static str = "webhook";
+    const USAGE: &'static str = "[url]";
+    const HELP: &'static str = "POST your @mentions to an http(s) url, or stop without one";
+
+    fn parse(&self, args: &str) -> Option<Option<Url>> {
+        if args.is_empty() {
+            return Some(None);
+        }
+        let url = Url::parse(args).ok()?;
+        matches!(url.scheme(), "http" | "https").then_some(Some(url))
+    }
+
+    async fn execute(&self, ctx: &mut CommandContext<'_>, url: Option<Url>) -> anyhow::Result<()> {
+        if let Some(Err(e)) = url.as_ref().map(|url| ctx.server.notifier().check(url)) {
+            return ctx.reply(e).await;
+        }
+        ctx.server
+            .store()
+            .set_webhook(ctx.name, url.as_ref().map(Url::as_str))
+            .await?;
+        match url {
+            Some(url) => {
+                ctx.reply(format!("mentions will be posted to {}", url))
+                    .await
+            }
+            None => ctx.reply("webhook removed").await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::server::tests::{server, Client};
+
+    #[test]
+    fn standard_commands_parse_their_arguments() {
+        let (to, text) = Msg.parse("bob  hi there").unwrap();
+        assert_eq!((to.as_ref(), text.as_str()), ("bob", "hi there"));
+        assert!(Msg.parse("bob").is_none());
+
+        assert_eq!(Nick.parse("carol").unwrap(), "carol");
+        for name in ["", "two words", "Server", "SERVER"] {
+            assert!(Nick.parse(name).is_none(), "{:?}", name);
+        }
+
+        assert_eq!(Topic.parse("").unwrap(), None);
+        assert_eq!(Topic.parse("rust").unwrap().as_deref(), Some("rust"));
+
+        assert_eq!(Webhook.parse("").unwrap(), None);
+        let url = Webhook.parse("https://example.com/hook").unwrap().unwrap();
+        assert_eq!(url.as_str(), "https://example.com/hook");
+        for url in ["ftp://example.com/", "file:///etc/passwd", "not a url"] {
+            assert!(Webhook.parse(url).is_none(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn help_lists_every_command_in_order() {
+        let help = CommandRegistry::standard().help();
+        let synopses: Vec<_> = help
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(synopses, ["/help", "/msg", "/nick", "/topic", "/webhook"]);
+        assert!(help.contains("/msg <user> <text>"));
+        assert!(help.contains(Webhook::HELP));
+    }
+
+    #[tokio::test]
+    async fn nick_refuses_names_in_use_or_registered() {
+        let server = server(4).await;
+        let mut alice = Client::log_in(&server, "alice", "a").await;
+        let bob = Client::log_in(&server, "bob", "b").await;
+        alice.expect("Server:bob joined the chat.").await;
+
+        alice.send("/nick bob").await;
+        alice.expect("Server:bob is already taken").await;
+
+        drop(bob);
+        alice.expect("Server:bob left the chat.").await;
+        alice.send("/nick bob").await;
+        alice
+            .expect("Server:bob is registered to someone else")
+            .await;
+
+        alice.send("/nick ally").await;
+        alice.send("/topic").await;
+        alice.expect("Server:no topic set").await;
+        assert!(server.find_by_name("alice").is_none());
+        assert!(server.find_by_name("ally").is_some());
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::{Display, Formatter};
+
+use super::style;
+use crate::ids::UserId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Chat,
+    Direct,
+    Offline,
+    /// A notice from the server itself. Only [`Message::system`] makes one,
+    /// so no user can pass for it by picking the server's name.
+    System,
+}
+
+#[derive(Debug)]
+pub struct Message {
+    username: UserId,
+    content: String,
+    kind: MessageKind,
+}
+
+impl Message {
+    pub fn new(username: UserId, content: String) -> Self {
+        Self {
+            username,
+            content,
+            kind: MessageKind::Chat,
+        }
+    }
+
+    pub fn direct(username: UserId, content: String) -> Self {
+        Self {
+            username,
+            content,
+            kind: MessageKind::Direct,
+        }
+    }
+
+    pub fn offline(username: UserId, content: String) -> Self {
+        Self {
+            username,
+            content,
+            kind: MessageKind::Offline,
+        }
+    }
+
+    pub fn system(content: String) -> Self {
+        Self {
+            username: UserId::from("Server"),
+            content,
+            kind: MessageKind::System,
+        }
+    }
+
+    pub fn username(&self) -> &UserId {
+        &self.username
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Like `Display`, with ANSI colors added. The codec strips them again
+    /// for clients that didn't negotiate `CAP color`.
+    pub fn styled(&self) -> String {
+        // only the server gets to style things; escapes in the text, which
+        // notices quote from users too, are dropped
+        let content = style::strip_ansi(&self.content);
+        let nick = style::nick(&self.username);
+        match self.kind {
+            MessageKind::System => style::system(&format!("{}:{}", self.username, content)),
+            MessageKind::Chat => format!("{}:{}", nick, content),
+            MessageKind::Direct => format!("{} {}:{}", style::direct("[dm]"), nick, content),
+            MessageKind::Offline => {
+                format!("{} {}:{}", style::system("[offline]"), nick, content)
+            }
+        }
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            MessageKind::Chat | MessageKind::System => {
+                write!(f, "{}:{}", self.username, self.content)
+            }
+            MessageKind::Direct => write!(f, "[dm] {}:{}", self.username, self.content),
+            MessageKind::Offline => write!(f, "[offline] {}:{}", self.username, self.content),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_system_messages_get_system_styling() {
+        let spoofed = Message::new(UserId::from("Server"), "hi".to_string()).styled();
+        assert_eq!(spoofed, format!("{}:hi", style::nick("Server")));
+        let notice = Message::system("hi".to_string()).styled();
+        assert_eq!(notice, style::system("Server:hi"));
+    }
+
+    #[test]
+    fn escapes_are_stripped_from_every_kind() {
+        let text = "\x1b[5mred\x1b[0m".to_string();
+        let messages = [
+            Message::new(UserId::from("alice"), text.clone()),
+            Message::direct(UserId::from("alice"), text.clone()),
+            Message::offline(UserId::from("alice"), text.clone()),
+            Message::system(text),
+        ];
+        for msg in messages {
+            let styled = msg.styled();
+            assert!(styled.contains(":red"), "{:?}", styled);
+            assert!(!styled.contains("\x1b[5m"), "{:?}", styled);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+//! The line-based chat server behind the `chat*` examples. Transports (TCP,
+//! unix sockets, QUIC, ...) live in the examples and hand accepted streams to
+//! [`admit`], and own the tasks it returns.
+
+mod audit;
+pub mod bus;
+mod codec;
+mod command;
+mod message;
+mod server;
+mod store;
+mod style;
+mod webhook;
+
+pub use audit::{AuditEvent, AuditLog};
+pub use codec::ChatCodec;
+pub use command::{Command, CommandContext, CommandRegistry};
+pub use message::{Message, MessageKind};
+pub use server::{admit, reap, ChatStream, ClientFramed, Peer, PeerAddr, Server, LOBBY};
+pub use store::{Login, PendingMessage, UserStore};
+pub use webhook::{MentionNotification, WebhookNotifier};
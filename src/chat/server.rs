@@ -0,0 +1,724 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::anyhow;
+use dashmap::DashMap;
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use opentelemetry::global;
+use opentelemetry::metrics::Gauge;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinError;
+use tokio_util::codec::Framed;
+use tracing::{error, info, info_span, instrument, warn, Instrument};
+
+use super::webhook::{mentions, MentionNotification};
+use super::{
+    AuditEvent, AuditLog, ChatCodec, CommandContext, CommandRegistry, Login, Message, UserStore,
+    WebhookNotifier,
+};
+use crate::ids::{RoomName, UserId};
+
+/// There is a single shared room for now; spans still record it so traces
+/// keep the same shape once more rooms exist.
+pub const LOBBY: &str = "lobby";
+
+/// Failed logins before a client is disconnected.
+const LOGIN_ATTEMPTS: usize = 3;
+
+/// Whether anyone may go by `name`: one word, and not the server's.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(char::is_whitespace) && !name.eq_ignore_ascii_case("Server")
+}
+
+/// Any byte stream a client can talk to us over (TCP, unix socket, QUIC, ...).
+pub trait ChatStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ChatStream for T {}
+
+pub type ClientFramed = Framed<Box<dyn ChatStream>, ChatCodec>;
+
+/// Identifies a connected client independently of the transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    Unix(u64),
+    Quic(SocketAddr),
+}
+
+impl PeerAddr {
+    pub fn next_unix() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self::Unix(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Display for PeerAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{}", addr),
+            PeerAddr::Unix(id) => write!(f, "unix#{}", id),
+            PeerAddr::Quic(addr) => write!(f, "quic://{}", addr),
+        }
+    }
+}
+
+pub struct Peer {
+    name: UserId,
+    stream: SplitSink<ClientFramed, String>,
+}
+
+impl Peer {
+    pub fn new(name: UserId, stream: SplitSink<ClientFramed, String>) -> Self {
+        Self { name, stream }
+    }
+}
+
+impl Debug for Peer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Peer")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Connection slot use, on the global meter provider.
+struct SlotMetrics {
+    connected: Gauge<u64>,
+    utilization: Gauge<f64>,
+}
+
+impl SlotMetrics {
+    fn new() -> Self {
+        let meter = global::meter("chat");
+        Self {
+            connected: meter
+                .u64_gauge("chat.clients.connected")
+                .with_description("Clients holding a connection slot")
+                .init(),
+            utilization: meter
+                .f64_gauge("chat.clients.utilization")
+                .with_description("Share of connection slots in use, from 0 to 1")
+                .init(),
+        }
+    }
+}
+
+impl Debug for SlotMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlotMetrics").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub struct Server {
+    peers: DashMap<PeerAddr, Peer>,
+    slots: Arc<Semaphore>,
+    max_clients: usize,
+    slot_metrics: SlotMetrics,
+    store: UserStore,
+    notifier: WebhookNotifier,
+    audit: AuditLog,
+    commands: CommandRegistry,
+    room: RoomName,
+    topic: RwLock<Option<String>>,
+}
+
+impl Server {
+    pub fn new(
+        max_clients: NonZeroUsize,
+        store: UserStore,
+        notifier: WebhookNotifier,
+        audit: AuditLog,
+    ) -> Self {
+        let max_clients = max_clients.get();
+        Self {
+            peers: DashMap::new(),
+            slots: Arc::new(Semaphore::new(max_clients)),
+            max_clients,
+            slot_metrics: SlotMetrics::new(),
+            store,
+            notifier,
+            audit,
+            commands: CommandRegistry::standard(),
+            room: RoomName::from(LOBBY),
+            topic: RwLock::new(None),
+        }
+    }
+
+    pub fn store(&self) -> &UserStore {
+        &self.store
+    }
+
+    pub fn notifier(&self) -> &WebhookNotifier {
+        &self.notifier
+    }
+
+    pub fn commands(&self) -> &CommandRegistry {
+        &self.commands
+    }
+
+    /// Register extra slash commands before the server starts accepting.
+    pub fn commands_mut(&mut self) -> &mut CommandRegistry {
+        &mut self.commands
+    }
+
+    pub fn room(&self) -> &RoomName {
+        &self.room
+    }
+
+    pub fn topic(&self) -> Option<String> {
+        self.topic.read().unwrap().clone()
+    }
+
+    /// Reserve a connection slot, or `None` if the server is full.
+    pub fn try_admit(&self) -> Option<OwnedSemaphorePermit> {
+        self.slots.clone().try_acquire_owned().ok()
+    }
+
+    /// Number of clients currently holding a connection slot.
+    pub fn connected(&self) -> usize {
+        self.max_clients - self.slots.available_permits()
+    }
+
+    /// Record slot use in the `chat.clients.*` gauges, and log it.
+    pub fn report_utilization(&self) {
+        let connected = self.connected();
+        let utilization = connected as f64 / self.max_clients as f64;
+        self.slot_metrics.connected.record(connected as u64, &[]);
+        self.slot_metrics.utilization.record(utilization, &[]);
+        info!(
+            connected,
+            max_clients = self.max_clients,
+            utilization,
+            "connection slots"
+        );
+    }
+
+    pub async fn join(&self, addr: PeerAddr, peer: Peer) -> anyhow::Result<()> {
+        let name = peer.name.clone();
+        self.peers.insert(addr, peer);
+        self.audit.record(&name, addr, AuditEvent::Join).await;
+        let msg = format!("{} joined the chat.", name);
+        info!(msg);
+        let msg = Message::system(msg);
+        self.broadcast(addr, Arc::new(msg)).await?;
+        if let Some(topic) = self.topic() {
+            let msg = Message::system(format!("topic: {}", topic));
+            self.send_to(addr, &msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Give a connected user a new name, announcing it to everyone. The name
+    /// must already be theirs, see [`UserStore::claim`].
+    pub async fn rename(&self, addr: PeerAddr, from: &UserId, to: &UserId) -> anyhow::Result<()> {
+        match self.peers.get_mut(&addr) {
+            Some(mut peer) => peer.name = to.clone(),
+            None => return Err(anyhow!("peer({}) is not connected", addr)),
+        }
+        let event = AuditEvent::Nick {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        self.audit.record(from, addr, event).await;
+        let msg = format!("{} is now known as {}.", from, to);
+        info!(msg);
+        let msg = Message::system(msg);
+        self.broadcast(addr, Arc::new(msg)).await
+    }
+
+    pub async fn set_topic(&self, addr: PeerAddr, by: &str, topic: String) -> anyhow::Result<()> {
+        *self.topic.write().unwrap() = Some(topic.clone());
+        let msg = format!("{} set the topic to: {}", by, topic);
+        self.audit
+            .record(by, addr, AuditEvent::Topic { topic })
+            .await;
+        info!(msg);
+        let msg = Arc::new(Message::system(msg));
+        self.broadcast(addr, msg.clone()).await?;
+        // the setter sees it too, as confirmation
+        self.send_to(addr, &msg).await?;
+        Ok(())
+    }
+
+    #[instrument(
+        skip_all,
+        fields(
+            room = %self.room,
+            sender = %msg.username(),
+            size = msg.content().len(),
+            recipients
+        )
+    )]
+    pub async fn broadcast(&self, src_addr: PeerAddr, msg: Arc<Message>) -> anyhow::Result<()> {
+        let line = msg.styled();
+        let mut recipients = 0;
+        let mut failed = Vec::new();
+        for mut peer in self.peers.iter_mut() {
+            if peer.key().eq(&src_addr) {
+                continue;
+            }
+            let addr = *peer.key();
+            let span = info_span!("deliver", peer = %addr);
+            match peer.stream.send(line.clone()).instrument(span).await {
+                Ok(()) => recipients += 1,
+                Err(e) => {
+                    warn!("failed sending message to {}: {}", addr, e);
+                    failed.push(addr);
+                }
+            }
+        }
+        // removing while iterating would deadlock on the shard lock
+        for addr in failed {
+            self.peers.remove(&addr);
+        }
+        tracing::Span::current().record("recipients", recipients);
+
+        Ok(())
+    }
+
+    /// Send a message to a single peer, returning whether it was delivered.
+    pub async fn send_to(&self, addr: PeerAddr, msg: &Message) -> anyhow::Result<bool> {
+        let Some(mut peer) = self.peers.get_mut(&addr) else {
+            return Ok(false);
+        };
+        let span = info_span!("deliver", peer = %addr);
+        peer.stream.send(msg.styled()).instrument(span).await?;
+        Ok(true)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<PeerAddr> {
+        self.peers
+            .iter()
+            .find(|peer| peer.name == name)
+            .map(|peer| *peer.key())
+    }
+
+    /// Deliver a direct message, queueing it if the recipient is offline.
+    pub async fn direct(
+        &self,
+        src_addr: PeerAddr,
+        from: &UserId,
+        to: &UserId,
+        content: &str,
+    ) -> anyhow::Result<()> {
+        if let Some(addr) = self.find_by_name(to) {
+            let msg = Message::direct(from.clone(), content.to_string());
+            if self.send_to(addr, &msg).await? {
+                return Ok(());
+            }
+        }
+
+        let notice = if self.store.exists(to).await? {
+            self.store.queue(from, to, content).await?;
+            info!("queued direct message from {} to offline user {}", from, to);
+            format!("{} is offline, message queued.", to)
+        } else {
+            format!("no such user: {}", to)
+        };
+        let msg = Message::system(notice);
+        self.send_to(src_addr, &msg).await?;
+        Ok(())
+    }
+
+    /// Hand a freshly joined user everything sent to them while offline.
+    pub async fn deliver_pending(&self, addr: PeerAddr, name: &str) -> anyhow::Result<()> {
+        for pending in self.store.take_pending(name).await? {
+            let msg = Message::offline(pending.sender, pending.content);
+            self.send_to(addr, &msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Queue a webhook call for every mentioned user who registered one.
+    pub async fn notify_mentions(&self, from: &UserId, content: &str) -> anyhow::Result<()> {
+        for name in mentions(content) {
+            if *from == name {
+                continue;
+            }
+            if let Some(url) = self.store.webhook(name).await? {
+                self.notifier.notify(MentionNotification::new(
+                    url,
+                    name.to_string(),
+                    from.to_string(),
+                    content.to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn leave(&self, addr: PeerAddr) -> anyhow::Result<()> {
+        let Some((_, peer)) = self.peers.remove(&addr) else {
+            return Err(anyhow!("fail to remove peer({}) from global state.", addr));
+        };
+        self.audit.record(&peer.name, addr, AuditEvent::Leave).await;
+        let msg = format!("{} left the chat.", peer.name);
+
+        info!(msg);
+        let msg = Message::system(msg);
+        self.broadcast(addr, Arc::new(msg)).await
+    }
+}
+
+async fn reject_client(mut stream: ClientFramed) -> anyhow::Result<()> {
+    stream.send("server full, try later").await?;
+    SinkExt::<&str>::close(&mut stream).await?;
+    Ok(())
+}
+
+/// Answer a `CAP <name>` request sent before login. Clients that never ask
+/// keep talking plain newline-delimited text.
+async fn negotiate(stream: &mut ClientFramed, addr: PeerAddr, cap: &str) -> anyhow::Result<()> {
+    match cap {
+        "zstd" if !stream.codec().is_zstd() => {
+            stream.send("CAP ACK zstd").await?;
+            stream.codec_mut().enable_zstd();
+            info!("{} enabled zstd compression", addr);
+        }
+        "color" if !stream.codec().is_color() => {
+            stream.send("CAP ACK color").await?;
+            stream.codec_mut().enable_color();
+        }
+        _ => stream.send(format!("CAP NAK {}", cap)).await?,
+    }
+    stream.send("Please enter your name:").await?;
+    Ok(())
+}
+
+/// What a line received from a client asks us to do.
+#[derive(Debug)]
+enum Line<'a> {
+    Command(&'a str),
+    Chat,
+    Empty,
+}
+
+fn classify(msg: &str) -> Line<'_> {
+    if let Some(command) = msg.strip_prefix('/') {
+        Line::Command(command)
+    } else if msg.is_empty() {
+        Line::Empty
+    } else {
+        Line::Chat
+    }
+}
+
+async fn handle_line(
+    server: &Server,
+    addr: PeerAddr,
+    name: &mut UserId,
+    msg: String,
+) -> anyhow::Result<()> {
+    let line = info_span!("filter").in_scope(|| classify(&msg));
+    match line {
+        Line::Command(command) => {
+            let mut ctx = CommandContext { server, addr, name };
+            server.commands.dispatch(&mut ctx, command).await?;
+        }
+        Line::Empty => warn!("empty line"),
+        Line::Chat => {
+            server.notify_mentions(name, &msg).await?;
+            let msg = Message::new(name.clone(), msg);
+            server.broadcast(addr, Arc::new(msg)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The next line from a client that hasn't joined yet.
+async fn next_line(stream: &mut ClientFramed) -> anyhow::Result<String> {
+    match stream.next().await {
+        Some(line) => Ok(line?),
+        None => Err(anyhow!("client left before logging in")),
+    }
+}
+
+/// Check the name a client asked for and authenticate them as it, or tell
+/// them why not and return `None`.
+async fn login(
+    stream: &mut ClientFramed,
+    server: &Server,
+    name: String,
+) -> anyhow::Result<Option<UserId>> {
+    if !valid_name(&name) {
+        stream
+            .send("a name is one word, and can't be Server")
+            .await?;
+        return Ok(None);
+    }
+    if server.find_by_name(&name).is_some() {
+        stream
+            .send(format!("{} is already connected", name))
+            .await?;
+        return Ok(None);
+    }
+    stream.send("Password:").await?;
+    let password = next_line(stream).await?;
+    if password.is_empty() {
+        stream.send("the password can't be empty").await?;
+        return Ok(None);
+    }
+    match server.store.login(&name, &password).await? {
+        Login::Created => {
+            stream
+                .send(format!(
+                    "registered {}, log in with the same password next time",
+                    name
+                ))
+                .await?
+        }
+        Login::Verified => {}
+        Login::WrongPassword => {
+            warn!("wrong password for {}", name);
+            stream.send("wrong password").await?;
+            return Ok(None);
+        }
+    }
+    Ok(Some(UserId::from(name)))
+}
+
+async fn handle_client(
+    mut stream: ClientFramed,
+    addr: PeerAddr,
+    server: Arc<Server>,
+) -> anyhow::Result<()> {
+    stream.send("Please enter your name:").await?;
+    let mut failed = 0;
+    let mut name = loop {
+        let line = next_line(&mut stream).await?;
+        if let Some(cap) = line.strip_prefix("CAP ") {
+            negotiate(&mut stream, addr, cap.trim()).await?;
+            continue;
+        }
+        if let Some(name) = login(&mut stream, &server, line).await? {
+            break name;
+        }
+        failed += 1;
+        if failed == LOGIN_ATTEMPTS {
+            SinkExt::<&str>::close(&mut stream).await?;
+            return Err(anyhow!("too many failed logins"));
+        }
+        stream.send("Please enter your name:").await?;
+    };
+
+    let (writer, mut reader) = stream.split();
+    let peer = Peer::new(name.clone(), writer);
+
+    // whatever ends the session, the peer has to go again
+    let session = async {
+        server.join(addr, peer).await?;
+        server.deliver_pending(addr, &name).await?;
+
+        while let Some(line) = reader.next().await {
+            match line {
+                Ok(msg) => {
+                    let span = info_span!(
+                        "receive",
+                        room = %server.room,
+                        sender = %name,
+                        size = msg.len()
+                    );
+                    handle_line(&server, addr, &mut name, msg)
+                        .instrument(span)
+                        .await?;
+                }
+                Err(e) => {
+                    warn!("error read line from {}: {}", addr, e);
+                    break;
+                }
+            }
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    let left = server.leave(addr).await;
+    session.and(left)
+}
+
+/// Admit a freshly accepted client if there's a free slot, otherwise tell it
+/// to come back later. Returns the client's task without spawning it, so
+/// the accept loop can own it (in a `JoinSet`) and see how it ended.
+pub fn admit<S>(stream: S, addr: PeerAddr, server: &Arc<Server>) -> impl Future<Output = ()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let framed: ClientFramed = Framed::new(Box::new(stream), ChatCodec::new());
+    let permit = server.try_admit();
+    match permit {
+        Some(_) => {
+            info!("Accepted connection from {}", addr);
+            server.report_utilization();
+        }
+        None => warn!("server full, rejecting connection from {}", addr),
+    }
+    let server = server.clone();
+    async move {
+        let Some(permit) = permit else {
+            if let Err(e) = reject_client(framed).await {
+                warn!("error rejecting client {}: {}", addr, e);
+            }
+            return;
+        };
+        if let Err(e) = handle_client(framed, addr, server.clone()).await {
+            error!("error handle client {}: {}", addr, e);
+        }
+        drop(permit);
+        server.report_utilization();
+    }
+    .in_current_span()
+}
+
+/// Log a client task that panicked. One that was aborted, on shutdown, ended
+/// the way it was meant to.
+pub fn reap(result: Result<(), JoinError>) {
+    if let Err(e) = result {
+        if e.is_panic() {
+            let payload = e.into_panic();
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("(no message)");
+            error!("client task panicked: {}", message);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::time::Duration;
+
+    use tokio::io::DuplexStream;
+    use tokio_util::codec::LinesCodec;
+
+    use super::*;
+
+    pub(in crate::chat) async fn server(max_clients: usize) -> Arc<Server> {
+        let audit = std::env::temp_dir().join(format!("chat-audit-{}.jsonl", nanoid::nanoid!()));
+        Arc::new(Server::new(
+            NonZeroUsize::new(max_clients).unwrap(),
+            UserStore::memory().await,
+            WebhookNotifier::spawn(1, false),
+            AuditLog::open(audit, 1 << 20, 0).await.unwrap(),
+        ))
+    }
+
+    /// The far end of a connection, as a plain-text client sees it.
+    pub(in crate::chat) struct Client(Framed<DuplexStream, LinesCodec>);
+
+    impl Client {
+        pub(in crate::chat) fn connect(server: &Arc<Server>) -> Self {
+            let (client, conn) = tokio::io::duplex(4096);
+            tokio::spawn(admit(conn, PeerAddr::next_unix(), server));
+            Self(Framed::new(client, LinesCodec::new()))
+        }
+
+        /// Connect, log in, and wait until the server has us joined.
+        pub(in crate::chat) async fn log_in(
+            server: &Arc<Server>,
+            name: &str,
+            password: &str,
+        ) -> Self {
+            let mut client = Self::connect(server);
+            client.expect("Please enter your name:").await;
+            client.send(name).await;
+            client.expect("Password:").await;
+            client.send(password).await;
+            // replying to a command means we've joined
+            client.send("/topic").await;
+            let mut reply = client.recv().await;
+            if reply.starts_with("registered ") {
+                reply = client.recv().await;
+            }
+            assert_eq!(reply, "Server:no topic set");
+            client
+        }
+
+        pub(in crate::chat) async fn send(&mut self, line: &str) {
+            self.0.send(line).await.unwrap();
+        }
+
+        /// The next line, `None` once the server hung up.
+        pub(in crate::chat) async fn try_recv(&mut self) -> Option<String> {
+            tokio::time::timeout(Duration::from_secs(10), self.0.next())
+                .await
+                .expect("no reply from the server")
+                .map(Result::unwrap)
+        }
+
+        pub(in crate::chat) async fn recv(&mut self) -> String {
+            self.try_recv().await.expect("server hung up")
+        }
+
+        pub(in crate::chat) async fn expect(&mut self, line: &str) {
+            assert_eq!(self.recv().await, line);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_full_server_turns_clients_away() {
+        let server = server(1).await;
+        let _alice = Client::log_in(&server, "alice", "a").await;
+
+        let mut bob = Client::connect(&server);
+        bob.expect("server full, try later").await;
+        assert_eq!(bob.try_recv().await, None);
+        assert_eq!(server.connected(), 1);
+    }
+
+    #[tokio::test]
+    async fn names_are_checked_before_the_password_is_asked_for() {
+        let server = server(4).await;
+        let _alice = Client::log_in(&server, "alice", "a").await;
+
+        let mut client = Client::connect(&server);
+        client.expect("Please enter your name:").await;
+        for (name, reply) in [
+            ("two words", "a name is one word, and can't be Server"),
+            ("server", "a name is one word, and can't be Server"),
+        ] {
+            client.send(name).await;
+            client.expect(reply).await;
+            client.expect("Please enter your name:").await;
+        }
+        // the third failure is the last
+        client.send("alice").await;
+        client.expect("alice is already connected").await;
+        assert_eq!(client.try_recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn pending_messages_wait_for_the_right_password() {
+        let server = server(4).await;
+        let mut alice = Client::log_in(&server, "alice", "a").await;
+        let bob = Client::log_in(&server, "bob", "b").await;
+        alice.expect("Server:bob joined the chat.").await;
+        drop(bob);
+        alice.expect("Server:bob left the chat.").await;
+
+        alice.send("/msg bob psst").await;
+        alice.expect("Server:bob is offline, message queued.").await;
+
+        let mut impostor = Client::connect(&server);
+        impostor.expect("Please enter your name:").await;
+        impostor.send("bob").await;
+        impostor.expect("Password:").await;
+        impostor.send("a").await;
+        impostor.expect("wrong password").await;
+        drop(impostor);
+
+        let mut bob = Client::connect(&server);
+        bob.expect("Please enter your name:").await;
+        bob.send("bob").await;
+        bob.expect("Password:").await;
+        bob.send("b").await;
+        bob.expect("[offline] alice:psst").await;
+    }
+}
@@ -0,0 +1,281 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+use crate::ids::UserId;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct PendingMessage {
+    pub sender: UserId,
+    pub content: String,
+}
+
+/// How a [`UserStore::login`] went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Login {
+    /// First login; the password now belongs to the account.
+    Created,
+    Verified,
+    WrongPassword,
+}
+
+/// Persistent user accounts plus the direct messages waiting for users who
+/// were offline when they were sent.
+#[derive(Debug, Clone)]
+pub struct UserStore {
+    db: SqlitePool,
+}
+
+impl UserStore {
+    pub async fn try_new(url: &str) -> anyhow::Result<Self> {
+        let db = SqlitePool::connect(url).await?;
+        let sql = r#"CREATE TABLE IF NOT EXISTS users (
+            name TEXT PRIMARY KEY,
+            created_at TEXT NOT NULL,
+            password_hash TEXT
+        )"#;
+        sqlx::query(sql).execute(&db).await?;
+        // databases from before logins had passwords
+        let (has_password,): (bool,) = sqlx::query_as(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('users') WHERE name = 'password_hash'",
+        )
+        .fetch_one(&db)
+        .await?;
+        if !has_password {
+            sqlx::query("ALTER TABLE users ADD COLUMN password_hash TEXT")
+                .execute(&db)
+                .await?;
+        }
+        let sql = r#"CREATE TABLE IF NOT EXISTS pending_messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            recipient TEXT NOT NULL REFERENCES users(name),
+            sender TEXT NOT NULL,
+            content TEXT NOT NULL,
+            sent_at TEXT NOT NULL
+        )"#;
+        sqlx::query(sql).execute(&db).await?;
+        let sql = r#"CREATE TABLE IF NOT EXISTS webhooks (
+            name TEXT PRIMARY KEY REFERENCES users(name),
+            url TEXT NOT NULL
+        )"#;
+        sqlx::query(sql).execute(&db).await?;
+        Ok(Self { db })
+    }
+
+    pub async fn set_webhook(&self, name: &str, url: Option<&str>) -> anyhow::Result<()> {
+        match url {
+            Some(url) => {
+                sqlx::query(
+                    "INSERT INTO webhooks(name, url) VALUES($1, $2) \
+                    ON CONFLICT(name) DO UPDATE SET url=EXCLUDED.url",
+                )
+                .bind(name)
+                .bind(url)
+                .execute(&self.db)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM webhooks WHERE name = $1")
+                    .bind(name)
+                    .execute(&self.db)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn webhook(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT url FROM webhooks WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(row.map(|(url,)| url))
+    }
+
+    /// Check `password` against the account, creating it on first login.
+    /// Accounts made before passwords existed are claimed by whoever logs in
+    /// as them first.
+    pub async fn login(&self, name: &str, password: &str) -> anyhow::Result<Login> {
+        if let Some(hash) = self.password_hash(name).await? {
+            let password = password.to_string();
+            let verified = tokio::task::spawn_blocking(move || {
+                let hash = PasswordHash::new(&hash)?;
+                anyhow::Ok(
+                    Argon2::default()
+                        .verify_password(password.as_bytes(), &hash)
+                        .is_ok(),
+                )
+            })
+            .await??;
+            return Ok(if verified {
+                Login::Verified
+            } else {
+                Login::WrongPassword
+            });
+        }
+
+        let password = password.to_string();
+        let hash = tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| anyhow::anyhow!("failed to hash password: {}", e))
+        })
+        .await??;
+        // someone else may have created it since we looked
+        Ok(if self.create(name, &hash).await? {
+            Login::Created
+        } else {
+            Login::WrongPassword
+        })
+    }
+
+    /// Let `owner` go by `name` too, creating the account with `owner`'s
+    /// password if needed. `false` if `name` belongs to someone else.
+    pub async fn claim(&self, owner: &str, name: &str) -> anyhow::Result<bool> {
+        let Some(owner_hash) = self.password_hash(owner).await? else {
+            return Ok(false);
+        };
+        if let Some(hash) = self.password_hash(name).await? {
+            return Ok(owner_hash == hash);
+        }
+        self.create(name, &owner_hash).await
+    }
+
+    /// Create the account, or give a password to one that has none. `false`
+    /// if it already has one.
+    async fn create(&self, name: &str, password_hash: &str) -> anyhow::Result<bool> {
+        let created = sqlx::query(
+            "INSERT INTO users(name, created_at, password_hash) VALUES($1, $2, $3) \
+            ON CONFLICT(name) DO UPDATE SET password_hash = EXCLUDED.password_hash \
+            WHERE users.password_hash IS NULL",
+        )
+        .bind(name)
+        .bind(Utc::now())
+        .bind(password_hash)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        Ok(created == 1)
+    }
+
+    async fn password_hash(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let row: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT password_hash FROM users WHERE name = $1")
+                .bind(name)
+                .fetch_optional(&self.db)
+                .await?;
+        Ok(row.and_then(|(hash,)| hash))
+    }
+
+    pub async fn exists(&self, name: &str) -> anyhow::Result<bool> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT name FROM users WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.db)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    pub async fn queue(&self, sender: &str, recipient: &str, content: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO pending_messages(recipient, sender, content, sent_at) VALUES($1, $2, $3, $4)",
+        )
+        .bind(recipient)
+        .bind(sender)
+        .bind(content)
+        .bind(Utc::now())
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Remove and return everything queued for `recipient`, oldest first.
+    pub async fn take_pending(&self, recipient: &str) -> anyhow::Result<Vec<PendingMessage>> {
+        let mut tx = self.db.begin().await?;
+        let pending = sqlx::query_as(
+            "SELECT sender, content FROM pending_messages WHERE recipient = $1 ORDER BY id",
+        )
+        .bind(recipient)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM pending_messages WHERE recipient = $1")
+            .bind(recipient)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(pending)
+    }
+}
+
+#[cfg(test)]
+impl UserStore {
+    /// A fresh in-memory database, shared by the pool's connections (each
+    /// plain `sqlite::memory:` connection would get its own).
+    pub(crate) async fn memory() -> Self {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let url = format!("sqlite:file:chat-{}?mode=memory&cache=shared", id);
+        Self::try_new(&url).await.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pending_messages_are_taken_once_in_order() {
+        let store = UserStore::memory().await;
+        store.login("bob", "pw").await.unwrap();
+        store.login("dave", "pw").await.unwrap();
+        store.queue("alice", "bob", "first").await.unwrap();
+        store.queue("carol", "bob", "second").await.unwrap();
+        store.queue("alice", "dave", "elsewhere").await.unwrap();
+
+        let pending = store.take_pending("bob").await.unwrap();
+        let pending: Vec<_> = pending
+            .iter()
+            .map(|m| (m.sender.to_string(), m.content.as_str()))
+            .collect();
+        assert_eq!(
+            pending,
+            [
+                ("alice".to_string(), "first"),
+                ("carol".to_string(), "second")
+            ]
+        );
+        assert!(store.take_pending("bob").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_first_login_sets_the_password() {
+        let store = UserStore::memory().await;
+        assert!(!store.exists("bob").await.unwrap());
+        assert_eq!(store.login("bob", "pw").await.unwrap(), Login::Created);
+        assert!(store.exists("bob").await.unwrap());
+        assert_eq!(store.login("bob", "pw").await.unwrap(), Login::Verified);
+        assert_eq!(
+            store.login("bob", "guess").await.unwrap(),
+            Login::WrongPassword
+        );
+    }
+
+    #[tokio::test]
+    async fn names_are_claimed_only_by_their_owner() {
+        let store = UserStore::memory().await;
+        store.login("alice", "a").await.unwrap();
+        store.login("bob", "b").await.unwrap();
+
+        assert!(store.claim("alice", "ally").await.unwrap());
+        // the new name shares alice's password, and she can move back to it
+        assert_eq!(store.login("ally", "a").await.unwrap(), Login::Verified);
+        assert!(store.claim("ally", "ally").await.unwrap());
+        assert!(store.claim("alice", "ally").await.unwrap());
+        assert!(!store.claim("alice", "bob").await.unwrap());
+        assert!(!store.claim("nobody", "carol").await.unwrap());
+    }
+}
@@ -0,0 +1,77 @@
+use std::borrow::Cow;
+
+const RESET: &str = "\x1b[0m";
+const SYSTEM: &str = "\x1b[2;3m";
+const DIRECT: &str = "\x1b[1;35m";
+const NICKS: [&str; 6] = [
+    "\x1b[31m", "\x1b[32m", "\x1b[33m", "\x1b[34m", "\x1b[36m", "\x1b[91m",
+];
+
+/// A stable color per nickname, so the same user looks the same everywhere.
+pub(super) fn nick(name: &str) -> String {
+    let hash = name
+        .bytes()
+        .fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+    format!("{}{}{}", NICKS[hash % NICKS.len()], name, RESET)
+}
+
+/// Dimmed italics for server notices.
+pub(super) fn system(text: &str) -> String {
+    format!("{}{}{}", SYSTEM, text, RESET)
+}
+
+pub(super) fn direct(tag: &str) -> String {
+    format!("{}{}{}", DIRECT, tag, RESET)
+}
+
+/// Remove ANSI escape sequences, for clients that didn't ask for color (and
+/// so a user can't paint a plain terminal with escapes of their own).
+pub(super) fn strip_ansi(s: &str) -> Cow<'_, str> {
+    if !s.contains('\x1b') {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates up to a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: up to BEL or ST
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ansi_leaves_plain_text() {
+        assert_eq!(strip_ansi(&nick("alice")), "alice");
+        assert_eq!(strip_ansi(&system("bob joined.")), "bob joined.");
+        assert_eq!(
+            strip_ansi("a\x1b]0;title\x07b\x1b[38;5;208mc\x1b[0m"),
+            "abc"
+        );
+        assert!(matches!(strip_ansi("plain"), Cow::Borrowed("plain")));
+    }
+}
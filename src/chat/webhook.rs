@@ -0,0 +1,293 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect;
+use reqwest::Url;
+use serde::Serialize;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, error, info, warn};
+
+use crate::observed::{self, ObservedSender};
+
+/// Body POSTed to a user's webhook when somebody mentions them.
+#[derive(Debug, Serialize)]
+pub struct MentionNotification {
+    #[serde(skip)]
+    url: String,
+    mentioned: String,
+    from: String,
+    content: String,
+    sent_at: DateTime<Utc>,
+}
+
+impl MentionNotification {
+    pub fn new(url: String, mentioned: String, from: String, content: String) -> Self {
+        Self {
+            url,
+            mentioned,
+            from,
+            content,
+            sent_at: Utc::now(),
+        }
+    }
+}
+
+/// Delivers mention notifications from a bounded background queue so slow
+/// webhook endpoints never hold up the chat itself.
+///
+/// Webhook URLs come from users, so unless told otherwise the notifier only
+/// posts to public addresses: loopback, private and link-local ones are
+/// refused both as literal hosts and as what a name resolves to, and
+/// redirects aren't followed.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    tx: ObservedSender<MentionNotification>,
+    allow_private: bool,
+}
+
+impl WebhookNotifier {
+    const MAX_ATTEMPTS: u32 = 3;
+    const CONCURRENCY: usize = 8;
+
+    /// `allow_private` lets webhooks reach the chat server's own network,
+    /// for trying them out locally.
+    pub fn spawn(capacity: usize, allow_private: bool) -> Self {
+        let (tx, rx) = observed::channel("webhooks", capacity);
+        let mut client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .redirect(redirect::Policy::none());
+        if !allow_private {
+            client = client.dns_resolver(Arc::new(PublicOnly));
+        }
+        let client = client.build().expect("failed to build webhook http client");
+        tokio::spawn(ReceiverStream::new(rx).for_each_concurrent(
+            Self::CONCURRENCY,
+            move |notification| {
+                let client = client.clone();
+                async move { Self::deliver(&client, notification).await }
+            },
+        ));
+        Self { tx, allow_private }
+    }
+
+    /// Why `url` can't be used as a webhook, if it can't. Names are checked
+    /// again each time they are resolved.
+    pub fn check(&self, url: &Url) -> Result<(), &'static str> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err("only http and https webhooks are supported");
+        }
+        let Some(host) = url.host_str() else {
+            return Err("the webhook needs a host");
+        };
+        // IPv6 literals keep their brackets
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let Ok(ip) = host.parse::<IpAddr>() else {
+            return Ok(());
+        };
+        if self.allow_private || is_public(ip) {
+            Ok(())
+        } else {
+            Err("webhooks can't point at private addresses")
+        }
+    }
+
+    pub fn notify(&self, notification: MentionNotification) {
+        // set before these checks existed, or with them turned off
+        let checked = Url::parse(&notification.url)
+            .map_err(|_| "not a url")
+            .and_then(|url| self.check(&url));
+        if let Err(e) = checked {
+            warn!(
+                "not notifying {} at {}: {}",
+                notification.mentioned, notification.url, e
+            );
+            return;
+        }
+        match self.tx.try_send(notification) {
+            Ok(()) => {}
+            // the queue logs and counts drops itself
+            Err(TrySendError::Full(n)) => {
+                debug!("dropped webhook notification for {}", n.mentioned)
+            }
+            Err(TrySendError::Closed(n)) => {
+                warn!(
+                    "webhook worker gone, dropping notification for {}",
+                    n.mentioned
+                )
+            }
+        }
+    }
+
+    async fn deliver(client: &reqwest::Client, notification: MentionNotification) {
+        let mut backoff = Duration::from_millis(500);
+        for attempt in 1..=Self::MAX_ATTEMPTS {
+            let ret = client
+                .post(&notification.url)
+                .json(&notification)
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+            match ret {
+                Ok(_) => {
+                    info!("notified {} via webhook", notification.mentioned);
+                    return;
+                }
+                Err(e) if attempt < Self::MAX_ATTEMPTS => {
+                    warn!(
+                        "webhook for {} failed (attempt {}): {}",
+                        notification.mentioned, attempt, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    error!("giving up on webhook for {}: {}", notification.mentioned, e);
+                }
+            }
+        }
+    }
+}
+
+/// Resolves names like the system resolver, leaving out addresses that
+/// aren't public.
+struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is reachable from the internet at large, rather than the
+/// host itself or a network it's on.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // 0.0.0.0/8 reaches the host on some systems
+        || a == 0
+        // carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        // unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Names mentioned as `@name` in a chat line, without duplicates.
+pub(super) fn mentions(content: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = content
+        .split_whitespace()
+        // `(@bob)` and `@@bob` both mention bob, `bob@example.com` nobody
+        .map(|word| word.trim_start_matches(|c: char| c != '@' && c.is_ascii_punctuation()))
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|name| name.trim_start_matches('@'))
+        .map(|name| name.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_skip_punctuation_around_names() {
+        assert_eq!(
+            mentions("hi @bob, @carol! and @bob again"),
+            ["bob", "carol"]
+        );
+        assert_eq!(
+            mentions("@@dave (@erin) \"@frank\"..."),
+            ["dave", "erin", "frank"]
+        );
+        assert_eq!(mentions("@bob's turn"), ["bob's"]);
+    }
+
+    #[test]
+    fn mentions_need_a_leading_at_and_a_name() {
+        assert!(mentions("mail bob@example.com").is_empty());
+        assert!(mentions("@ @@ @!? a@").is_empty());
+        assert!(mentions("").is_empty());
+    }
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in [
+            "93.184.216.34",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "100.64.0.1",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn check_refuses_other_schemes_and_private_hosts() {
+        let notifier = WebhookNotifier::spawn(1, false);
+        let check = |url: &str| notifier.check(&Url::parse(url).unwrap());
+        assert!(check("https://example.com/hook").is_ok());
+        assert!(check("ftp://example.com/hook").is_err());
+        assert!(check("file:///etc/passwd").is_err());
+        assert!(check("http://127.0.0.1:8080/").is_err());
+        assert!(check("http://[::1]/").is_err());
+        assert!(check("http://169.254.169.254/latest/meta-data").is_err());
+
+        let permissive = WebhookNotifier::spawn(1, true);
+        assert!(permissive
+            .check(&Url::parse("http://127.0.0.1:8080/").unwrap())
+            .is_ok());
+    }
+}
@@ -0,0 +1,45 @@
+//! [tokio-console](https://github.com/tokio-rs/console) for the examples,
+//! behind the `console` feature. Tokio only records its tasks when built
+//! with `tokio_unstable`, so run an example as
+//!
+//! ```text
+//! RUSTFLAGS="--cfg tokio_unstable" cargo run --features console --example tokio2
+//! tokio-console    # in another terminal
+//! ```
+//!
+//! and it lists each task with how long it was busy and how long it went
+//! unpolled; a task hogging a current-thread runtime, such as tokio1's and
+//! tokio2's `expensive_op`, shows up as a long poll.
+
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// The console layer, serving on `127.0.0.1:6669` (or as set by the
+/// `TOKIO_CONSOLE_*` variables); `None` without the feature, which as a
+/// layer does nothing.
+#[cfg(feature = "console")]
+pub fn layer<S>() -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    Some(console_subscriber::spawn())
+}
+
+#[cfg(not(feature = "console"))]
+pub fn layer<S>() -> Option<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    None::<tracing_subscriber::layer::Identity>
+}
+
+/// Give tokio-console a few seconds to connect before a short-lived example
+/// starts the tasks worth seeing. Does nothing without the feature.
+pub fn wait_for_client() {
+    #[cfg(feature = "console")]
+    {
+        eprintln!("waiting 3s for tokio-console to connect...");
+        std::thread::sleep(std::time::Duration::from_secs(3));
+    }
+}
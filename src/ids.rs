@@ -0,0 +1,98 @@
+//! Ids and names that are all strings underneath but never interchangeable:
+//! a [`UserId`] won't go where a [`RoomName`] is expected, though either
+//! still reads as a `&str` wherever one is.
+
+use derive_more::{AsRef, Deref, Display, From, FromStr, Into};
+use serde::{Deserialize, Serialize};
+
+macro_rules! string_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(
+            Debug,
+            Default,
+            Clone,
+            PartialEq,
+            Eq,
+            Hash,
+            PartialOrd,
+            Ord,
+            Display,
+            From,
+            Into,
+            AsRef,
+            Deref,
+            FromStr,
+            Serialize,
+            Deserialize,
+            sqlx::Type,
+        )]
+        #[as_ref(forward)]
+        #[deref(forward)]
+        #[serde(transparent)]
+        #[sqlx(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(s: &str) -> Self {
+                Self(s.to_string())
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+    };
+}
+
+string_id! {
+    /// A chat user, by the name they go by.
+    UserId
+}
+
+string_id! {
+    /// The short id a shortened URL is served under, like `aZ3_x-`.
+    SlugId
+}
+
+string_id! {
+    /// A chat room.
+    RoomName
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_as_a_str() {
+        let user: UserId = "alice".parse().unwrap();
+        assert_eq!(user.len(), 5);
+        assert_eq!(user.as_ref() as &str, "alice");
+        assert_eq!(user, "alice");
+        assert_eq!(user.to_string(), "alice");
+        assert_eq!(String::from(user), "alice");
+    }
+
+    #[test]
+    fn serializes_as_a_plain_string() {
+        let slug = SlugId::from("aZ3_x-");
+        assert_eq!(serde_json::to_string(&slug).unwrap(), r#""aZ3_x-""#);
+        let room: RoomName = serde_json::from_str(r#""lobby""#).unwrap();
+        assert_eq!(room, RoomName::from("lobby"));
+    }
+}
@@ -1 +1,7 @@
-
+pub mod bridge;
+pub mod chat;
+pub mod console;
+pub mod ids;
+pub mod observed;
+pub mod supervisor;
+pub mod units;
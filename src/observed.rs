@@ -0,0 +1,276 @@
+//! Channels that make backpressure visible instead of silent. [`channel`] is
+//! a tokio `mpsc` channel whose sender samples how deep the queue gets, times
+//! sends that had to wait for room and counts values dropped on a full
+//! queue; [`Lagging`] wraps a `broadcast` receiver and counts the messages
+//! it missed by falling behind. Totals are kept in [`ChannelStats`], and
+//! waits, drops and lag are logged through `tracing` with the channel name.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tracing::{trace, warn};
+
+use crate::units::Millis;
+
+/// Running totals for one channel, shared by all clones of its sender.
+#[derive(Debug, Default)]
+pub struct ChannelStats {
+    sent: AtomicU64,
+    waited: AtomicU64,
+    waited_for: AtomicU64,
+    dropped: AtomicU64,
+    lagged: AtomicU64,
+    max_depth: AtomicUsize,
+}
+
+/// A copy of [`ChannelStats`] at one moment.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ChannelSnapshot {
+    /// Values sent, or received for a [`Lagging`] receiver.
+    pub sent: u64,
+    /// Sends that found the queue full and had to wait for room.
+    pub waited: u64,
+    /// How long those sends waited in all.
+    pub waited_for: Millis,
+    /// Values given up on because the queue was full.
+    pub dropped: u64,
+    /// Messages a broadcast receiver skipped by falling behind.
+    pub lagged: u64,
+    /// The deepest the queue was seen to be.
+    pub max_depth: usize,
+}
+
+impl ChannelStats {
+    pub fn snapshot(&self) -> ChannelSnapshot {
+        ChannelSnapshot {
+            sent: self.sent.load(Ordering::Relaxed),
+            waited: self.waited.load(Ordering::Relaxed),
+            waited_for: Millis(self.waited_for.load(Ordering::Relaxed)),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            lagged: self.lagged.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+        }
+    }
+
+    fn sample(&self, name: &str, depth: usize) {
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+        trace!(channel = name, depth, "queue depth");
+    }
+}
+
+/// A bounded `mpsc` channel named `name` for its logs, with a sender that
+/// keeps [`ChannelStats`].
+pub fn channel<T>(
+    name: impl Into<Arc<str>>,
+    capacity: usize,
+) -> (ObservedSender<T>, mpsc::Receiver<T>) {
+    let (inner, rx) = mpsc::channel(capacity);
+    let tx = ObservedSender {
+        inner,
+        name: name.into(),
+        stats: Arc::default(),
+    };
+    (tx, rx)
+}
+
+/// An `mpsc::Sender` that reports on the queue behind it.
+pub struct ObservedSender<T> {
+    inner: mpsc::Sender<T>,
+    name: Arc<str>,
+    stats: Arc<ChannelStats>,
+}
+
+impl<T> ObservedSender<T> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn stats(&self) -> &ChannelStats {
+        &self.stats
+    }
+
+    /// Values sent and not yet received.
+    pub fn depth(&self) -> usize {
+        self.inner.max_capacity() - self.inner.capacity()
+    }
+
+    /// Like `mpsc::Sender::send`; a send that finds the queue full is timed
+    /// and logged once room frees up.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let depth = self.depth();
+        self.stats.sample(&self.name, depth);
+        if depth < self.inner.max_capacity() {
+            self.inner.send(value).await?;
+        } else {
+            let start = Instant::now();
+            self.inner.send(value).await?;
+            let waited = Millis::from(start.elapsed());
+            self.stats.waited.fetch_add(1, Ordering::Relaxed);
+            self.stats.waited_for.fetch_add(waited.0, Ordering::Relaxed);
+            warn!(
+                channel = &*self.name,
+                capacity = self.inner.max_capacity(),
+                %waited,
+                "queue full, send waited for room"
+            );
+        }
+        self.stats.sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Like `mpsc::Sender::try_send`; a value refused by a full queue is
+    /// counted as dropped and logged, and handed back as usual.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.stats.sample(&self.name, self.depth());
+        match self.inner.try_send(value) {
+            Ok(()) => {
+                self.stats.sent.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(e @ TrySendError::Full(_)) => {
+                let dropped = self.stats.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(
+                    channel = &*self.name,
+                    capacity = self.inner.max_capacity(),
+                    dropped,
+                    "queue full, value dropped"
+                );
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<T> Clone for ObservedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            name: self.name.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<T> Debug for ObservedSender<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObservedSender")
+            .field("name", &self.name)
+            .field("depth", &self.depth())
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+/// A `broadcast::Receiver` that counts and logs the messages it misses.
+#[derive(Debug)]
+pub struct Lagging<T> {
+    inner: broadcast::Receiver<T>,
+    name: Arc<str>,
+    stats: ChannelStats,
+}
+
+impl<T: Clone> Lagging<T> {
+    pub fn new(name: impl Into<Arc<str>>, inner: broadcast::Receiver<T>) -> Self {
+        Self {
+            inner,
+            name: name.into(),
+            stats: ChannelStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &ChannelStats {
+        &self.stats
+    }
+
+    /// The next message, or `None` once every sender is gone. Messages this
+    /// receiver was too slow for are skipped over, counted and logged,
+    /// rather than returned as an error.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            self.stats.sample(&self.name, self.inner.len());
+            match self.inner.recv().await {
+                Ok(value) => {
+                    self.stats.sent.fetch_add(1, Ordering::Relaxed);
+                    return Some(value);
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    self.stats.lagged.fetch_add(skipped, Ordering::Relaxed);
+                    warn!(
+                        channel = &*self.name,
+                        skipped, "receiver fell behind, messages skipped"
+                    );
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn send_on_a_full_queue_is_counted_as_a_wait() {
+        let (tx, mut rx) = channel("test", 1);
+        tx.send(1).await.unwrap();
+        assert_eq!(tx.depth(), 1);
+
+        let reader = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            (rx.recv().await, rx.recv().await)
+        });
+        tx.send(2).await.unwrap();
+        assert_eq!(reader.await.unwrap(), (Some(1), Some(2)));
+
+        let stats = tx.stats().snapshot();
+        assert_eq!((stats.sent, stats.waited, stats.max_depth), (2, 1, 1));
+        assert!(stats.waited_for >= Millis(10), "{:?}", stats);
+    }
+
+    #[tokio::test]
+    async fn try_send_on_a_full_queue_is_counted_as_a_drop() {
+        let (tx, _rx) = channel("test", 2);
+        let results: Vec<_> = (0..5).map(|i| tx.try_send(i).is_ok()).collect();
+        assert_eq!(results, [true, true, false, false, false]);
+
+        let stats = tx.stats().snapshot();
+        assert_eq!(
+            stats,
+            ChannelSnapshot {
+                sent: 2,
+                dropped: 3,
+                max_depth: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn lagging_receiver_skips_and_counts() {
+        let (tx, rx) = broadcast::channel(2);
+        let mut rx = Lagging::new("test", rx);
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+
+        let mut received = Vec::new();
+        while let Some(i) = rx.recv().await {
+            received.push(i);
+        }
+        assert_eq!(received, [3, 4]);
+        let stats = rx.stats().snapshot();
+        assert_eq!((stats.sent, stats.lagged), (2, 3));
+    }
+}
@@ -0,0 +1,279 @@
+//! Keeps named background tasks running. Each child is spawned on its own,
+//! and its `JoinHandle` tells the supervisor whether it returned an error,
+//! panicked or simply stopped; any of those gets it restarted after a
+//! backoff that doubles with each restart in a row. A child that keeps
+//! failing is given up on after [`RestartPolicy::max_restarts`].
+//!
+//! Dropping the [`Supervisor`] aborts every child with it.
+
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use crate::units::Millis;
+
+/// How a child is restarted.
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Wait before the first restart in a row; doubled for each one after.
+    pub backoff: Duration,
+    /// Longest wait between restarts.
+    pub max_backoff: Duration,
+    /// Restarts in a row before giving up.
+    pub max_restarts: u32,
+    /// A child that ran at least this long before it stopped is restarted
+    /// as if for the first time.
+    pub reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            max_restarts: 5,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Wait before restart number `restart` in a row, counting from 1.
+    fn delay(&self, restart: u32) -> Duration {
+        let factor = 2u32.saturating_pow(restart.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// A child the supervisor stopped restarting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GaveUp {
+    pub name: String,
+    pub restarts: u32,
+}
+
+impl Display for GaveUp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "gave up on {} after {} restarts",
+            self.name, self.restarts
+        )
+    }
+}
+
+/// Why a child stopped.
+#[derive(Debug)]
+enum Exit {
+    Finished,
+    Failed(anyhow::Error),
+    Panicked(String),
+}
+
+impl Display for Exit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exit::Finished => write!(f, "stopped"),
+            Exit::Failed(e) => write!(f, "failed: {:#}", e),
+            Exit::Panicked(message) => write!(f, "panicked: {}", message),
+        }
+    }
+}
+
+/// Aborts the child when the task supervising it is dropped or aborted.
+struct Child(JoinHandle<anyhow::Result<()>>);
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    children: JoinSet<GaveUp>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the future `start` returns as the child `name`, calling `start`
+    /// again for each restart.
+    pub fn spawn<F, Fut>(&mut self, name: impl Into<String>, policy: RestartPolicy, start: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.children.spawn(supervise(name.into(), policy, start));
+    }
+
+    /// The next child given up on, or `None` once there are no children.
+    pub async fn join_next(&mut self) -> Option<GaveUp> {
+        while let Some(result) = self.children.join_next().await {
+            if let Ok(gave_up) = result {
+                return Some(gave_up);
+            }
+        }
+        None
+    }
+
+    /// Children still being supervised.
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+async fn supervise<F, Fut>(name: String, policy: RestartPolicy, mut start: F) -> GaveUp
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let mut restarts = 0;
+    loop {
+        let started = Instant::now();
+        let mut child = Child(tokio::spawn(start()));
+        let exit = match (&mut child.0).await {
+            Ok(Ok(())) => Exit::Finished,
+            Ok(Err(e)) => Exit::Failed(e),
+            Err(e) => Exit::Panicked(panic_message(e)),
+        };
+        if started.elapsed() >= policy.reset_after {
+            restarts = 0;
+        }
+        if restarts == policy.max_restarts {
+            error!(child = %name, "{}, not restarting after {} restarts", exit, restarts);
+            return GaveUp { name, restarts };
+        }
+        restarts += 1;
+        let delay = policy.delay(restarts);
+        warn!(
+            child = %name,
+            restart = restarts,
+            "{}, restarting in {}",
+            exit,
+            Millis::from(delay)
+        );
+        tokio::time::sleep(delay).await;
+        info!(child = %name, "restarting");
+    }
+}
+
+/// What a child panicked with. The child is only ever aborted along with
+/// the supervisor, which then isn't around to ask.
+fn panic_message(e: tokio::task::JoinError) -> String {
+    if !e.is_panic() {
+        return e.to_string();
+    }
+    let payload = e.into_panic();
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "(no message)".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn quick(max_restarts: u32) -> RestartPolicy {
+        RestartPolicy {
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            max_restarts,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RestartPolicy {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let delays: Vec<_> = (1..=6).map(|n| policy.delay(n).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn restarts_after_errors_and_panics_until_it_runs() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut runs = 0;
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("flaky", quick(5), move || {
+            runs += 1;
+            let run = runs;
+            tx.send(run).unwrap();
+            async move {
+                match run {
+                    1 => anyhow::bail!("first run fails"),
+                    2 => panic!("second run panics"),
+                    _ => std::future::pending().await,
+                }
+            }
+        });
+
+        for expected in 1..=3 {
+            assert_eq!(rx.recv().await, Some(expected));
+        }
+        let more = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
+        assert!(more.is_err(), "restarted a child that kept running");
+        assert_eq!(supervisor.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_restarts() {
+        let runs = Arc::new(AtomicU32::new(0));
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("broken", quick(2), {
+            let runs = runs.clone();
+            move || {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async { Ok(()) }
+            }
+        });
+
+        let gave_up = supervisor.join_next().await;
+        assert_eq!(
+            gave_up,
+            Some(GaveUp {
+                name: "broken".to_string(),
+                restarts: 2
+            })
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert!(supervisor.join_next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn dropping_the_supervisor_aborts_children() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<()>(1);
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("holder", quick(0), move || {
+            let tx = tx.clone();
+            async move {
+                let _tx = tx;
+                std::future::pending().await
+            }
+        });
+        tokio::task::yield_now().await;
+        drop(supervisor);
+        // the only sender lived in the child, so this ends once it's gone
+        assert_eq!(rx.recv().await, None);
+    }
+}
@@ -0,0 +1,120 @@
+//! Quantities that carry their unit in the type, so a byte count can't be
+//! added to a duration, and that print the way a person would write them.
+//! Both serialize as the bare number.
+
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use derive_more::{Add, AddAssign, From, Into, Sub, SubAssign, Sum};
+use serde::{Deserialize, Serialize};
+
+/// A byte count, shown in binary units: `512 B`, `1.4 MiB`.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    From,
+    Into,
+    Add,
+    AddAssign,
+    Sub,
+    SubAssign,
+    Sum,
+    Serialize,
+    Deserialize,
+)]
+#[serde(transparent)]
+pub struct Bytes(pub u64);
+
+/// A span of milliseconds: `250ms`, `1.5s`, `2m 5s`.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    From,
+    Into,
+    Add,
+    AddAssign,
+    Sub,
+    SubAssign,
+    Sum,
+    Serialize,
+    Deserialize,
+)]
+#[serde(transparent)]
+pub struct Millis(pub u64);
+
+impl Display for Bytes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{:.1} {}", value, UNITS[unit])
+    }
+}
+
+impl Display for Millis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            ms @ 0..=999 => write!(f, "{}ms", ms),
+            ms @ 1_000..=59_999 => write!(f, "{:.1}s", ms as f64 / 1000.0),
+            ms => write!(f, "{}m {}s", ms / 60_000, ms % 60_000 / 1000),
+        }
+    }
+}
+
+/// Whole milliseconds, saturating at `u64::MAX`.
+impl From<Duration> for Millis {
+    fn from(duration: Duration) -> Self {
+        Self(duration.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+}
+
+impl From<Millis> for Duration {
+    fn from(millis: Millis) -> Self {
+        Duration::from_millis(millis.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_display_in_binary_units() {
+        assert_eq!(Bytes(0).to_string(), "0 B");
+        assert_eq!(Bytes(1023).to_string(), "1023 B");
+        assert_eq!(Bytes(1024).to_string(), "1.0 KiB");
+        assert_eq!(Bytes(1_468_006).to_string(), "1.4 MiB");
+        assert_eq!(Bytes(u64::MAX).to_string(), "16.0 EiB");
+        assert_eq!(Bytes(100) + Bytes(28), Bytes(128));
+    }
+
+    #[test]
+    fn millis_display_by_magnitude() {
+        assert_eq!(Millis(250).to_string(), "250ms");
+        assert_eq!(Millis(1_500).to_string(), "1.5s");
+        assert_eq!(Millis(125_000).to_string(), "2m 5s");
+        assert_eq!(Millis::from(Duration::from_micros(2_999)), Millis(2));
+        assert_eq!(Duration::from(Millis(1_500)), Duration::from_millis(1_500));
+    }
+}
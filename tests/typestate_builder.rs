@@ -0,0 +1,9 @@
+//! The typestate builder in `examples/builder` must refuse to build users
+//! missing a name or date of birth at compile time.
+
+#[test]
+fn build_needs_name_and_dob() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/typestate_complete.rs");
+    cases.compile_fail("tests/ui/typestate_missing_*.rs");
+}
@@ -0,0 +1,18 @@
+#[allow(dead_code, unused_macros)]
+#[path = "../../examples/builder/aged.rs"]
+mod aged;
+#[allow(dead_code)]
+#[path = "../../examples/builder/typestate.rs"]
+mod typestate;
+
+use chrono::NaiveDate;
+
+fn main() {
+    let user = typestate::UserBuilder::new()
+        .dob(NaiveDate::from_ymd_opt(1990, 1, 1).unwrap())
+        .email("alice@example.com")
+        .name("Alice")
+        .build()
+        .unwrap();
+    println!("{:?}", user);
+}
@@ -0,0 +1,10 @@
+#[allow(dead_code, unused_macros)]
+#[path = "../../examples/builder/aged.rs"]
+mod aged;
+#[allow(dead_code)]
+#[path = "../../examples/builder/typestate.rs"]
+mod typestate;
+
+fn main() {
+    typestate::UserBuilder::default().email("alice@example.com").build();
+}
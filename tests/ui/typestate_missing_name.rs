@@ -0,0 +1,14 @@
+#[allow(dead_code, unused_macros)]
+#[path = "../../examples/builder/aged.rs"]
+mod aged;
+#[allow(dead_code)]
+#[path = "../../examples/builder/typestate.rs"]
+mod typestate;
+
+use chrono::NaiveDate;
+
+fn main() {
+    typestate::UserBuilder::new()
+        .dob(NaiveDate::from_ymd_opt(1990, 1, 1).unwrap())
+        .build();
+}